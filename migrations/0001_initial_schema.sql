@@ -0,0 +1,16 @@
+-- Tables as they were created by hand before migrations existed, hence
+-- IF NOT EXISTS: on such databases this migration only gets recorded.
+
+CREATE TABLE IF NOT EXISTS messages (
+    msg_id serial PRIMARY KEY,
+    msg_timestamp timestamp NOT NULL,
+    msg_offset integer NOT NULL,
+    msg_channel text NOT NULL,
+    msg_author text NOT NULL,
+    msg_body text NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS aliases (
+    alias_primary text NOT NULL,
+    alias_secondary text PRIMARY KEY
+);
@@ -0,0 +1,7 @@
+-- Day views, date filters and keyset pagination order by time.
+CREATE INDEX IF NOT EXISTS messages_timestamp_idx ON messages (msg_timestamp, msg_offset);
+CREATE INDEX IF NOT EXISTS messages_author_idx ON messages (msg_author);
+CREATE INDEX IF NOT EXISTS messages_channel_idx ON messages (msg_channel);
+
+-- author: looks up every nickname of a primary one.
+CREATE INDEX IF NOT EXISTS aliases_primary_idx ON aliases (alias_primary);
@@ -0,0 +1,8 @@
+-- Stored text-search vectors, so phrase filters can use an index instead of
+-- computing to_tsvector() for every row. The database computes them from
+-- msg_body, so no writer can leave them out; run `logger-viewer reindex`
+-- after changing the 'russian' configuration.
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS msg_tsv tsvector
+    GENERATED ALWAYS AS (to_tsvector('russian', msg_body)) STORED;
+
+CREATE INDEX IF NOT EXISTS messages_tsv_idx ON messages USING GIN (msg_tsv);
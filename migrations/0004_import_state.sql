@@ -0,0 +1,8 @@
+-- Where the last import of each source stopped: the day of the source's
+-- time zone and the line offset within that day's file.
+CREATE TABLE IF NOT EXISTS import_state (
+    source text PRIMARY KEY,
+    last_date date NOT NULL,
+    last_offset integer NOT NULL,
+    updated_at timestamp NOT NULL DEFAULT now()
+);
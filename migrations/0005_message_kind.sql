@@ -0,0 +1,3 @@
+-- Actions and channel events are stored next to ordinary messages.
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS msg_kind text NOT NULL DEFAULT 'message'
+    CHECK (msg_kind IN ('message', 'action', 'join', 'part', 'quit', 'nick', 'topic'));
@@ -0,0 +1,10 @@
+-- Days whose import kept failing after retries, kept until a later import
+-- of the same day succeeds.
+CREATE TABLE IF NOT EXISTS import_failures (
+    source text NOT NULL,
+    date date NOT NULL,
+    error text NOT NULL,
+    attempts integer NOT NULL,
+    failed_at timestamp NOT NULL DEFAULT now(),
+    PRIMARY KEY (source, date)
+);
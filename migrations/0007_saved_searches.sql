@@ -0,0 +1,28 @@
+-- Searches whose new matches are sent to the alert webhook.
+CREATE TABLE IF NOT EXISTS saved_searches (
+    search_id serial PRIMARY KEY,
+    search_name text NOT NULL UNIQUE,
+    search_query text NOT NULL,
+    created_at timestamp NOT NULL DEFAULT now()
+);
+
+-- Matches of saved searches, one per search and message. Messages are keyed
+-- by channel, time and offset rather than ID, since importing a day again
+-- stores its messages under new IDs, and the message itself is kept as it
+-- was matched for the same reason.
+CREATE TABLE IF NOT EXISTS alert_deliveries (
+    search_id integer NOT NULL REFERENCES saved_searches ON DELETE CASCADE,
+    msg_channel text NOT NULL,
+    msg_timestamp timestamp NOT NULL,
+    msg_offset integer NOT NULL,
+    message text NOT NULL,
+    attempts integer NOT NULL DEFAULT 0,
+    error text,
+    next_attempt_at timestamp NOT NULL DEFAULT now(),
+    delivered_at timestamp,
+    created_at timestamp NOT NULL DEFAULT now(),
+    PRIMARY KEY (search_id, msg_channel, msg_timestamp, msg_offset)
+);
+
+CREATE INDEX IF NOT EXISTS alert_deliveries_pending_idx
+    ON alert_deliveries (next_attempt_at) WHERE delivered_at IS NULL;
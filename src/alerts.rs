@@ -0,0 +1,500 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use chrono_tz::Tz;
+use once_cell::sync::Lazy;
+use reqwest::Client as WebClient;
+use serde::Serialize;
+use serde_json::json;
+use sqlx::{postgres::PgConnection, prelude::*, FromRow, Pool, Postgres};
+use tokio::sync::Mutex;
+
+use crate::config::Config;
+use crate::import::Imported;
+use crate::query::{self, Expr, MessageFilter, QueryOptions};
+use crate::stream;
+
+/// Attempts at delivering a match before it is given up on.
+const ATTEMPTS: i32 = 8;
+
+/// Wait before the first retry of a delivery, doubled after each further attempt.
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Most matches sent in a single webhook request.
+const BATCH_SIZE: i64 = 100;
+
+/// How long a compiled filter is reused. Aliases and relative dates like
+/// `date:today` are resolved when it is compiled, so it goes stale.
+const FILTER_TTL: Duration = Duration::from_secs(60);
+
+/// Deliveries run one at a time, so no match is sent twice.
+static LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// Filters of saved searches by ID, so each search isn't compiled again for
+/// every import notification.
+static FILTERS: Lazy<Mutex<HashMap<i32, Compiled>>> = Lazy::new(Mutex::default);
+
+struct Compiled {
+    query: String,
+    filter: Arc<MessageFilter>,
+    tz: Tz,
+    compiled_at: Instant,
+}
+
+#[derive(Serialize, FromRow)]
+pub struct SavedSearch {
+    pub id: i32,
+    pub name: String,
+    pub query: String,
+    pub created_at: NaiveDateTime,
+}
+
+/// A saved search with the state of its matches.
+#[derive(Serialize, FromRow)]
+pub struct SearchStatus {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub search: SavedSearch,
+    /// Matches waiting to be sent, including ones being retried.
+    pub pending: i64,
+    pub delivered: i64,
+    /// Matches given up on after all attempts failed.
+    pub failed: i64,
+}
+
+/// Saved searches by name.
+pub async fn list(db: Pool<Postgres>) -> Result<Vec<SearchStatus>> {
+    let searches = sqlx::query_as(
+        "SELECT search_id AS id, search_name AS name, search_query AS query, s.created_at, \
+            count(*) FILTER (WHERE delivered_at IS NULL AND attempts < $1) AS pending, \
+            count(delivered_at) AS delivered, \
+            count(*) FILTER (WHERE delivered_at IS NULL AND attempts >= $1) AS failed \
+        FROM saved_searches s LEFT JOIN alert_deliveries USING (search_id) \
+        GROUP BY search_id ORDER BY search_name",
+    )
+    .bind(ATTEMPTS)
+    .fetch_all(&db)
+    .await?;
+
+    Ok(searches)
+}
+
+/// Saves `query` as `name`, replacing the query of an existing search of
+/// that name. Matches of the old query stay delivered.
+pub async fn save(db: Pool<Postgres>, name: &str, query: &str) -> Result<SavedSearch> {
+    let search: SavedSearch = sqlx::query_as(
+        "INSERT INTO saved_searches (search_name, search_query) VALUES ($1, $2) \
+        ON CONFLICT (search_name) DO UPDATE SET search_query = $2 \
+        RETURNING search_id AS id, search_name AS name, search_query AS query, created_at",
+    )
+    .bind(name)
+    .bind(query)
+    .fetch_one(&db)
+    .await?;
+
+    FILTERS.lock().await.remove(&search.id);
+    Ok(search)
+}
+
+/// Deletes the search called `name` along with its pending matches.
+/// Returns whether there was one.
+pub async fn delete(db: Pool<Postgres>, name: &str) -> Result<bool> {
+    let id: Option<i32> =
+        sqlx::query_scalar("DELETE FROM saved_searches WHERE search_name = $1 RETURNING search_id")
+            .bind(name)
+            .fetch_optional(&db)
+            .await?;
+
+    if let Some(id) = id {
+        FILTERS.lock().await.remove(&id);
+    }
+    Ok(id.is_some())
+}
+
+/// Filter of a saved search, with the zone its messages are shown in.
+async fn compile(db: &mut PgConnection, config: &Config, query: &str) -> Result<(MessageFilter, Tz)> {
+    let expr = Expr::parse(query)?;
+    let tz = query::timezone(&expr, config.timezone)?;
+    let options = QueryOptions {
+        timezone: tz,
+        bots: config.bot_list.clone(),
+    };
+
+    Ok((MessageFilter::new(db, expr, options).await?, tz))
+}
+
+/// Filter of `search` from [`FILTERS`], compiled if it isn't there, has
+/// expired or was compiled from another query.
+async fn cached_filter(
+    db: &mut PgConnection,
+    config: &Config,
+    search: &SavedSearch,
+) -> Result<(Arc<MessageFilter>, Tz)> {
+    let mut filters = FILTERS.lock().await;
+    if let Some(cached) = filters.get(&search.id) {
+        if cached.query == search.query && cached.compiled_at.elapsed() < FILTER_TTL {
+            return Ok((cached.filter.clone(), cached.tz));
+        }
+    }
+
+    let (filter, tz) = compile(db, config, &search.query).await?;
+    let filter = Arc::new(filter);
+    filters.insert(
+        search.id,
+        Compiled {
+            query: search.query.clone(),
+            filter: filter.clone(),
+            tz,
+            compiled_at: Instant::now(),
+        },
+    );
+    Ok((filter, tz))
+}
+
+/// Records messages of an import notification matching saved searches, to
+/// be sent by [`deliver`]. Messages already matched by a search, like the
+/// ones of a day imported again, are skipped. Returns the number of new
+/// matches.
+pub async fn check(db: Pool<Postgres>, config: &Config, imported: &Imported) -> Result<u64> {
+    let searches: Vec<SavedSearch> = sqlx::query_as(
+        "SELECT search_id AS id, search_name AS name, search_query AS query, created_at \
+        FROM saved_searches",
+    )
+    .fetch_all(&db)
+    .await?;
+
+    if searches.is_empty() {
+        return Ok(0);
+    }
+
+    let rows = stream::imported_rows(&db, imported).await?;
+    let mut conn = db.acquire().await?;
+    let mut matched = 0;
+
+    for search in searches {
+        let (filter, tz) = match cached_filter(&mut conn, config, &search).await {
+            Ok(v) => v,
+            Err(err) => {
+                eprintln!("alerts: skipping saved search '{}': {:#}", search.name, err);
+                continue;
+            }
+        };
+
+        for row in rows.iter().filter(|row| filter.matches(row)) {
+            let mut message = serde_json::to_value(row.message.clone().in_timezone(tz))?;
+            message["channel"] = json!(row.channel);
+
+            let query = sqlx::query(
+                "INSERT INTO alert_deliveries \
+                    (search_id, msg_channel, msg_timestamp, msg_offset, message) \
+                VALUES ($1, $2, $3, $4, $5) ON CONFLICT DO NOTHING",
+            )
+            .bind(search.id)
+            .bind(&row.channel)
+            .bind(row.message.time)
+            .bind(row.message.offset)
+            .bind(message.to_string());
+
+            matched += conn.execute(query).await?.rows_affected();
+        }
+    }
+
+    Ok(matched)
+}
+
+#[derive(FromRow)]
+struct Pending {
+    search_id: i32,
+    name: String,
+    query: String,
+    msg_channel: String,
+    msg_timestamp: NaiveDateTime,
+    msg_offset: i32,
+    message: String,
+}
+
+/// Sends `pending`, matches of a single search, to `url`, then marks them
+/// delivered or schedules their next attempt. Returns the outcome of the
+/// request; fails only when the outcome could not be recorded.
+async fn send_batch(
+    db: &Pool<Postgres>,
+    web: &WebClient,
+    url: &str,
+    pending: &[Pending],
+) -> Result<reqwest::Result<()>> {
+    let search = &pending[0];
+    let messages = pending
+        .iter()
+        .map(|p| serde_json::from_str(&p.message))
+        .collect::<Result<Vec<serde_json::Value>, _>>()?;
+
+    let body = json!({
+        "search": {
+            "id": search.search_id,
+            "name": search.name,
+            "query": search.query,
+        },
+        "messages": messages,
+    });
+
+    let request = web
+        .post(url)
+        .header("Content-Type", "application/json")
+        .body(body.to_string());
+
+    let result = match request.send().await {
+        Ok(response) => response.error_for_status().map(|_| ()),
+        Err(err) => Err(err),
+    };
+
+    let channels: Vec<&str> = pending.iter().map(|p| p.msg_channel.as_str()).collect();
+    let timestamps: Vec<NaiveDateTime> = pending.iter().map(|p| p.msg_timestamp).collect();
+    let offsets: Vec<i32> = pending.iter().map(|p| p.msg_offset).collect();
+
+    let query = match &result {
+        Ok(()) => sqlx::query(
+            "UPDATE alert_deliveries SET attempts = attempts + 1, error = NULL, delivered_at = now() \
+            WHERE search_id = $1 AND (msg_channel, msg_timestamp, msg_offset) IN \
+                (SELECT * FROM unnest($2::text[], $3::timestamp[], $4::integer[]))",
+        ),
+        Err(_) => sqlx::query(
+            "UPDATE alert_deliveries SET attempts = attempts + 1, error = $5, \
+                next_attempt_at = now() + make_interval(secs => $6 * power(2, attempts)) \
+            WHERE search_id = $1 AND (msg_channel, msg_timestamp, msg_offset) IN \
+                (SELECT * FROM unnest($2::text[], $3::timestamp[], $4::integer[]))",
+        ),
+    }
+    .bind(search.search_id)
+    .bind(channels)
+    .bind(timestamps)
+    .bind(offsets);
+
+    let query = match &result {
+        Ok(()) => query,
+        Err(err) => query.bind(err.to_string()).bind(RETRY_DELAY.as_secs_f64()),
+    };
+
+    db.execute(query).await?;
+    Ok(result)
+}
+
+/// Sends matches that are due to `url`, as JSON batches of one search each.
+/// Failed batches are retried with backoff on later runs.
+async fn deliver_pending(db: &Pool<Postgres>, url: &str) -> Result<()> {
+    let web = WebClient::builder().timeout(TIMEOUT).build()?;
+
+    loop {
+        let pending: Vec<Pending> = sqlx::query_as(
+            "SELECT search_id, search_name AS name, search_query AS query, \
+                msg_channel, msg_timestamp, msg_offset, message \
+            FROM alert_deliveries JOIN saved_searches USING (search_id) \
+            WHERE delivered_at IS NULL AND attempts < $1 AND next_attempt_at <= now() \
+            ORDER BY search_id, msg_timestamp, msg_offset LIMIT $2",
+        )
+        .bind(ATTEMPTS)
+        .bind(BATCH_SIZE)
+        .fetch_all(db)
+        .await?;
+
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        // batches that fail are not due again until their next attempt;
+        // when that can't be recorded, give up until the next run rather
+        // than sending the same batch over and over
+        for batch in pending.chunk_by(|a, b| a.search_id == b.search_id) {
+            if let Err(err) = send_batch(db, &web, url, batch).await? {
+                eprintln!("alerts: delivery of '{}' failed: {:#}", batch[0].name, err);
+            }
+        }
+    }
+}
+
+/// Sends pending matches to the webhook at `url`, see [`deliver_pending`].
+pub async fn deliver(db: Pool<Postgres>, url: String) {
+    let _guard = LOCK.lock().await;
+
+    if let Err(err) = deliver_pending(&db, &url).await {
+        eprintln!("alerts: {:#}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU16, Ordering};
+
+    use chrono::NaiveDate;
+    use warp::http::StatusCode;
+    use warp::Filter;
+
+    use super::*;
+
+    type Requests = Arc<std::sync::Mutex<Vec<serde_json::Value>>>;
+
+    /// A local stand-in webhook recording request bodies and answering with
+    /// the current `status`.
+    fn serve(status: Arc<AtomicU16>) -> (String, Requests) {
+        let requests: Requests = Arc::default();
+        let route = warp::body::json().map({
+            let requests = requests.clone();
+            move |body: serde_json::Value| {
+                requests.lock().unwrap().push(body);
+                let status = StatusCode::from_u16(status.load(Ordering::SeqCst)).unwrap();
+                warp::reply::with_status("", status)
+            }
+        });
+
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (format!("http://{}/", addr), requests)
+    }
+
+    /// Stores `messages` of `author` on 2023-01-01, returning the notification
+    /// an import of them sends.
+    async fn store(db: &Pool<Postgres>, messages: &[(&str, &str)]) -> Imported {
+        let start = NaiveDate::from_ymd_opt(2023, 1, 1).unwrap().and_hms_opt(10, 0, 0).unwrap();
+        let mut ids = vec![];
+        for (offset, (author, body)) in messages.iter().enumerate() {
+            let id: i32 = sqlx::query_scalar(
+                "INSERT INTO messages (msg_timestamp, msg_offset, msg_channel, msg_author, msg_body) \
+                VALUES ($1, $2, '#chan', $3, $4) RETURNING msg_id",
+            )
+            .bind(start + chrono::Duration::seconds(offset as i64))
+            .bind(offset as i32)
+            .bind(author)
+            .bind(body)
+            .fetch_one(db)
+            .await
+            .unwrap();
+            ids.push(id);
+        }
+
+        Imported {
+            source: String::from("test"),
+            channel: String::from("#chan"),
+            date: start.date(),
+            count: ids.len() as u64,
+            first_id: ids[0],
+            last_id: *ids.last().unwrap(),
+            replaced: None,
+        }
+    }
+
+    /// Attempts, whether an error is recorded, seconds until the next
+    /// attempt (rounded) and whether it was delivered, of every match.
+    async fn deliveries(db: &Pool<Postgres>) -> Vec<(i32, bool, i64, bool)> {
+        sqlx::query_as(
+            "SELECT attempts, error IS NOT NULL, \
+                round(extract(epoch FROM next_attempt_at - now()))::int8, delivered_at IS NOT NULL \
+            FROM alert_deliveries ORDER BY search_id, msg_offset",
+        )
+        .fetch_all(db)
+        .await
+        .unwrap()
+    }
+
+    /// Search names and message counts of the requests received.
+    fn batches(requests: &Requests) -> Vec<(String, usize)> {
+        requests
+            .lock()
+            .unwrap()
+            .drain(..)
+            .map(|body| {
+                let name = body["search"]["name"].as_str().unwrap().to_owned();
+                (name, body["messages"].as_array().unwrap().len())
+            })
+            .collect()
+    }
+
+    #[sqlx::test]
+    async fn delivers_in_batches_with_retries(db: Pool<Postgres>) {
+        let status = Arc::new(AtomicU16::new(500));
+        let (url, requests) = serve(status.clone());
+        let config = Config {
+            timezone: chrono_tz::UTC,
+            ..Config::default()
+        };
+
+        save(db.clone(), "cats", "cat").await.unwrap();
+        save(db.clone(), "bob", "author:bob").await.unwrap();
+
+        let mut messages = vec![("amy", "a cat"); 150];
+        messages.push(("bob", "hello"));
+        let imported = store(&db, &messages).await;
+
+        assert_eq!(check(db.clone(), &config, &imported).await.unwrap(), 151);
+        // matches already recorded are skipped
+        assert_eq!(check(db.clone(), &config, &imported).await.unwrap(), 0);
+
+        // at most BATCH_SIZE matches of a single search per request
+        deliver(db.clone(), url.clone()).await;
+        let expected = vec![
+            (String::from("cats"), 100),
+            (String::from("cats"), 50),
+            (String::from("bob"), 1),
+        ];
+        assert_eq!(batches(&requests), expected);
+
+        // failed batches are not due again until the retry delay has passed
+        let delay = RETRY_DELAY.as_secs() as i64;
+        assert!(deliveries(&db).await.iter().all(|d| *d == (1, true, delay, false)));
+        deliver(db.clone(), url.clone()).await;
+        assert!(batches(&requests).is_empty());
+
+        // and the delay doubles with each attempt
+        sqlx::query("UPDATE alert_deliveries SET next_attempt_at = now()").execute(&db).await.unwrap();
+        deliver(db.clone(), url.clone()).await;
+        assert_eq!(batches(&requests), expected);
+        assert!(deliveries(&db).await.iter().all(|d| *d == (2, true, delay * 2, false)));
+
+        status.store(200, Ordering::SeqCst);
+        sqlx::query("UPDATE alert_deliveries SET next_attempt_at = now()").execute(&db).await.unwrap();
+        deliver(db.clone(), url.clone()).await;
+        assert_eq!(batches(&requests), expected);
+        assert!(deliveries(&db).await.iter().all(|d| (d.0, d.1, d.3) == (3, false, true)));
+
+        // delivered matches are neither recorded nor sent again
+        assert_eq!(check(db.clone(), &config, &imported).await.unwrap(), 0);
+        deliver(db.clone(), url.clone()).await;
+        assert!(batches(&requests).is_empty());
+
+        let searches = list(db.clone()).await.unwrap();
+        let counts: Vec<_> = searches.iter().map(|s| (s.search.name.as_str(), s.delivered, s.pending)).collect();
+        assert_eq!(counts, vec![("bob", 1, 0), ("cats", 150, 0)]);
+
+        // a changed query takes effect at once
+        save(db.clone(), "bob", "author:amy").await.unwrap();
+        assert_eq!(check(db.clone(), &config, &imported).await.unwrap(), 150);
+    }
+
+    #[sqlx::test]
+    async fn stops_when_outcome_is_not_recorded(db: Pool<Postgres>) {
+        let (url, requests) = serve(Arc::new(AtomicU16::new(200)));
+        let config = Config::default();
+
+        save(db.clone(), "cats", "cat").await.unwrap();
+        let imported = store(&db, &[("amy", "a cat"); 3]).await;
+        assert_eq!(check(db.clone(), &config, &imported).await.unwrap(), 3);
+
+        db.execute(
+            "CREATE FUNCTION refuse() RETURNS trigger LANGUAGE plpgsql AS \
+                $$ BEGIN RAISE EXCEPTION 'read-only'; END $$; \
+            CREATE TRIGGER refuse BEFORE UPDATE ON alert_deliveries \
+                FOR EACH ROW EXECUTE FUNCTION refuse();",
+        )
+        .await
+        .unwrap();
+
+        // sent once, then left for the next run rather than sent again
+        let run = tokio::time::timeout(Duration::from_secs(5), deliver(db.clone(), url));
+        run.await.unwrap();
+        assert_eq!(batches(&requests), vec![(String::from("cats"), 3)]);
+        assert!(deliveries(&db).await.iter().all(|d| d.0 == 0));
+    }
+}
@@ -0,0 +1,146 @@
+use std::sync::Arc;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use warp::{http::StatusCode, Filter, Rejection};
+
+use crate::config::Config;
+use crate::error::ErrorResponse;
+
+/// Hash of an admin token as stored in `admin_tokens`.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// A new random admin token.
+pub fn new_token() -> String {
+    URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>())
+}
+
+/// Whether `authorization`, an `Authorization: Bearer` header value, holds
+/// one of the configured admin tokens.
+fn is_admin(config: &Config, authorization: Option<&str>) -> bool {
+    let token = match authorization.and_then(|value| value.strip_prefix("Bearer ")) {
+        Some(token) => token.trim(),
+        None => return false,
+    };
+    let hash = hash_token(token);
+
+    config
+        .admin_tokens
+        .iter()
+        .any(|allowed| bool::from(allowed.to_lowercase().as_bytes().ct_eq(hash.as_bytes())))
+}
+
+/// Rejects `authorization` unless it holds a valid admin bearer token. With
+/// no `admin_tokens` configured, admin endpoints are disabled altogether.
+pub fn check_admin(config: &Config, authorization: Option<&str>) -> Result<(), Rejection> {
+    if config.admin_tokens.is_empty() {
+        return Err(warp::reject::custom(ErrorResponse {
+            message: String::from("Admin endpoints are disabled"),
+            status_code: StatusCode::FORBIDDEN,
+        }));
+    }
+
+    if !is_admin(config, authorization) {
+        return Err(warp::reject::custom(ErrorResponse {
+            message: String::from("Missing or invalid admin token"),
+            status_code: StatusCode::UNAUTHORIZED,
+        }));
+    }
+
+    Ok(())
+}
+
+/// Rejects requests without a valid admin bearer token, see [`check_admin`].
+pub fn admin(config: Arc<Config>) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(move |authorization: Option<String>| {
+            let config = config.clone();
+            async move { check_admin(&config, authorization.as_deref()) }
+        })
+        .untuple_one()
+}
+
+#[cfg(test)]
+mod tests {
+    use warp::http::Method;
+
+    use super::*;
+    use crate::error::handle_rejection_json;
+
+    const TOKEN: &str = "test-token";
+
+    fn config(tokens: Vec<String>) -> Arc<Config> {
+        Arc::new(Config {
+            admin_tokens: tokens,
+            ..Config::default()
+        })
+    }
+
+    /// Status of a request to an endpoint guarded like `/logs/import`.
+    async fn status(config: Arc<Config>, method: Method, authorization: Option<&str>) -> StatusCode {
+        let route = warp::path!("logs" / "import")
+            .and(warp::post())
+            .and(admin(config))
+            .map(|| "started")
+            .recover(handle_rejection_json);
+
+        let mut request = warp::test::request().method(method.as_str()).path("/logs/import");
+        if let Some(value) = authorization {
+            request = request.header("authorization", value);
+        }
+        request.reply(&route).await.status()
+    }
+
+    #[tokio::test]
+    async fn admin_endpoints() {
+        let config = config(vec![hash_token(TOKEN)]);
+        let bearer = format!("Bearer {}", TOKEN);
+
+        assert_eq!(status(config.clone(), Method::POST, Some(&bearer)).await, StatusCode::OK);
+        assert_eq!(status(config.clone(), Method::POST, None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            status(config.clone(), Method::POST, Some(&format!("Basic {}", TOKEN))).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(status(config.clone(), Method::POST, Some(TOKEN)).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            status(config.clone(), Method::POST, Some("Bearer wrong-token")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(status(config.clone(), Method::POST, Some("Bearer ")).await, StatusCode::UNAUTHORIZED);
+
+        // imports change data, so they are never started by a GET
+        assert_eq!(
+            status(config.clone(), Method::GET, Some(&bearer)).await,
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+        assert_eq!(status(config, Method::GET, None).await, StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    #[tokio::test]
+    async fn no_tokens_deny_everyone() {
+        let config = config(vec![]);
+        let bearer = format!("Bearer {}", TOKEN);
+
+        assert_eq!(status(config.clone(), Method::POST, Some(&bearer)).await, StatusCode::FORBIDDEN);
+        assert_eq!(status(config, Method::POST, None).await, StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn tokens() {
+        let config = config(vec![hash_token(TOKEN).to_uppercase()]);
+
+        // hashes are compared case-insensitively, tokens exactly
+        assert!(is_admin(&config, Some(&format!("Bearer {}", TOKEN))));
+        assert!(is_admin(&config, Some(&format!("Bearer  {} ", TOKEN))));
+        assert!(!is_admin(&config, Some(&format!("Bearer {}", TOKEN.to_uppercase()))));
+        assert!(!is_admin(&config, Some(&format!("Bearer {}", hash_token(TOKEN)))));
+        assert!(!is_admin(&config, None));
+
+        assert_ne!(new_token(), new_token());
+        assert_eq!(new_token().len(), 43);
+    }
+}
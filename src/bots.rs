@@ -0,0 +1,170 @@
+use regex::{Regex, RegexBuilder};
+
+/// Nicknames of bots, matched case-insensitively. Entries may contain `*`
+/// (any run of characters) and `?` (any single character).
+#[derive(Clone, Debug, Default)]
+pub struct BotList {
+    patterns: Vec<String>,
+    regex: Option<Regex>,
+}
+
+impl BotList {
+    pub fn new(entries: &[String]) -> BotList {
+        let alternatives: Vec<String> = entries
+            .iter()
+            .map(|entry| {
+                entry
+                    .split('*')
+                    .map(|part| {
+                        part.split('?')
+                            .map(regex::escape)
+                            .collect::<Vec<_>>()
+                            .join(".")
+                    })
+                    .collect::<Vec<_>>()
+                    .join(".*")
+            })
+            .collect();
+
+        let regex = (!alternatives.is_empty()).then(|| {
+            RegexBuilder::new(&format!("^(?:{})$", alternatives.join("|")))
+                .case_insensitive(true)
+                .build()
+                .unwrap()
+        });
+
+        BotList {
+            patterns: entries.iter().map(|e| to_like_pattern(e)).collect(),
+            regex,
+        }
+    }
+
+    pub fn is_bot(&self, nick: &str) -> bool {
+        self.regex.as_ref().is_some_and(|r| r.is_match(nick))
+    }
+
+    /// Patterns for `msg_author ILIKE ANY(...)`.
+    pub fn like_patterns(&self) -> Vec<String> {
+        self.patterns.clone()
+    }
+}
+
+fn to_like_pattern(entry: &str) -> String {
+    let mut pattern = String::with_capacity(entry.len());
+
+    for c in entry.chars() {
+        match c {
+            '*' => pattern.push('%'),
+            '?' => pattern.push('_'),
+            '%' | '_' | '\\' => {
+                pattern.push('\\');
+                pattern.push(c);
+            }
+            c => pattern.push(c),
+        }
+    }
+
+    pattern
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::{Pool, Postgres};
+
+    use super::*;
+    use crate::query::{self, Expr, Page, QueryOptions};
+
+    fn list(entries: &[&str]) -> BotList {
+        BotList::new(&entries.iter().map(|e| e.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn matches_nicks() {
+        let bots = list(&["GitBot", "relay?", "*serv", "a.b", "x_y%"]);
+
+        assert!(bots.is_bot("GitBot"));
+        assert!(bots.is_bot("gitbot"));
+        assert!(bots.is_bot("GITBOT"));
+        assert!(!bots.is_bot("GitBot2"));
+        assert!(!bots.is_bot("MyGitBot"));
+
+        assert!(bots.is_bot("relay1"));
+        assert!(!bots.is_bot("relay"));
+        assert!(!bots.is_bot("relay12"));
+        assert!(bots.is_bot("NickServ"));
+        assert!(bots.is_bot("serv"));
+
+        // everything but the wildcards is literal
+        assert!(bots.is_bot("a.b"));
+        assert!(!bots.is_bot("axb"));
+        assert!(bots.is_bot("x_y%"));
+        assert!(!bots.is_bot("xzy%"));
+
+        assert_eq!(
+            bots.like_patterns(),
+            vec!["GitBot", "relay_", "%serv", "a.b", "x\\_y\\%"]
+        );
+    }
+
+    #[test]
+    fn empty_list_matches_nobody() {
+        let bots = list(&[]);
+        assert!(!bots.is_bot("GitBot"));
+        assert!(!bots.is_bot(""));
+        assert!(bots.like_patterns().is_empty());
+    }
+
+    #[sqlx::test]
+    async fn bots_function(db: Pool<Postgres>) {
+        for author in ["amy", "GitBot", "gitbot", "bob", "x_y"] {
+            sqlx::query(
+                "INSERT INTO messages (msg_timestamp, msg_offset, msg_channel, msg_author, msg_body) \
+                VALUES ('2023-01-01 10:00:00', 0, '#chan', $1, 'hello')",
+            )
+            .bind(author)
+            .execute(&db)
+            .await
+            .unwrap();
+        }
+
+        let options = |entries: &[&str]| QueryOptions {
+            timezone: chrono_tz::UTC,
+            bots: list(entries),
+        };
+        let authors = |query: &'static str, entries: &'static [&'static str]| {
+            let db = db.clone();
+            async move {
+                let page = Page {
+                    limit: 10,
+                    cursor: None,
+                };
+                let expr = Expr::parse(query).unwrap();
+                let result = query::search(db, expr, options(entries), page).await.unwrap();
+                let mut authors: Vec<String> = result.messages.into_iter().map(|m| m.author).collect();
+                authors.sort();
+                authors
+            }
+        };
+
+        assert_eq!(authors("hello bots:exclude", &["gitbot", "x?y"]).await, vec!["amy", "bob"]);
+        assert_eq!(authors("hello bots:only", &["gitbot", "x?y"]).await, vec!["GitBot", "gitbot", "x_y"]);
+        assert_eq!(authors("hello bots:include", &["gitbot"]).await.len(), 5);
+        assert_eq!(authors("hello bots:exclude", &[]).await.len(), 5);
+        assert!(authors("hello bots:only", &[]).await.is_empty());
+        // `_` of a nick is no wildcard
+        assert_eq!(authors("hello bots:only", &["x_y", "a_y"]).await, vec!["x_y"]);
+
+        // statistics leave bots out unless asked otherwise
+        let mut conn = db.acquire().await.unwrap();
+        let hello = || Expr::parse("hello").unwrap();
+        let count = query::count(&mut conn, hello(), options(&["*bot"])).await.unwrap();
+        assert_eq!((count.total_messages, count.total_users_raw), (3, 3));
+        let count = query::count(&mut conn, hello(), options(&[])).await.unwrap();
+        assert_eq!(count.total_messages, 5);
+
+        let top = query::top(&mut conn, hello(), options(&["*bot"])).await.unwrap();
+        let mut authors: Vec<String> = top.top.into_iter().map(|a| a.author).collect();
+        authors.sort();
+        assert_eq!(authors, vec!["amy", "bob", "x_y"]);
+    }
+}
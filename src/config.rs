@@ -1,12 +1,116 @@
+use std::io::ErrorKind;
 use std::net::{IpAddr, Ipv4Addr};
+use std::path::Path;
 
+use anyhow::{Context, Result};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
 
+use crate::bots::BotList;
+
+/// A place logs are downloaded from, one file per day.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Source {
+    pub name: String,
+    /// Download URL, with `{date}` standing for the day as `YYYY-MM-DD`.
+    pub url: String,
+    /// Channel the imported messages are stored under.
+    pub channel: String,
+    /// Zone the log files' days and times are in.
+    pub timezone: Tz,
+    /// Line format of the log files: `fomalhaut`, `irssi`, `weechat`,
+    /// `znc`, `hexchat` or `mirc`.
+    pub format: String,
+    /// Minutes between automatic incremental imports; unset disables them.
+    #[serde(default)]
+    pub import_interval: Option<u32>,
+}
+
+impl Default for Source {
+    fn default() -> Self {
+        Self {
+            name: String::from("cc.ru"),
+            url: String::from("https://logs.fomalhaut.me/download/{date}.log"),
+            channel: String::from("#cc.ru"),
+            timezone: chrono_tz::EET,
+            format: String::from("fomalhaut"),
+            import_interval: None,
+        }
+    }
+}
+
+/// Live connection to an IRC server, storing messages of its channels as
+/// they arrive.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Irc {
+    pub server: String,
+    pub port: u16,
+    pub tls: bool,
+    pub nick: String,
+    pub username: String,
+    pub realname: String,
+    /// Server password, sent with `PASS`.
+    pub password: Option<String>,
+    /// Channels to join. Messages are stored under these names, which
+    /// should not also be the channel of an import source: importing a day
+    /// replaces the messages stored for it.
+    pub channels: Vec<String>,
+    /// Zone of the days message offsets are counted in.
+    pub timezone: Tz,
+    /// Seconds to wait before reconnecting, doubled after each failed
+    /// attempt up to five minutes.
+    pub reconnect_delay: u64,
+}
+
+impl Default for Irc {
+    fn default() -> Self {
+        Self {
+            server: String::from("irc.esper.net"),
+            port: 6697,
+            tls: true,
+            nick: String::from("sprout"),
+            username: String::from("sprout"),
+            realname: String::from("Sprout IRC logger"),
+            password: None,
+            channels: vec![],
+            timezone: chrono_tz::EET,
+            reconnect_delay: 10,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub postgres_url: String,
     pub bind_address: IpAddr,
     pub port: u16,
+    /// Zone used for day boundaries, date filters and displayed times,
+    /// unless overridden by the `tz=` parameter or the `tz:` function.
+    pub timezone: Tz,
+    /// Bot nicknames; `*` and `?` wildcards are allowed.
+    pub bots: Vec<String>,
+    /// Search results per page when `limit=` is not given.
+    pub search_limit: usize,
+    /// Upper bound for `limit=`.
+    pub max_search_limit: usize,
+    /// Apply pending database migrations at startup.
+    pub migrate: bool,
+    /// Import sources; the first one is used when `/logs/import` is not
+    /// given a `source=` parameter.
+    pub sources: Vec<Source>,
+    /// SHA-256 hashes, hex encoded, of the bearer tokens accepted by admin
+    /// endpoints such as `/logs/import`; `logger-viewer new-token` makes one.
+    /// Admin endpoints are disabled while this is empty.
+    pub admin_tokens: Vec<String>,
+    /// Live IRC logging; disabled while unset.
+    pub irc: Option<Irc>,
+    /// URL new matches of saved searches are POSTed to as JSON; alerts are
+    /// disabled while unset.
+    pub alert_webhook: Option<String>,
+    #[serde(skip)]
+    pub bot_list: BotList,
 }
 
 impl Default for Config {
@@ -15,24 +119,109 @@ impl Default for Config {
             postgres_url: String::new(),
             port: 3030,
             bind_address: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            timezone: chrono_tz::EET,
+            bots: vec![],
+            search_limit: 100,
+            max_search_limit: 1000,
+            migrate: true,
+            sources: vec![Source::default()],
+            admin_tokens: vec![],
+            irc: None,
+            alert_webhook: None,
+            bot_list: BotList::default(),
         }
     }
 }
 
+/// Where the configuration is read from and saved to.
+const PATH: &str = "config.toml";
+
 impl Config {
-    pub fn new_from_file() -> Config {
-        match std::fs::read_to_string("config.toml") {
-            Ok(string) => {
-                return toml::from_str(&string).unwrap_or_default();
-            }
-            Err(_) => {
-                return Config::default();
+    /// Reads `config.toml`, or the defaults if there is none. A file that
+    /// fails to parse is an error, so it is never saved over.
+    pub fn new_from_file() -> Result<Config> {
+        Config::load(Path::new(PATH))
+    }
+
+    fn load(path: &Path) -> Result<Config> {
+        let mut config: Config = match std::fs::read_to_string(path) {
+            Ok(string) => toml::from_str(&string)
+                .with_context(|| format!("failed to parse {}", path.display()))?,
+            Err(err) if err.kind() == ErrorKind::NotFound => Config::default(),
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to read {}", path.display()))
             }
+        };
+
+        config.bot_list = BotList::new(&config.bots);
+        Ok(config)
+    }
+
+    /// Source called `name`, or the first one when no name is given.
+    pub fn source(&self, name: Option<&str>) -> Option<&Source> {
+        match name {
+            Some(name) => self.sources.iter().find(|source| source.name == name),
+            None => self.sources.first(),
         }
     }
 
-    pub fn save(&self) -> anyhow::Result<()> {
-        std::fs::write("config.toml", toml::to_string(self).unwrap())?;
+    pub fn save(&self) -> Result<()> {
+        self.save_to(Path::new(PATH))
+    }
+
+    fn save_to(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, toml::to_string(self)?)?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// A fresh directory for the files of `test`.
+    fn directory(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sprout-config-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn missing_file_gives_defaults() {
+        let path = directory("missing").join("config.toml");
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.port, 3030);
+        assert_eq!(config.sources.len(), 1);
+    }
+
+    #[test]
+    fn round_trips() {
+        let path = directory("round-trip").join("config.toml");
+        let config = Config {
+            admin_tokens: vec![String::from("abc")],
+            alert_webhook: Some(String::from("http://127.0.0.1/hook")),
+            bots: vec![String::from("*bot")],
+            ..Config::default()
+        };
+        config.save_to(&path).unwrap();
+
+        let loaded = Config::load(&path).unwrap();
+        assert_eq!(loaded.admin_tokens, config.admin_tokens);
+        assert_eq!(loaded.alert_webhook, config.alert_webhook);
+        assert!(loaded.bot_list.is_bot("GitBot"));
+    }
+
+    #[test]
+    fn malformed_file_is_an_error_and_kept() {
+        let path = directory("malformed").join("config.toml");
+        let contents = "admin_tokens = [\"abc\"]\nport = \"not a number\"\n[[sources]\n";
+        std::fs::write(&path, contents).unwrap();
+
+        let err = Config::load(&path).err().unwrap();
+        assert!(format!("{:#}", err).contains("failed to parse"), "{:#}", err);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), contents);
+    }
+}
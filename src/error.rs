@@ -2,9 +2,22 @@ use std::convert::Infallible;
 use serde::Serialize;
 use warp::{http::StatusCode, Rejection, Reply, reject};
 
+use crate::query::ParseError;
+
 #[derive(Serialize)]
 pub struct ErrorMessage {
     pub message: String,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub details: Option<ParseErrorDetails>,
+}
+
+#[derive(Serialize)]
+pub struct ParseErrorDetails {
+    pub query: String,
+    pub offset: usize,
+    pub column: usize,
+    pub expected: Vec<String>,
+    pub found: Option<String>,
 }
 
 #[derive(Debug)]
@@ -22,13 +35,22 @@ impl reject::Reject for AnyhowError {}
 #[derive(Debug)]
 pub struct DatabaseError;
 
+#[derive(Debug)]
+pub struct QueryParseError {
+    pub query: String,
+    pub error: ParseError,
+}
+
 impl reject::Reject for DatabaseError {}
 impl reject::Reject for ErrorResponse {}
+impl reject::Reject for QueryParseError {}
 
 
 pub async fn handle_rejection_json(err: Rejection) -> Result<impl Reply, Infallible> {
     let code;
     let message;
+    let mut details = None;
+    let error_message;
 
     if err.is_not_found() {
         message = "NOT_FOUND";
@@ -36,12 +58,27 @@ pub async fn handle_rejection_json(err: Rejection) -> Result<impl Reply, Infalli
     } else if let Some(DatabaseError) = err.find() {
         code = StatusCode::INTERNAL_SERVER_ERROR;
         message = "Database error";
-    } else if let Some(_) = err.find::<warp::reject::MethodNotAllowed>() {
-        message = "METHOD_NOT_ALLOWED";
-        code = StatusCode::METHOD_NOT_ALLOWED;
     } else if let Some(error) = err.find::<ErrorResponse>() {
         message = &error.message;
         code = error.status_code;
+    } else if let Some(AnyhowError(error)) = err.find() {
+        code = StatusCode::INTERNAL_SERVER_ERROR;
+        error_message = format!("{:#}", error);
+        message = &error_message;
+    } else if let Some(QueryParseError { query, error }) = err.find() {
+        code = StatusCode::BAD_REQUEST;
+        error_message = error.to_string();
+        message = &error_message;
+        details = Some(ParseErrorDetails {
+            query: query.clone(),
+            offset: error.offset,
+            column: error.column,
+            expected: error.expected(),
+            found: error.found(),
+        });
+    } else if err.find::<warp::reject::MethodNotAllowed>().is_some() {
+        message = "METHOD_NOT_ALLOWED";
+        code = StatusCode::METHOD_NOT_ALLOWED;
     } else {
         eprintln!("unhandled rejection: {:?}", err);
         message = "UNHANDLED_REJECTION";
@@ -50,6 +87,7 @@ pub async fn handle_rejection_json(err: Rejection) -> Result<impl Reply, Infalli
 
     let json = warp::reply::json(&ErrorMessage {
         message: message.into(),
+        details,
     });
 
     Ok(warp::reply::with_status(json, code))
@@ -0,0 +1,484 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use chrono::{NaiveDate, NaiveDateTime};
+use chrono_tz::Tz;
+use futures::{stream, Stream, StreamExt, TryStreamExt};
+use handlebars::html_escape;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres};
+
+use crate::formats::LogLine;
+use crate::models::MessageKind;
+use crate::query::set_timezone;
+use crate::timezone::{day_bounds, local_to_utc, utc_to_local};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// A tar archive of `<channel>/<date>.log` files in the `fomalhaut`
+    /// format, which `import-files` reads back.
+    Log,
+    Ndjson,
+    Csv,
+    /// A single HTML page with its styles inlined.
+    Html,
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<ExportFormat> {
+        match s {
+            "log" => Ok(ExportFormat::Log),
+            "ndjson" => Ok(ExportFormat::Ndjson),
+            "csv" => Ok(ExportFormat::Csv),
+            "html" => Ok(ExportFormat::Html),
+            _ => Err(anyhow!(
+                "Unknown export format: {}; one of 'log', 'ndjson', 'csv' or 'html' expected",
+                s
+            )),
+        }
+    }
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Log => "application/x-tar",
+            ExportFormat::Ndjson => "application/x-ndjson; charset=utf-8",
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Html => "text/html; charset=utf-8",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Log => "tar",
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Html => "html",
+        }
+    }
+}
+
+/// What to export: days `from` through `to` of `channel`, or of every
+/// channel, with days and times in `timezone`.
+pub struct Export {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub channel: Option<String>,
+    pub timezone: Tz,
+    pub format: ExportFormat,
+}
+
+/// A message as exported, with its time in the export's zone.
+#[derive(Serialize)]
+struct Row {
+    id: i32,
+    channel: String,
+    time: NaiveDateTime,
+    offset: i32,
+    kind: MessageKind,
+    author: String,
+    body: String,
+}
+
+/// Days from `from` through `to` with messages to export, so long ranges
+/// cost nothing for the days without any.
+async fn days_with_messages(db: &Pool<Postgres>, export: &Export) -> Result<Vec<NaiveDate>> {
+    let (start, _) = day_bounds(export.timezone, export.from);
+    let (_, end) = day_bounds(export.timezone, export.to);
+
+    let mut tx = db.begin().await?;
+    set_timezone(&mut tx, export.timezone).await?;
+
+    let days = sqlx::query_scalar!(
+        "SELECT DISTINCT (msg_timestamp AT TIME ZONE 'UTC')::date AS \"day!\" FROM messages WHERE msg_timestamp >= $1 AND msg_timestamp < $2 AND ($3::text IS NULL OR msg_channel = $3) ORDER BY 1",
+        start,
+        end,
+        export.channel
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    Ok(days)
+}
+
+/// Messages of a day, ordered by channel and then as they were logged.
+async fn fetch_day(db: &Pool<Postgres>, export: &Export, date: NaiveDate) -> Result<Vec<Row>> {
+    let (start, end) = day_bounds(export.timezone, date);
+
+    let mut rows = sqlx::query_as!(
+        Row,
+        "SELECT msg_id AS id, msg_channel AS channel, msg_timestamp AS time, msg_offset AS offset, msg_kind AS \"kind: MessageKind\", msg_author AS author, msg_body AS body FROM messages WHERE msg_timestamp >= $1 AND msg_timestamp < $2 AND ($3::text IS NULL OR msg_channel = $3) ORDER BY msg_channel, msg_timestamp, msg_offset",
+        start,
+        end,
+        export.channel
+    )
+    .fetch_all(db)
+    .await?;
+
+    for row in &mut rows {
+        row.time = utc_to_local(export.timezone, row.time);
+    }
+
+    Ok(rows)
+}
+
+/// Rows of a day grouped by channel.
+fn by_channel(rows: &[Row]) -> impl Iterator<Item = &[Row]> {
+    rows.chunk_by(|a, b| a.channel == b.channel)
+}
+
+/// Longest channel directory name in a tar archive, so that with the
+/// `/<date>.log` after it the path fits the 100 bytes of a tar header.
+const MAX_DIRECTORY_LEN: usize = 85;
+
+/// Directory of `channel` in a tar archive: a single path component that
+/// can't climb out of where the archive is extracted. Names too long for a
+/// tar header are cut and told apart by a hash of the whole name.
+fn directory_name(channel: &str) -> String {
+    let mut name: String = channel
+        .chars()
+        .map(|c| if c == '/' || c == '\\' || c.is_control() { '_' } else { c })
+        .collect();
+
+    if name.starts_with('.') || name.is_empty() {
+        name.insert(0, '_');
+    }
+
+    if name.len() > MAX_DIRECTORY_LEN {
+        let hash = hex::encode(&Sha256::digest(channel.as_bytes())[..4]);
+        let mut cut = MAX_DIRECTORY_LEN - hash.len() - 1;
+        while !name.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        name = format!("{}-{}", &name[..cut], hash);
+    }
+
+    name
+}
+
+/// Tar entry of a file, padded to the 512-byte block size.
+fn tar_entry(path: &str, data: &[u8], mtime: NaiveDateTime) -> Result<Vec<u8>> {
+    let mut header = tar::Header::new_gnu();
+    header.set_path(path)?;
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime.and_utc().timestamp().max(0) as u64);
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_cksum();
+
+    let mut entry = header.as_bytes().to_vec();
+    entry.extend_from_slice(data);
+    entry.resize(entry.len().next_multiple_of(512), 0);
+    Ok(entry)
+}
+
+const HTML_HEADER: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body { font-family: monospace; background: #fafafa; color: #222; margin: 2em; }
+h2 { margin-top: 2em; border-bottom: 1px solid #ccc; }
+h3 { color: #555; }
+.message { white-space: pre-wrap; word-wrap: break-word; }
+.time, .time:visited { color: #888; text-decoration: none; }
+.author { font-weight: bold; }
+.event { color: #777; }
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+"#;
+
+fn html_day(date: NaiveDate, rows: &[Row]) -> String {
+    let mut html = format!("<section id=\"{0}\">\n<h2><a href=\"#{0}\">{0}</a></h2>\n", date);
+
+    for channel in by_channel(rows) {
+        html.push_str(&format!("<h3>{}</h3>\n", html_escape(&channel[0].channel)));
+
+        for row in channel {
+            let time = format!(
+                "<a class=\"time\" id=\"m{0}\" href=\"#m{0}\">{1}</a>",
+                row.id,
+                row.time.format("%H:%M:%S")
+            );
+            let author = html_escape(&row.author);
+            let body = html_escape(&row.body);
+
+            let line = match row.kind.marker() {
+                None => format!(
+                    "<div class=\"message\">{} &lt;<span class=\"author\">{}</span>&gt; {}</div>\n",
+                    time, author, body
+                ),
+                Some(marker) => format!(
+                    "<div class=\"message event\">{} {} <span class=\"author\">{}</span> {} {}</div>\n",
+                    time,
+                    html_escape(marker),
+                    author,
+                    row.kind.verb(),
+                    body
+                ),
+            };
+            html.push_str(&line);
+        }
+    }
+
+    html.push_str("</section>\n");
+    html
+}
+
+/// The start of the output, before any day.
+fn header(export: &Export) -> Result<Vec<u8>> {
+    let header = match export.format {
+        ExportFormat::Log | ExportFormat::Ndjson => vec![],
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(vec![]);
+            writer.write_record(["id", "channel", "time", "offset", "kind", "author", "body"])?;
+            writer.into_inner()?
+        }
+        ExportFormat::Html => {
+            let title = match &export.channel {
+                Some(channel) => format!("{} {} – {}", channel, export.from, export.to),
+                None => format!("Logs {} – {}", export.from, export.to),
+            };
+            HTML_HEADER.replace("{title}", &html_escape(&title)).into_bytes()
+        }
+    };
+
+    Ok(header)
+}
+
+/// Output for the messages of a day, nothing for a day without any.
+fn day(export: &Export, date: NaiveDate, rows: &[Row]) -> Result<Vec<u8>> {
+    if rows.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut output = vec![];
+
+    match export.format {
+        ExportFormat::Log => {
+            for channel in by_channel(rows) {
+                let mut data = String::new();
+                for row in channel {
+                    let line = LogLine {
+                        time: row.time.time(),
+                        kind: row.kind,
+                        author: &row.author,
+                        body: &row.body,
+                    };
+                    data.push_str(&line.write());
+                    data.push('\n');
+                }
+
+                let path = format!("{}/{}.log", directory_name(&channel[0].channel), date);
+                let mtime = local_to_utc(export.timezone, channel[channel.len() - 1].time);
+                output.extend(tar_entry(&path, data.as_bytes(), mtime)?);
+            }
+        }
+        ExportFormat::Ndjson => {
+            for row in rows {
+                serde_json::to_writer(&mut output, row)?;
+                output.push(b'\n');
+            }
+        }
+        ExportFormat::Csv => {
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(output);
+            for row in rows {
+                writer.serialize(row)?;
+            }
+            output = writer.into_inner()?;
+        }
+        ExportFormat::Html => output = html_day(date, rows).into_bytes(),
+    }
+
+    Ok(output)
+}
+
+/// The end of the output, after every day.
+fn footer(export: &Export) -> Vec<u8> {
+    match export.format {
+        // two empty blocks end a tar archive
+        ExportFormat::Log => vec![0; 1024],
+        ExportFormat::Ndjson | ExportFormat::Csv => vec![],
+        ExportFormat::Html => b"</body>\n</html>\n".to_vec(),
+    }
+}
+
+/// The export as chunks of output, one day at a time, so exports of any
+/// length are never held in memory whole. Only days with messages are
+/// fetched.
+pub fn export(db: Pool<Postgres>, export: Export) -> impl Stream<Item = Result<Vec<u8>>> {
+    let export = std::sync::Arc::new(export);
+
+    let header = stream::once(std::future::ready(header(&export)));
+    let footer = stream::once(std::future::ready(Ok(footer(&export))));
+
+    let days = stream::once({
+        let db = db.clone();
+        let export = export.clone();
+        async move { days_with_messages(&db, &export).await }
+    })
+    .map_ok(|days| stream::iter(days).map(Ok))
+    .try_flatten()
+    .and_then(move |date| {
+        let db = db.clone();
+        let export = export.clone();
+        async move {
+            let rows = fetch_day(&db, &export, date).await?;
+            day(&export, date, &rows)
+        }
+    });
+
+    header
+        .chain(days)
+        .chain(footer)
+        .filter(|chunk| std::future::ready(!matches!(chunk, Ok(chunk) if chunk.is_empty())))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveTime;
+
+    use super::*;
+    use crate::formats;
+
+    #[test]
+    fn log_lines_round_trip() {
+        let fomalhaut = formats::find("fomalhaut").unwrap();
+        let lines = [
+            (MessageKind::Message, "amy", "hello <world> [12:00:00] * ***"),
+            (MessageKind::Message, "amy", " leading and trailing spaces "),
+            (MessageKind::Message, "amy", ""),
+            (MessageKind::Message, "[amy]", "ünïcödé 😀"),
+            (MessageKind::Action, "amy", "waves"),
+            (MessageKind::Action, "amy", ""),
+            (MessageKind::Join, "bob", ""),
+            (MessageKind::Part, "bob", ""),
+            (MessageKind::Part, "bob", "see you (later)"),
+            (MessageKind::Quit, "bob", "Ping timeout: 240 seconds"),
+            (MessageKind::Quit, "bob", ""),
+            (MessageKind::Nick, "bob", "bobby"),
+            (MessageKind::Topic, "amy", "it's 'quoted'"),
+            (MessageKind::Topic, "amy", ""),
+        ];
+
+        for (kind, author, body) in lines {
+            let line = LogLine {
+                time: NaiveTime::from_hms_opt(23, 59, 1).unwrap(),
+                kind,
+                author,
+                body,
+            };
+            let written = line.write();
+            let parsed = fomalhaut.parse_line(&written).unwrap_or_else(|| panic!("{}", written));
+            assert_eq!(
+                (parsed.time, parsed.kind, parsed.author, parsed.body),
+                (line.time, kind, author, body),
+                "{}",
+                written
+            );
+        }
+    }
+
+    #[test]
+    fn directory_names() {
+        assert_eq!(directory_name("#chan"), "#chan");
+        assert_eq!(directory_name("#чат"), "#чат");
+        assert_eq!(directory_name("../../etc"), "_.._.._etc");
+        assert_eq!(directory_name(".."), "_..");
+        assert_eq!(directory_name("/abs"), "_abs");
+        assert_eq!(directory_name("a\\b\nc"), "a_b_c");
+        assert_eq!(directory_name(""), "_");
+
+        let long = "#".to_owned() + &"ж".repeat(100);
+        let name = directory_name(&long);
+        assert!(name.len() <= MAX_DIRECTORY_LEN, "{}", name);
+        assert!(name.starts_with("#жж"));
+        assert_ne!(name, directory_name(&(long.clone() + "x")));
+        tar_entry(&format!("{}/2023-01-01.log", name), b"", NaiveDateTime::MIN).unwrap();
+    }
+
+    async fn collect(db: &Pool<Postgres>, format: ExportFormat) -> Vec<u8> {
+        let export = Export {
+            from: NaiveDate::from_ymd_opt(1, 1, 1).unwrap(),
+            to: NaiveDate::from_ymd_opt(9999, 12, 31).unwrap(),
+            channel: None,
+            timezone: chrono_tz::Europe::Berlin,
+            format,
+        };
+        let chunks: Vec<Vec<u8>> = super::export(db.clone(), export).try_collect().await.unwrap();
+        chunks.concat()
+    }
+
+    #[sqlx::test]
+    async fn exports_days_with_messages(db: Pool<Postgres>) {
+        let messages = [
+            ("2001-02-03 10:00:00", "../#up", "amy", "first"),
+            ("2001-02-03 23:30:00", "../#up", "bob", "next day in Berlin"),
+            ("2023-06-30 12:00:00", "#chan", "amy", "much later"),
+        ];
+        for (offset, (time, channel, author, body)) in messages.into_iter().enumerate() {
+            sqlx::query(
+                "INSERT INTO messages (msg_timestamp, msg_offset, msg_channel, msg_author, msg_body) \
+                VALUES ($1::timestamp, $2, $3, $4, $5)",
+            )
+            .bind(time)
+            .bind(offset as i32)
+            .bind(channel)
+            .bind(author)
+            .bind(body)
+            .execute(&db)
+            .await
+            .unwrap();
+        }
+
+        let ndjson = String::from_utf8(collect(&db, ExportFormat::Ndjson).await).unwrap();
+        let times: Vec<String> = ndjson
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["time"].to_string())
+            .collect();
+        assert_eq!(
+            times,
+            vec!["\"2001-02-03T11:00:00\"", "\"2001-02-04T00:30:00\"", "\"2023-06-30T14:00:00\""]
+        );
+
+        let fomalhaut = formats::find("fomalhaut").unwrap();
+        let tar = collect(&db, ExportFormat::Log).await;
+        let mut archive = tar::Archive::new(tar.as_slice());
+        let mut files = vec![];
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().to_string_lossy().into_owned();
+            let mut data = String::new();
+            std::io::Read::read_to_string(&mut entry, &mut data).unwrap();
+            let lines: Vec<String> = data
+                .lines()
+                .map(|line| {
+                    let line = fomalhaut.parse_line(line).unwrap();
+                    format!("{} {} {}", line.time, line.author, line.body)
+                })
+                .collect();
+            files.push((path, lines));
+        }
+
+        let file = |path: &str, lines: &[&str]| {
+            (path.to_owned(), lines.iter().map(|l| l.to_string()).collect::<Vec<_>>())
+        };
+        assert_eq!(
+            files,
+            vec![
+                file("_.._#up/2001-02-03.log", &["11:00:00 amy first"]),
+                file("_.._#up/2001-02-04.log", &["00:30:00 bob next day in Berlin"]),
+                file("#chan/2023-06-30.log", &["14:00:00 amy much later"]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,23 @@
+use super::*;
+use once_cell::sync::Lazy;
+
+/// Plain-text downloads of logs.fomalhaut.me: `[12:34:56] <nick> text`,
+/// with actions and events written the way ZNC does.
+struct Fomalhaut;
+
+static PATTERNS: Lazy<Vec<(MessageKind, Regex)>> = Lazy::new(|| {
+    let mut list = patterns(&[(
+        MessageKind::Message,
+        r"^\[(?P<time>\d{2}:\d{2}:\d{2})\] <(?P<nick>[^>]+)> (?P<text>.*)",
+    )]);
+    list.extend(super::znc::events());
+    list
+});
+
+impl LogFormat for Fomalhaut {
+    fn parse_line<'a>(&self, line: &'a str) -> Option<LogLine<'a>> {
+        parse_any(&PATTERNS, line)
+    }
+}
+
+log_format!("fomalhaut", Fomalhaut);
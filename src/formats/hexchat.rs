@@ -0,0 +1,112 @@
+use super::*;
+use once_cell::sync::Lazy;
+
+/// HexChat's logs: `Jan 31 12:34:56 <nick>\ttext`, with `*`, `-->`, `<--`
+/// or `---` in place of `<nick>` for actions and events.
+struct HexChat;
+
+static PATTERNS: Lazy<Vec<(MessageKind, Regex)>> = Lazy::new(|| {
+    patterns(&[
+        (
+            MessageKind::Message,
+            r"^[A-Z][a-z]{2} [ \d]\d (?P<time>\d{2}:\d{2}:\d{2}) <(?P<nick>[^>]+)>\t(?P<text>.*)$",
+        ),
+        (
+            MessageKind::Action,
+            r"^[A-Z][a-z]{2} [ \d]\d (?P<time>\d{2}:\d{2}:\d{2}) \*\t(?P<nick>\S+) (?P<text>.*)$",
+        ),
+        (
+            MessageKind::Join,
+            r"^[A-Z][a-z]{2} [ \d]\d (?P<time>\d{2}:\d{2}:\d{2}) -->\t(?P<nick>\S+) .*has joined ",
+        ),
+        (
+            MessageKind::Part,
+            r"^[A-Z][a-z]{2} [ \d]\d (?P<time>\d{2}:\d{2}:\d{2}) <--\t(?P<nick>\S+) .*has left \S+(?: \((?P<text>.*)\))?$",
+        ),
+        (
+            MessageKind::Quit,
+            r"^[A-Z][a-z]{2} [ \d]\d (?P<time>\d{2}:\d{2}:\d{2}) <--\t(?P<nick>\S+) has quit(?: \((?P<text>.*)\))?$",
+        ),
+        (
+            MessageKind::Nick,
+            r"^[A-Z][a-z]{2} [ \d]\d (?P<time>\d{2}:\d{2}:\d{2}) ---\t(?P<nick>\S+) is now known as (?P<text>\S+)$",
+        ),
+        (
+            MessageKind::Topic,
+            r"^[A-Z][a-z]{2} [ \d]\d (?P<time>\d{2}:\d{2}:\d{2}) ---\t(?P<nick>\S+) has changed the topic to: (?P<text>.*)$",
+        ),
+    ])
+});
+
+impl LogFormat for HexChat {
+    fn parse_line<'a>(&self, line: &'a str) -> Option<LogLine<'a>> {
+        parse_any(&PATTERNS, line)
+    }
+}
+
+log_format!("hexchat", HexChat);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Option<(String, MessageKind, &str, &str)> {
+        parsed(&HexChat, line)
+    }
+
+    #[test]
+    fn lines() {
+        let cases = [
+            ("Jan 31 12:34:56 <@alice>\thello there", "12:34:56", MessageKind::Message, "alice", "hello there"),
+            ("Feb  5 12:35:00 *\talice waves hello", "12:35:00", MessageKind::Action, "alice", "waves hello"),
+            (
+                "Jan 31 12:36:00 -->\tcarol (~carol@example.org) has joined #chan",
+                "12:36:00",
+                MessageKind::Join,
+                "carol",
+                "",
+            ),
+            (
+                "Jan 31 12:37:00 <--\tcarol (~carol@example.org) has left #chan (bye now)",
+                "12:37:00",
+                MessageKind::Part,
+                "carol",
+                "bye now",
+            ),
+            (
+                "Jan 31 12:38:00 <--\tdave has quit (Ping timeout: 250 seconds)",
+                "12:38:00",
+                MessageKind::Quit,
+                "dave",
+                "Ping timeout: 250 seconds",
+            ),
+            ("Jan 31 12:39:00 ---\tdave is now known as dave_", "12:39:00", MessageKind::Nick, "dave", "dave_"),
+            (
+                "Jan 31 12:40:00 ---\talice has changed the topic to: new topic",
+                "12:40:00",
+                MessageKind::Topic,
+                "alice",
+                "new topic",
+            ),
+        ];
+
+        for (line, time, kind, author, body) in cases {
+            assert_eq!(parse(line), Some((time.to_owned(), kind, author, body)), "{}", line);
+        }
+    }
+
+    #[test]
+    fn malformed_lines() {
+        for line in [
+            "",
+            "**** BEGIN LOGGING AT Tue Jan 31 12:00:00 2023",
+            "Jan 31 12:34:56 <alice> hello",
+            "Jan 31 12:34 <alice>\thello",
+            "jan 31 12:34:56 <alice>\thello",
+            "Jan 31 12:61:00 <alice>\thello",
+            "Jan 31 12:41:00 ---\tbob gives channel operator status to alice",
+        ] {
+            assert!(parse(line).is_none(), "{}", line);
+        }
+    }
+}
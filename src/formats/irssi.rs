@@ -0,0 +1,111 @@
+use super::*;
+use once_cell::sync::Lazy;
+
+/// irssi's default log theme: `12:34 <@nick> text`, optionally with seconds,
+/// ` * nick acts` for actions and `-!- ` in front of events.
+struct Irssi;
+
+static PATTERNS: Lazy<Vec<(MessageKind, Regex)>> = Lazy::new(|| {
+    patterns(&[
+        (
+            MessageKind::Message,
+            r"^(?P<time>\d{2}:\d{2}(?::\d{2})?) <(?P<nick>[^>]+)> (?P<text>.*)$",
+        ),
+        (MessageKind::Action, r"^(?P<time>\d{2}:\d{2}(?::\d{2})?)  \* (?P<nick>\S+) (?P<text>.*)$"),
+        (
+            MessageKind::Join,
+            r"^(?P<time>\d{2}:\d{2}(?::\d{2})?) -!- (?P<nick>\S+) \[[^\]]*\] has joined ",
+        ),
+        (
+            MessageKind::Part,
+            r"^(?P<time>\d{2}:\d{2}(?::\d{2})?) -!- (?P<nick>\S+) \[[^\]]*\] has left \S+ \[(?P<text>.*)\]$",
+        ),
+        (
+            MessageKind::Quit,
+            r"^(?P<time>\d{2}:\d{2}(?::\d{2})?) -!- (?P<nick>\S+) \[[^\]]*\] has quit \[(?P<text>.*)\]$",
+        ),
+        (
+            MessageKind::Nick,
+            r"^(?P<time>\d{2}:\d{2}(?::\d{2})?) -!- (?P<nick>\S+) is now known as (?P<text>\S+)$",
+        ),
+        (
+            MessageKind::Topic,
+            r"^(?P<time>\d{2}:\d{2}(?::\d{2})?) -!- (?P<nick>\S+) changed the topic of \S+ to: (?P<text>.*)$",
+        ),
+    ])
+});
+
+impl LogFormat for Irssi {
+    fn parse_line<'a>(&self, line: &'a str) -> Option<LogLine<'a>> {
+        parse_any(&PATTERNS, line)
+    }
+}
+
+log_format!("irssi", Irssi);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Option<(String, MessageKind, &str, &str)> {
+        parsed(&Irssi, line)
+    }
+
+    #[test]
+    fn lines() {
+        let cases = [
+            ("12:34 <@alice> hello there", "12:34:00", MessageKind::Message, "alice", "hello there"),
+            ("12:34:56 < bob> <3", "12:34:56", MessageKind::Message, "bob", "<3"),
+            ("12:35  * alice waves hello", "12:35:00", MessageKind::Action, "alice", "waves hello"),
+            (
+                "12:36 -!- carol [~carol@example.org] has joined #chan",
+                "12:36:00",
+                MessageKind::Join,
+                "carol",
+                "",
+            ),
+            (
+                "12:37 -!- carol [~carol@example.org] has left #chan [bye now]",
+                "12:37:00",
+                MessageKind::Part,
+                "carol",
+                "bye now",
+            ),
+            (
+                "12:38:05 -!- dave [~dave@host] has quit [Ping timeout: 250 seconds]",
+                "12:38:05",
+                MessageKind::Quit,
+                "dave",
+                "Ping timeout: 250 seconds",
+            ),
+            ("12:39 -!- dave is now known as dave_", "12:39:00", MessageKind::Nick, "dave", "dave_"),
+            (
+                "12:40 -!- alice changed the topic of #chan to: new topic",
+                "12:40:00",
+                MessageKind::Topic,
+                "alice",
+                "new topic",
+            ),
+        ];
+
+        for (line, time, kind, author, body) in cases {
+            assert_eq!(parse(line), Some((time.to_owned(), kind, author, body)), "{}", line);
+        }
+    }
+
+    #[test]
+    fn malformed_lines() {
+        for line in [
+            "",
+            "--- Log opened Tue Jan 31 00:00:00 2023",
+            "12:34",
+            "12:34 <alice>",
+            "12:34 <@> hi",
+            "99:99 <alice> hi",
+            "12:41 -!- mode/#chan [+o alice] by bob",
+            "[12:34:56] <alice> hi",
+        ] {
+            assert!(parse(line).is_none(), "{}", line);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use super::*;
+use once_cell::sync::Lazy;
+
+/// mIRC's logs with the default `[HH:nn]` timestamps, or with seconds:
+/// `[12:34] <@nick> text`. Actions and events both start with `* `, so
+/// events are tried first.
+struct Mirc;
+
+static PATTERNS: Lazy<Vec<(MessageKind, Regex)>> = Lazy::new(|| {
+    patterns(&[
+        (
+            MessageKind::Message,
+            r"^\[(?P<time>\d{2}:\d{2}(?::\d{2})?)\] <(?P<nick>[^>]+)> (?P<text>.*)$",
+        ),
+        (
+            MessageKind::Join,
+            r"^\[(?P<time>\d{2}:\d{2}(?::\d{2})?)\] \* (?P<nick>\S+) \([^)]*\) has joined ",
+        ),
+        (
+            MessageKind::Join,
+            r"^\[(?P<time>\d{2}:\d{2}(?::\d{2})?)\] \* Joins: (?P<nick>\S+) \(",
+        ),
+        (
+            MessageKind::Part,
+            r"^\[(?P<time>\d{2}:\d{2}(?::\d{2})?)\] \* (?P<nick>\S+) \([^)]*\) has left \S+(?: \((?P<text>.*)\))?$",
+        ),
+        (
+            MessageKind::Quit,
+            r"^\[(?P<time>\d{2}:\d{2}(?::\d{2})?)\] \* (?P<nick>\S+) \([^)]*\) Quit(?: \((?P<text>.*)\))?$",
+        ),
+        (
+            MessageKind::Nick,
+            r"^\[(?P<time>\d{2}:\d{2}(?::\d{2})?)\] \* (?P<nick>\S+) is now known as (?P<text>\S+)$",
+        ),
+        (
+            MessageKind::Topic,
+            r"^\[(?P<time>\d{2}:\d{2}(?::\d{2})?)\] \* (?P<nick>\S+) changes topic to '(?P<text>.*)'$",
+        ),
+        (
+            MessageKind::Action,
+            r"^\[(?P<time>\d{2}:\d{2}(?::\d{2})?)\] \* (?P<nick>\S+) (?P<text>.*)$",
+        ),
+    ])
+});
+
+impl LogFormat for Mirc {
+    fn parse_line<'a>(&self, line: &'a str) -> Option<LogLine<'a>> {
+        parse_any(&PATTERNS, line)
+    }
+}
+
+log_format!("mirc", Mirc);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Option<(String, MessageKind, &str, &str)> {
+        parsed(&Mirc, line)
+    }
+
+    #[test]
+    fn lines() {
+        let cases = [
+            ("[12:34] <@alice> hello there", "12:34:00", MessageKind::Message, "alice", "hello there"),
+            ("[12:34:56] <bob> hi", "12:34:56", MessageKind::Message, "bob", "hi"),
+            ("[12:35] * alice waves hello", "12:35:00", MessageKind::Action, "alice", "waves hello"),
+            (
+                "[12:36] * carol (~carol@example.org) has joined #chan",
+                "12:36:00",
+                MessageKind::Join,
+                "carol",
+                "",
+            ),
+            ("[12:36:30] * Joins: carol (~carol@example.org)", "12:36:30", MessageKind::Join, "carol", ""),
+            (
+                "[12:37] * carol (~carol@example.org) has left #chan (bye now)",
+                "12:37:00",
+                MessageKind::Part,
+                "carol",
+                "bye now",
+            ),
+            (
+                "[12:38] * dave (~dave@host) Quit (Ping timeout: 250 seconds)",
+                "12:38:00",
+                MessageKind::Quit,
+                "dave",
+                "Ping timeout: 250 seconds",
+            ),
+            ("[12:39] * dave is now known as dave_", "12:39:00", MessageKind::Nick, "dave", "dave_"),
+            (
+                "[12:40] * alice changes topic to 'new topic'",
+                "12:40:00",
+                MessageKind::Topic,
+                "alice",
+                "new topic",
+            ),
+        ];
+
+        for (line, time, kind, author, body) in cases {
+            assert_eq!(parse(line), Some((time.to_owned(), kind, author, body)), "{}", line);
+        }
+    }
+
+    #[test]
+    fn malformed_lines() {
+        for line in [
+            "",
+            "Session Start: Tue Jan 31 00:00:00 2023",
+            "[12:34]",
+            "[1234] <alice> hello",
+            "12:34 <alice> hello",
+            "[12:34] <@> hello",
+            "[12:34] *",
+        ] {
+            assert!(parse(line).is_none(), "{}", line);
+        }
+    }
+}
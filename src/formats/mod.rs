@@ -0,0 +1,125 @@
+macro_rules! log_format {
+    ($name:literal, $format:expr) => {
+        inventory::submit!(RegisteredFormat {
+            name: $name,
+            format: &$format
+        });
+    };
+}
+
+mod fomalhaut;
+mod hexchat;
+mod irssi;
+mod mirc;
+mod weechat;
+mod znc;
+
+use anyhow::{anyhow, Result};
+use chrono::NaiveTime;
+use regex::Regex;
+
+use crate::models::MessageKind;
+
+/// A message or event line of a log file. The day comes from the file itself.
+#[derive(Clone, Debug)]
+pub struct LogLine<'a> {
+    pub time: NaiveTime,
+    pub kind: MessageKind,
+    pub author: &'a str,
+    pub body: &'a str,
+}
+
+/// Line syntax of the logs some IRC client or bouncer writes.
+pub trait LogFormat: Sync {
+    /// Parses a message or event line; anything else gives `None`.
+    fn parse_line<'a>(&self, line: &'a str) -> Option<LogLine<'a>>;
+}
+
+struct RegisteredFormat {
+    name: &'static str,
+    format: &'static dyn LogFormat,
+}
+
+inventory::collect!(RegisteredFormat);
+
+pub fn find(name: &str) -> Result<&'static dyn LogFormat> {
+    for registered in inventory::iter::<RegisteredFormat> {
+        if name.eq_ignore_ascii_case(registered.name) {
+            return Ok(registered.format);
+        }
+    }
+
+    Err(anyhow!("unknown log format '{}'", name))
+}
+
+/// Compiles the line patterns of a format, tried in order.
+fn patterns(patterns: &[(MessageKind, &str)]) -> Vec<(MessageKind, Regex)> {
+    patterns
+        .iter()
+        .map(|(kind, pattern)| (*kind, Regex::new(pattern).unwrap()))
+        .collect()
+}
+
+/// Parses a line with the first matching pattern.
+fn parse_any<'a>(patterns: &[(MessageKind, Regex)], line: &'a str) -> Option<LogLine<'a>> {
+    patterns
+        .iter()
+        .find_map(|(kind, re)| parse_with(re, *kind, line))
+}
+
+/// Parses a line with a regex having `time` and `nick` groups, and an
+/// optional `text` group. Times may omit seconds; channel mode prefixes
+/// of nicks are dropped.
+fn parse_with<'a>(re: &Regex, kind: MessageKind, line: &'a str) -> Option<LogLine<'a>> {
+    let c = re.captures(line)?;
+    let time = c.name("time")?.as_str();
+    let time = NaiveTime::parse_from_str(time, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(time, "%H:%M"))
+        .ok()?;
+
+    let author = c
+        .name("nick")?
+        .as_str()
+        .trim_start_matches(['~', '&', '@', '%', '+', ' ']);
+
+    if author.is_empty() {
+        return None;
+    }
+
+    Some(LogLine {
+        time,
+        kind,
+        author,
+        body: c.name("text").map_or("", |text| text.as_str()),
+    })
+}
+
+impl LogLine<'_> {
+    /// Writes the line the way the `fomalhaut` format reads it, with events
+    /// as ZNC writes them. Hosts, which are not stored, are left empty.
+    pub fn write(&self) -> String {
+        let time = self.time.format("%H:%M:%S");
+        let (author, body) = (self.author, self.body);
+        let reason = |body: &str| match body {
+            "" => String::new(),
+            body => format!(" ({})", body),
+        };
+
+        match self.kind {
+            MessageKind::Message => format!("[{}] <{}> {}", time, author, body),
+            MessageKind::Action => format!("[{}] * {} {}", time, author, body),
+            MessageKind::Join => format!("[{}] *** Joins: {} ()", time, author),
+            MessageKind::Part => format!("[{}] *** Parts: {} (){}", time, author, reason(body)),
+            MessageKind::Quit => format!("[{}] *** Quits: {} (){}", time, author, reason(body)),
+            MessageKind::Nick => format!("[{}] *** {} is now known as {}", time, author, body),
+            MessageKind::Topic => format!("[{}] *** {} changes topic to '{}'", time, author, body),
+        }
+    }
+}
+
+/// Parses `line` into its time, kind, author and body, for format tests.
+#[cfg(test)]
+fn parsed<'a>(format: &dyn LogFormat, line: &'a str) -> Option<(String, MessageKind, &'a str, &'a str)> {
+    let line = format.parse_line(line)?;
+    Some((line.time.format("%H:%M:%S").to_string(), line.kind, line.author, line.body))
+}
@@ -0,0 +1,123 @@
+use super::*;
+use once_cell::sync::Lazy;
+
+/// WeeChat's logger plugin: `2023-01-31 12:34:56\t@nick\ttext`. Actions and
+/// events put ` *`, `-->`, `<--` or `--` in the nick column.
+struct WeeChat;
+
+static PATTERNS: Lazy<Vec<(MessageKind, Regex)>> = Lazy::new(|| {
+    patterns(&[
+        (
+            MessageKind::Message,
+            r"^\d{4}-\d{2}-\d{2} (?P<time>\d{2}:\d{2}:\d{2})\t(?P<nick>[^\t\-<*= ][^\t]*)\t(?P<text>.*)$",
+        ),
+        (
+            MessageKind::Action,
+            r"^\d{4}-\d{2}-\d{2} (?P<time>\d{2}:\d{2}:\d{2})\t \*\t(?P<nick>\S+) (?P<text>.*)$",
+        ),
+        (
+            MessageKind::Join,
+            r"^\d{4}-\d{2}-\d{2} (?P<time>\d{2}:\d{2}:\d{2})\t-->\t(?P<nick>\S+) .*has joined ",
+        ),
+        (
+            MessageKind::Part,
+            r"^\d{4}-\d{2}-\d{2} (?P<time>\d{2}:\d{2}:\d{2})\t<--\t(?P<nick>\S+) .*has left \S+(?: \((?P<text>.*)\))?$",
+        ),
+        (
+            MessageKind::Quit,
+            r"^\d{4}-\d{2}-\d{2} (?P<time>\d{2}:\d{2}:\d{2})\t<--\t(?P<nick>\S+) .*has quit(?: \((?P<text>.*)\))?$",
+        ),
+        (
+            MessageKind::Nick,
+            r"^\d{4}-\d{2}-\d{2} (?P<time>\d{2}:\d{2}:\d{2})\t--\t(?P<nick>\S+) is now known as (?P<text>\S+)$",
+        ),
+        (
+            MessageKind::Topic,
+            r#"^\d{4}-\d{2}-\d{2} (?P<time>\d{2}:\d{2}:\d{2})\t--\t(?P<nick>\S+) has changed topic for \S+ (?:from ".*" )?to "(?P<text>.*)"$"#,
+        ),
+    ])
+});
+
+impl LogFormat for WeeChat {
+    fn parse_line<'a>(&self, line: &'a str) -> Option<LogLine<'a>> {
+        parse_any(&PATTERNS, line)
+    }
+}
+
+log_format!("weechat", WeeChat);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Option<(String, MessageKind, &str, &str)> {
+        parsed(&WeeChat, line)
+    }
+
+    #[test]
+    fn lines() {
+        let cases = [
+            (
+                "2023-01-31 12:34:56\t@alice\thello\tthere",
+                "12:34:56",
+                MessageKind::Message,
+                "alice",
+                "hello\tthere",
+            ),
+            ("2023-01-31 12:35:00\t *\talice waves hello", "12:35:00", MessageKind::Action, "alice", "waves hello"),
+            (
+                "2023-01-31 12:36:00\t-->\tcarol (~carol@example.org) has joined #chan",
+                "12:36:00",
+                MessageKind::Join,
+                "carol",
+                "",
+            ),
+            (
+                "2023-01-31 12:37:00\t<--\tcarol (~carol@example.org) has left #chan (bye now)",
+                "12:37:00",
+                MessageKind::Part,
+                "carol",
+                "bye now",
+            ),
+            (
+                "2023-01-31 12:38:00\t<--\tdave (~dave@host) has quit (Ping timeout: 250 seconds)",
+                "12:38:00",
+                MessageKind::Quit,
+                "dave",
+                "Ping timeout: 250 seconds",
+            ),
+            (
+                "2023-01-31 12:39:00\t--\tdave is now known as dave_",
+                "12:39:00",
+                MessageKind::Nick,
+                "dave",
+                "dave_",
+            ),
+            (
+                "2023-01-31 12:40:00\t--\talice has changed topic for #chan from \"old\" to \"new topic\"",
+                "12:40:00",
+                MessageKind::Topic,
+                "alice",
+                "new topic",
+            ),
+        ];
+
+        for (line, time, kind, author, body) in cases {
+            assert_eq!(parse(line), Some((time.to_owned(), kind, author, body)), "{}", line);
+        }
+    }
+
+    #[test]
+    fn malformed_lines() {
+        for line in [
+            "",
+            "2023-01-31 12:34:56 alice hello",
+            "2023-01-31\talice\thello",
+            "2023-01-31 25:00:00\talice\thello",
+            "2023-01-31 12:41:00\t--\tMode #chan [+o alice] by bob",
+            "2023-01-31 12:42:00\t=!=\tcannot join channel",
+        ] {
+            assert!(parse(line).is_none(), "{}", line);
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use super::*;
+use once_cell::sync::Lazy;
+
+/// ZNC's `log` module: `[12:34:56] <nick> text`, `[12:34:56] * nick acts`
+/// and `[12:34:56] *** Joins: nick (ident@host)` for events.
+struct Znc;
+
+/// Action and event lines, shared with formats that copy ZNC's.
+pub(super) fn events() -> Vec<(MessageKind, Regex)> {
+    patterns(&[
+        (MessageKind::Join, r"^\[(?P<time>\d{2}:\d{2}:\d{2})\] \*\*\* Joins: (?P<nick>\S+)"),
+        (
+            MessageKind::Part,
+            r"^\[(?P<time>\d{2}:\d{2}:\d{2})\] \*\*\* Parts: (?P<nick>\S+) \([^)]*\)(?: \((?P<text>.*)\))?$",
+        ),
+        (
+            MessageKind::Quit,
+            r"^\[(?P<time>\d{2}:\d{2}:\d{2})\] \*\*\* Quits: (?P<nick>\S+) \([^)]*\)(?: \((?P<text>.*)\))?$",
+        ),
+        (
+            MessageKind::Nick,
+            r"^\[(?P<time>\d{2}:\d{2}:\d{2})\] \*\*\* (?P<nick>\S+) is now known as (?P<text>\S+)$",
+        ),
+        (
+            MessageKind::Topic,
+            r"^\[(?P<time>\d{2}:\d{2}:\d{2})\] \*\*\* (?P<nick>\S+) changes topic to '(?P<text>.*)'$",
+        ),
+        (MessageKind::Action, r"^\[(?P<time>\d{2}:\d{2}:\d{2})\] \* (?P<nick>\S+) (?P<text>.*)$"),
+    ])
+}
+
+static PATTERNS: Lazy<Vec<(MessageKind, Regex)>> = Lazy::new(|| {
+    let mut list = patterns(&[(
+        MessageKind::Message,
+        r"^\[(?P<time>\d{2}:\d{2}:\d{2})\] <(?P<nick>[^>]+)> (?P<text>.*)$",
+    )]);
+    list.extend(events());
+    list
+});
+
+impl LogFormat for Znc {
+    fn parse_line<'a>(&self, line: &'a str) -> Option<LogLine<'a>> {
+        parse_any(&PATTERNS, line)
+    }
+}
+
+log_format!("znc", Znc);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Option<(String, MessageKind, &str, &str)> {
+        parsed(&Znc, line)
+    }
+
+    #[test]
+    fn lines() {
+        let cases = [
+            ("[12:34:56] <@alice> hello there", "12:34:56", MessageKind::Message, "alice", "hello there"),
+            ("[12:35:00] * alice waves hello", "12:35:00", MessageKind::Action, "alice", "waves hello"),
+            ("[12:36:00] *** Joins: carol (~carol@example.org)", "12:36:00", MessageKind::Join, "carol", ""),
+            (
+                "[12:37:00] *** Parts: carol (~carol@example.org) (bye now)",
+                "12:37:00",
+                MessageKind::Part,
+                "carol",
+                "bye now",
+            ),
+            ("[12:37:30] *** Parts: carol (~carol@example.org)", "12:37:30", MessageKind::Part, "carol", ""),
+            (
+                "[12:38:00] *** Quits: dave (~dave@host) (Ping timeout: 250 seconds)",
+                "12:38:00",
+                MessageKind::Quit,
+                "dave",
+                "Ping timeout: 250 seconds",
+            ),
+            ("[12:39:00] *** dave is now known as dave_", "12:39:00", MessageKind::Nick, "dave", "dave_"),
+            (
+                "[12:40:00] *** alice changes topic to 'it's new'",
+                "12:40:00",
+                MessageKind::Topic,
+                "alice",
+                "it's new",
+            ),
+        ];
+
+        for (line, time, kind, author, body) in cases {
+            assert_eq!(parse(line), Some((time.to_owned(), kind, author, body)), "{}", line);
+        }
+    }
+
+    #[test]
+    fn malformed_lines() {
+        for line in [
+            "",
+            "[12:34] <alice> hello",
+            "12:34:56 <alice> hello",
+            "[12:34:56]",
+            "[12:34:56] <alice>",
+            "[24:00:00] <alice> hello",
+            "[12:41:00] *** alice sets mode: +o bob",
+        ] {
+            assert!(parse(line).is_none(), "{}", line);
+        }
+    }
+}
@@ -1,61 +1,185 @@
-use std::str::FromStr;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
 use anyhow::{Context, Result};
-use chrono::{LocalResult, NaiveDate, NaiveTime, TimeZone};
+use chrono::{NaiveDate, NaiveDateTime};
+use chrono_tz::Tz;
+use flate2::read::GzDecoder;
 use once_cell::sync::Lazy;
-use regex::Regex;
-use reqwest::Client as WebClient;
-use sqlx::{prelude::*, Pool, Postgres};
+use reqwest::{Client as WebClient, StatusCode};
+use serde::{Deserialize, Serialize};
+use sqlx::{prelude::*, FromRow, Pool, Postgres};
 use tokio::sync::Mutex;
 
-pub async fn get_latest_msg(db: Pool<Postgres>) -> Result<Option<(i32, NaiveDate)>> {
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::Source;
+use crate::jobs::{ImportJob, JobState, Jobs};
+use crate::formats;
+use crate::models::MessageKind;
+use crate::timezone::{day_bounds, local_to_utc, today, utc_to_local};
+
+/// Where the previous import of `source` stopped, as the offset of the last
+/// imported line and its day. Sources imported before their state was tracked
+/// fall back to the latest message of their channel.
+pub async fn get_state(db: Pool<Postgres>, source: &Source) -> Result<Option<(i32, NaiveDate)>> {
+    let query = sqlx::query_as(
+        "SELECT last_offset, last_date FROM import_state WHERE source = $1",
+    )
+    .bind(&source.name);
+
+    if let Some(state) = query.fetch_optional(&db).await? {
+        return Ok(Some(state));
+    }
+
     let query = sqlx::query_as(
-        "SELECT msg_offset, msg_timestamp::date FROM messages \
-        ORDER BY messages.msg_timestamp DESC LIMIT 1",
-    );
+        "SELECT msg_offset, msg_timestamp FROM messages WHERE msg_channel = $1 \
+        ORDER BY msg_timestamp DESC, msg_offset DESC LIMIT 1",
+    )
+    .bind(&source.channel);
+
+    let latest: Option<(i32, NaiveDateTime)> = query.fetch_optional(&db).await?;
+    Ok(latest.map(|(offset, time)| (offset, utc_to_local(source.timezone, time).date())))
+}
+
+async fn save_state(db: Pool<Postgres>, source: &Source, date: NaiveDate, offset: i32) -> Result<()> {
+    let query = sqlx::query(
+        "INSERT INTO import_state (source, last_date, last_offset) VALUES ($1, $2, $3) \
+        ON CONFLICT (source) DO UPDATE \
+        SET last_date = $2, last_offset = $3, updated_at = now()",
+    )
+    .bind(&source.name)
+    .bind(date)
+    .bind(offset);
 
-    Ok(query.fetch_optional(&db).await?)
+    db.execute(query).await?;
+    Ok(())
 }
 
-async fn download_logs(web: &WebClient, date: NaiveDate) -> Result<String> {
-    let url = format!("https://logs.fomalhaut.me/download/{}.log", date);
-    let data = web.get(&url).send().await?.bytes().await?;
+/// Log of `source` for `date`, or `None` if there is no log for that day.
+async fn download_logs(web: &WebClient, source: &Source, date: NaiveDate) -> Result<Option<String>> {
+    let url = source.url.replace("{date}", &date.to_string());
+    let response = web.get(&url).send().await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+
+    let data = response.error_for_status()?.bytes().await?;
     let data = String::from_utf8_lossy(&data).into_owned();
-    Ok(data)
+    Ok(Some(data))
+}
+
+/// A day that could not be imported, see [`failures`].
+#[derive(Serialize, FromRow)]
+pub struct Failure {
+    pub source: String,
+    pub date: NaiveDate,
+    pub error: String,
+    pub attempts: i32,
+    pub failed_at: NaiveDateTime,
+}
+
+async fn save_failure(
+    db: Pool<Postgres>,
+    source: &Source,
+    date: NaiveDate,
+    error: &str,
+    attempts: u32,
+) -> Result<()> {
+    let query = sqlx::query(
+        "INSERT INTO import_failures (source, date, error, attempts) VALUES ($1, $2, $3, $4) \
+        ON CONFLICT (source, date) DO UPDATE \
+        SET error = $3, attempts = import_failures.attempts + $4, failed_at = now()",
+    )
+    .bind(&source.name)
+    .bind(date)
+    .bind(error)
+    .bind(attempts as i32);
+
+    db.execute(query).await?;
+    Ok(())
+}
+
+async fn clear_failure(db: Pool<Postgres>, source: &Source, date: NaiveDate) -> Result<()> {
+    let query = sqlx::query("DELETE FROM import_failures WHERE source = $1 AND date = $2")
+        .bind(&source.name)
+        .bind(date);
+
+    db.execute(query).await?;
+    Ok(())
 }
 
-static MESSAGE_RE: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r"^\[(\d{2}:\d{2}:\d{2})\] <([^>]+)> (.+)").unwrap());
+/// Days of `source` that failed to import and were not imported since,
+/// oldest first.
+pub async fn failures(db: Pool<Postgres>, source: &Source) -> Result<Vec<Failure>> {
+    let failures = sqlx::query_as(
+        "SELECT source, date, error, attempts, failed_at FROM import_failures \
+        WHERE source = $1 ORDER BY date",
+    )
+    .bind(&source.name)
+    .fetch_all(&db)
+    .await?;
+
+    Ok(failures)
+}
 
 pub static LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
 
+/// Channel notified with an [`Imported`] payload whenever messages are
+/// imported or received live.
+pub const CHANNEL: &str = "logs_imported";
+
+/// Notification of messages `first_id..=last_id` stored under `channel` on
+/// `date`, a day of the source's time zone.
+#[derive(Serialize, Deserialize)]
+pub struct Imported {
+    pub source: String,
+    pub channel: String,
+    pub date: NaiveDate,
+    pub count: u64,
+    pub first_id: i32,
+    pub last_id: i32,
+    /// Offset of the last message of the day the import replaced, if any.
+    /// Subscribers were already sent the messages up to it.
+    #[serde(default)]
+    pub replaced: Option<i32>,
+}
+
+async fn notify(db: Pool<Postgres>, imported: &Imported) -> Result<()> {
+    sqlx::query("SELECT pg_notify($1, $2)")
+        .bind(CHANNEL)
+        .bind(serde_json::to_string(imported)?)
+        .execute(&db)
+        .await?;
+    Ok(())
+}
+
+/// Replaces messages of `source` on `date` past `cut_offset` with the lines
+/// of `data`. Returns the number of inserted messages and the offset of the
+/// last one, or `cut_offset` if nothing was inserted.
 async fn insert_logs(
     db: Pool<Postgres>,
+    source: &Source,
     data: String,
     date: NaiveDate,
     cut_offset: i32,
-) -> Result<u64> {
+) -> Result<(u64, i32)> {
+    let format = formats::find(&source.format)?;
     let mut timestamps = Vec::new();
     let mut offsets = Vec::new();
     let mut authors = Vec::new();
     let mut bodies = Vec::new();
+    let mut kinds = Vec::new();
 
     for (offset, line) in data.lines().enumerate() {
         let offset = offset as i32;
-        let c = match MESSAGE_RE.captures(line) {
+        let line = match format.parse_line(line) {
             Some(v) => v,
             None => continue,
         };
 
-        let time = c.get(1).unwrap().as_str();
-        let time = NaiveTime::from_str(time).unwrap();
-        let local = chrono_tz::EET.from_local_datetime(&date.and_time(time));
-        let timestamp = match local {
-            LocalResult::Single(v) => v.naive_utc(),
-            _ => {
-                println!("Invalid message time, skipping: {} {}", date, time);
-                continue;
-            }
-        };
+        let timestamp = local_to_utc(source.timezone, date.and_time(line.time));
 
         if offset <= cut_offset {
             continue;
@@ -63,45 +187,677 @@ async fn insert_logs(
 
         timestamps.push(timestamp);
         offsets.push(offset);
-        authors.push(c.get(2).unwrap().as_str());
-        bodies.push(c.get(3).unwrap().as_str());
+        authors.push(line.author);
+        bodies.push(line.body);
+        kinds.push(line.kind.as_str());
     }
 
-    let query =
-        sqlx::query("DELETE FROM messages WHERE msg_timestamp::date = $1 AND msg_offset > $2")
-            .bind(&date)
-            .bind(&cut_offset);
-    db.execute(query).await?;
+    let last_offset = offsets.last().copied().unwrap_or(cut_offset);
+    let (start, end) = day_bounds(source.timezone, date);
 
-    let query = sqlx::query(
+    // readers see either the old or the new messages of the day, never neither
+    let mut tx = db.begin().await?;
+
+    let query = sqlx::query_scalar(
+        "DELETE FROM messages WHERE msg_channel = $1 \
+        AND msg_timestamp >= $2 AND msg_timestamp < $3 AND msg_offset > $4 \
+        RETURNING msg_offset",
+    )
+    .bind(&source.channel)
+    .bind(start)
+    .bind(end)
+    .bind(cut_offset);
+    let replaced: Vec<i32> = query.fetch_all(&mut *tx).await?;
+
+    let query = sqlx::query_as(
         r#"INSERT INTO messages (msg_timestamp, msg_offset,
-            msg_channel, msg_author, msg_body)
+            msg_channel, msg_author, msg_body, msg_kind)
         SELECT msg_timestamp, msg_offset,
-            $2 AS msg_channel, msg_author, msg_body
-        FROM unnest($1::timestamp[], $3::integer[], $4::text[], $5::text[]) AS query(msg_timestamp, msg_offset,
-            msg_author, msg_body)"#,
+            $2 AS msg_channel, msg_author, msg_body, msg_kind
+        FROM unnest($1::timestamp[], $3::integer[], $4::text[], $5::text[], $6::text[]) AS query(msg_timestamp, msg_offset,
+            msg_author, msg_body, msg_kind)
+        RETURNING msg_id"#,
     )
     .bind(timestamps)
-    .bind("#cc.ru")
+    .bind(&source.channel)
     .bind(offsets)
     .bind(authors)
-    .bind(bodies);
+    .bind(bodies)
+    .bind(kinds);
+
+    let ids: Vec<(i32,)> = query.fetch_all(&mut *tx).await?;
+    tx.commit().await?;
+
+    let count = ids.len() as u64;
+    if let (Some((first_id,)), Some((last_id,))) = (ids.iter().min(), ids.iter().max()) {
+        let imported = Imported {
+            source: source.name.clone(),
+            channel: source.channel.clone(),
+            date,
+            count,
+            first_id: *first_id,
+            last_id: *last_id,
+            replaced: replaced.into_iter().max(),
+        };
+        notify(db, &imported).await?;
+    }
+
+    Ok((count, last_offset))
+}
+
+/// Stores a single message received live in `channel` at `time`, in UTC.
+/// Its offset continues the messages of the day in `timezone`, as if it
+/// were the next line of that day's log file.
+pub async fn insert_message(
+    db: Pool<Postgres>,
+    channel: &str,
+    timezone: Tz,
+    time: NaiveDateTime,
+    kind: MessageKind,
+    author: &str,
+    body: &str,
+) -> Result<()> {
+    let date = utc_to_local(timezone, time).date();
+    let (start, end) = day_bounds(timezone, date);
+
+    let query = sqlx::query_as(
+        r#"INSERT INTO messages (msg_timestamp, msg_offset,
+            msg_channel, msg_author, msg_body, msg_kind)
+        SELECT $1, COALESCE(max(msg_offset) + 1, 0),
+            $2, $3, $4, $5
+        FROM messages WHERE msg_channel = $2 AND msg_timestamp >= $6 AND msg_timestamp < $7
+        RETURNING msg_id"#,
+    )
+    .bind(time)
+    .bind(channel)
+    .bind(author)
+    .bind(body)
+    .bind(kind.as_str())
+    .bind(start)
+    .bind(end);
+
+    let (id,): (i32,) = query.fetch_one(&db).await?;
+    let imported = Imported {
+        source: String::from("irc"),
+        channel: channel.to_owned(),
+        date,
+        count: 1,
+        first_id: id,
+        last_id: id,
+        replaced: None,
+    };
+    notify(db, &imported).await
+}
+
+/// Attempts at importing a day before it is recorded as failed.
+const ATTEMPTS: u32 = 4;
+
+/// Wait before the first retry of a day, doubled after each further attempt.
+#[cfg(not(test))]
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+#[cfg(test)]
+const RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// Longest a download may take, so a stalled source can't hold [`LOCK`]
+/// and its job forever.
+#[cfg(not(test))]
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+#[cfg(test)]
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_millis(300);
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Days from `start` through today in the time zone of `source`.
+pub fn days_since(source: &Source, start: NaiveDate) -> Vec<NaiveDate> {
+    start
+        .iter_days()
+        .take_while(|date| *date <= today(source.timezone))
+        .collect()
+}
+
+/// Downloads and imports `days` of `source` as a background job, stopping
+/// early when the job is cancelled. The first day is imported past
+/// `cut_offset` only.
+///
+/// Each day is retried with backoff. Days that still fail are recorded in
+/// `import_failures` and skipped, so the rest of the days are imported.
+pub async fn run(
+    db: Pool<Postgres>,
+    source: Source,
+    days: Vec<NaiveDate>,
+    mut cut_offset: i32,
+    job: Arc<ImportJob>,
+) {
+    let web = match WebClient::builder()
+        .timeout(DOWNLOAD_TIMEOUT)
+        .connect_timeout(CONNECT_TIMEOUT)
+        .build()
+    {
+        Ok(web) => web,
+        Err(err) => {
+            job.update(|status| status.errors.push(format!("{:#}", err)));
+            job.finish(JobState::Failed);
+            return;
+        }
+    };
+
+    for date in days {
+        if job.is_cancelled() {
+            job.finish(JobState::Cancelled);
+            return;
+        }
+
+        job.update(|status| status.current_date = Some(date));
+
+        let mut attempts = 0;
+        let mut delay = RETRY_DELAY;
+        let result = loop {
+            attempts += 1;
+            match download_and_insert_logs(db.clone(), &web, &source, date, cut_offset).await {
+                Err(err) if attempts < ATTEMPTS && !job.is_cancelled() => {
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                    if job.is_cancelled() {
+                        break Err(err);
+                    }
+                }
+                result => break result,
+            }
+        };
+
+        match result {
+            Ok(count) => {
+                if let Err(err) = clear_failure(db.clone(), &source, date).await {
+                    job.update(|status| status.errors.push(format!("{}: {:#}", date, err)));
+                }
+                job.update(|status| status.rows += count);
+            }
+            Err(err) => {
+                let error = format!("{:#}", err);
+                if let Err(err) = save_failure(db.clone(), &source, date, &error, attempts).await {
+                    job.update(|status| status.errors.push(format!("{}: {:#}", date, err)));
+                }
+                job.update(|status| {
+                    status.errors.push(format!("{}: {}", date, error));
+                    status.failed_days.push(date);
+                });
+            }
+        }
+
+        job.update(|status| {
+            status.days_done += 1;
+            status.days_remaining -= 1;
+        });
+        cut_offset = -1;
+    }
+
+    if job.is_cancelled() {
+        job.finish(JobState::Cancelled);
+    } else if job.status().failed_days.is_empty() {
+        job.finish(JobState::Finished);
+    } else {
+        job.finish(JobState::Failed);
+    }
+}
+
+/// Imports `source` from where the previous import stopped, as scheduled by
+/// its `import_interval`. Skipped when another import holds [`LOCK`].
+pub async fn scheduled(db: Pool<Postgres>, source: Source, jobs: Arc<Jobs>) {
+    let _guard = match LOCK.try_lock() {
+        Ok(v) => v,
+        Err(_) => {
+            println!("scheduled import of '{}' skipped: import already running", source.name);
+            return;
+        }
+    };
+
+    let (cut_offset, start) = match get_state(db.clone(), &source).await {
+        Ok(Some(state)) => state,
+        Ok(None) => {
+            println!("scheduled import of '{}' skipped: cannot get start date", source.name);
+            return;
+        }
+        Err(err) => {
+            eprintln!("scheduled import of '{}' failed: {:#}", source.name, err);
+            return;
+        }
+    };
+
+    let days = days_since(&source, start);
+    let job = jobs.start(&source.name, days.len() as i64);
+    run(db, source, days, cut_offset, job).await;
+}
+
+/// Day a log file holds, taken from its name (without `.gz`) using `pattern`,
+/// a `chrono` format like `%Y-%m-%d.log`.
+pub fn file_date(path: &Path, pattern: &str) -> Option<NaiveDate> {
+    let name = path.file_name()?.to_str()?;
+    let name = name.strip_suffix(".gz").unwrap_or(name);
+    NaiveDate::parse_from_str(name, pattern).ok()
+}
+
+/// Log files among `paths` with their days, searching directories
+/// recursively. Files whose names don't match `pattern` are left out.
+pub fn collect_log_files(paths: &[PathBuf], pattern: &str) -> Result<Vec<(PathBuf, NaiveDate)>> {
+    let mut files = Vec::new();
+
+    for path in paths {
+        if path.is_dir() {
+            let mut entries = std::fs::read_dir(path)
+                .with_context(|| format!("failed to read {}", path.display()))?
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<std::io::Result<Vec<_>>>()?;
+            entries.sort();
+            files.extend(collect_log_files(&entries, pattern)?);
+        } else if let Some(date) = file_date(path, pattern) {
+            files.push((path.clone(), date));
+        }
+    }
+
+    Ok(files)
+}
+
+fn read_log_file(path: &Path) -> Result<String> {
+    let mut data = Vec::new();
+    let file = File::open(path)?;
+
+    if path.extension().is_some_and(|ext| ext == "gz") {
+        GzDecoder::new(file).read_to_end(&mut data)?;
+    } else {
+        BufReader::new(file).read_to_end(&mut data)?;
+    }
+
+    Ok(String::from_utf8_lossy(&data).into_owned())
+}
+
+/// Imports a whole day of `source` from a log file, replacing the messages
+/// stored for that day. The incremental state of the source is left alone.
+pub async fn import_file(
+    db: Pool<Postgres>,
+    source: &Source,
+    path: &Path,
+    date: NaiveDate,
+) -> Result<u64> {
+    let data = read_log_file(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+
+    let (count, _) = insert_logs(db, source, data, date, -1)
+        .await
+        .context("failed to insert logs")?;
+
+    Ok(count)
+}
+
+/// Messages updated per statement by [`reindex`].
+const REINDEX_BATCH: i32 = 10000;
 
-    let count = db.execute(query).await?;
-    Ok(count.rows_affected())
+/// Recomputes `msg_tsv` of every message, e.g. after the 'russian' text
+/// search configuration was changed. The column is generated, so updating a
+/// row recomputes it. Returns the number of updated rows.
+pub async fn reindex(db: Pool<Postgres>) -> Result<u64> {
+    let (max_id,): (Option<i32>,) = sqlx::query_as("SELECT max(msg_id) FROM messages")
+        .fetch_one(&db)
+        .await?;
+
+    let mut count = 0;
+    let mut start = 0;
+    while start < max_id.unwrap_or(0) {
+        let query = sqlx::query(
+            "UPDATE messages SET msg_body = msg_body \
+            WHERE msg_id > $1 AND msg_id <= $2",
+        )
+        .bind(start)
+        .bind(start.saturating_add(REINDEX_BATCH));
+
+        count += db.execute(query).await?.rows_affected();
+        start = start.saturating_add(REINDEX_BATCH);
+    }
+
+    Ok(count)
 }
 
 pub async fn download_and_insert_logs(
     db: Pool<Postgres>,
     web: &WebClient,
+    source: &Source,
     date: NaiveDate,
     cut_offset: i32,
 ) -> Result<u64> {
-    let data = download_logs(web, date)
+    let data = download_logs(web, source, date)
         .await
         .context("failed to download logs")?;
 
-    insert_logs(db, data, date, cut_offset)
+    // a missing log is an empty day, whatever is stored for it stays
+    let (count, last_offset) = match data {
+        Some(data) => insert_logs(db.clone(), source, data, date, cut_offset)
+            .await
+            .context("failed to insert logs")?,
+        None => (0, cut_offset),
+    };
+
+    save_state(db, source, date, last_offset)
+        .await
+        .context("failed to save import state")?;
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Instant;
+
+    use warp::http::StatusCode;
+    use warp::Filter;
+
+    use super::*;
+
+    type Hits = Arc<std::sync::Mutex<Vec<(String, Instant)>>>;
+
+    /// A local stand-in source serving `/<date>.log`, answering the n-th
+    /// request for a day with the n-th of its responses, or the last one.
+    fn serve(responses: HashMap<&'static str, Vec<(u16, &'static str)>>) -> (String, Hits) {
+        let hits: Hits = Arc::default();
+        let route = warp::path::param::<String>().map({
+            let hits = hits.clone();
+            move |file: String| {
+                let mut hits = hits.lock().unwrap();
+                let n = hits.iter().filter(|(f, _)| *f == file).count();
+                hits.push((file.clone(), Instant::now()));
+
+                let day = file.trim_end_matches(".log");
+                let (status, body) = match responses.get(day) {
+                    Some(list) => list[n.min(list.len() - 1)],
+                    None => (404, ""),
+                };
+                warp::reply::with_status(body, StatusCode::from_u16(status).unwrap())
+            }
+        });
+
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (format!("http://{}/{{date}}.log", addr), hits)
+    }
+
+    fn source(url: String) -> Source {
+        Source {
+            name: String::from("stand-in"),
+            url,
+            channel: String::from("#stand-in"),
+            timezone: chrono_tz::UTC,
+            format: String::from("fomalhaut"),
+            import_interval: None,
+        }
+    }
+
+    fn day(value: &str) -> NaiveDate {
+        value.parse().unwrap()
+    }
+
+    async fn messages(db: &Pool<Postgres>, date: &str) -> Vec<String> {
+        let start = day(date).and_time(chrono::NaiveTime::MIN);
+        sqlx::query_scalar(
+            "SELECT msg_body FROM messages WHERE msg_channel = '#stand-in' \
+            AND msg_timestamp >= $1 AND msg_timestamp < $1 + interval '1 day' \
+            ORDER BY msg_offset",
+        )
+        .bind(start)
+        .fetch_all(db)
         .await
-        .context("failed to insert logs")
-}
\ No newline at end of file
+        .unwrap()
+    }
+
+    #[sqlx::test]
+    async fn imports_days_with_retries(db: Pool<Postgres>) {
+        let (url, hits) = serve(HashMap::from([
+            ("2023-01-01", vec![(200, "[10:00:00] <amy> first\n[10:01:00] <bob> second\n")]),
+            ("2023-01-02", vec![(404, "")]),
+            ("2023-01-03", vec![(500, ""), (502, ""), (200, "[12:00:00] <amy> third\n")]),
+            ("2023-01-04", vec![(503, "")]),
+        ]));
+        let source = source(url);
+        let days: Vec<NaiveDate> = ["2023-01-01", "2023-01-02", "2023-01-03", "2023-01-04"]
+            .into_iter()
+            .map(day)
+            .collect();
+
+        let jobs = Jobs::default();
+        let job = jobs.start(&source.name, days.len() as i64);
+        run(db.clone(), source.clone(), days, -1, job.clone()).await;
+
+        let status = job.status();
+        assert_eq!(status.state, JobState::Failed);
+        assert_eq!(status.rows, 3);
+        assert_eq!(status.days_done, 4);
+        assert_eq!(status.failed_days, vec![day("2023-01-04")]);
+
+        assert_eq!(messages(&db, "2023-01-01").await, vec!["first", "second"]);
+        assert!(messages(&db, "2023-01-02").await.is_empty());
+        assert_eq!(messages(&db, "2023-01-03").await, vec!["third"]);
+
+        // a missing log is an empty day, only the failing day is recorded
+        let failures = failures(db.clone(), &source).await.unwrap();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].date, day("2023-01-04"));
+        assert_eq!(failures[0].attempts, ATTEMPTS as i32);
+        assert!(failures[0].error.contains("503"), "{}", failures[0].error);
+
+        // the state points past the last day imported
+        let state = get_state(db.clone(), &source).await.unwrap();
+        assert_eq!(state, Some((0, day("2023-01-03"))));
+
+        let hits = hits.lock().unwrap();
+        let times = |file: &str| -> Vec<Instant> {
+            hits.iter().filter(|(f, _)| f == file).map(|(_, t)| *t).collect()
+        };
+        assert_eq!(times("2023-01-01.log").len(), 1);
+        assert_eq!(times("2023-01-02.log").len(), 1);
+        assert_eq!(times("2023-01-03.log").len(), 3);
+
+        let retries = times("2023-01-04.log");
+        assert_eq!(retries.len(), ATTEMPTS as usize);
+        for (i, pair) in retries.windows(2).enumerate() {
+            assert!(pair[1] - pair[0] >= RETRY_DELAY * 2u32.pow(i as u32));
+        }
+    }
+
+    #[sqlx::test]
+    async fn successful_import_clears_failure(db: Pool<Postgres>) {
+        let (url, _) = serve(HashMap::from([(
+            "2023-01-01",
+            vec![(500, ""), (500, ""), (500, ""), (500, ""), (200, "[10:00:00] <amy> late\n")],
+        )]));
+        let source = source(url);
+        let jobs = Jobs::default();
+
+        let job = jobs.start(&source.name, 1);
+        run(db.clone(), source.clone(), vec![day("2023-01-01")], -1, job.clone()).await;
+        assert_eq!(job.status().state, JobState::Failed);
+        assert_eq!(failures(db.clone(), &source).await.unwrap().len(), 1);
+
+        let job = jobs.start(&source.name, 1);
+        run(db.clone(), source.clone(), vec![day("2023-01-01")], -1, job.clone()).await;
+        assert_eq!(job.status().state, JobState::Finished);
+        assert!(failures(db.clone(), &source).await.unwrap().is_empty());
+        assert_eq!(messages(&db, "2023-01-01").await, vec!["late"]);
+    }
+
+    /// A local stand-in source that accepts requests but never answers.
+    fn serve_stalled() -> (String, Hits) {
+        let hits: Hits = Arc::default();
+        let route = warp::path::param::<String>().and_then({
+            let hits = hits.clone();
+            move |file: String| {
+                hits.lock().unwrap().push((file, Instant::now()));
+                async {
+                    tokio::time::sleep(Duration::from_secs(3600)).await;
+                    Ok::<_, warp::Rejection>("")
+                }
+            }
+        });
+
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (format!("http://{}/{{date}}.log", addr), hits)
+    }
+
+    #[sqlx::test]
+    async fn stalled_downloads_time_out(db: Pool<Postgres>) {
+        let (url, hits) = serve_stalled();
+        let source = source(url);
+        let jobs = Jobs::default();
+
+        let job = jobs.start(&source.name, 1);
+        let started = Instant::now();
+        run(db.clone(), source.clone(), vec![day("2023-01-01")], -1, job.clone()).await;
+
+        assert!(started.elapsed() < DOWNLOAD_TIMEOUT * ATTEMPTS + RETRY_DELAY * 16);
+        assert_eq!(hits.lock().unwrap().len(), ATTEMPTS as usize);
+        let status = job.status();
+        assert_eq!(status.state, JobState::Failed);
+        assert_eq!(status.failed_days, vec![day("2023-01-01")]);
+        assert!(status.errors[0].contains("download"), "{:?}", status.errors);
+    }
+
+    #[sqlx::test]
+    async fn cancelling_stops_retries_of_a_stalled_day(db: Pool<Postgres>) {
+        let (url, hits) = serve_stalled();
+        let source = source(url);
+        let jobs = Jobs::default();
+
+        let job = jobs.start(&source.name, 2);
+        let running = tokio::spawn(run(
+            db.clone(),
+            source.clone(),
+            vec![day("2023-01-01"), day("2023-01-02")],
+            -1,
+            job.clone(),
+        ));
+        tokio::time::sleep(DOWNLOAD_TIMEOUT / 2).await;
+        job.cancel();
+        running.await.unwrap();
+
+        // the attempt under way runs into the timeout, no further one starts
+        assert_eq!(hits.lock().unwrap().len(), 1);
+        assert_eq!(job.status().state, JobState::Cancelled);
+    }
+
+    /// A fresh directory for the files of `test`.
+    fn directory(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sprout-import-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_gz(path: &Path, data: &str) {
+        let mut encoder = flate2::write::GzEncoder::new(File::create(path).unwrap(), flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, data.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+    }
+
+    #[test]
+    fn dates_from_file_names() {
+        let date = |name: &str, pattern: &str| file_date(Path::new(name), pattern);
+        let pattern = "%Y-%m-%d.log";
+
+        assert_eq!(date("2023-01-15.log", pattern), Some(day("2023-01-15")));
+        assert_eq!(date("logs/2023/2023-01-15.log", pattern), Some(day("2023-01-15")));
+        assert_eq!(date("2023-01-15.log.gz", pattern), Some(day("2023-01-15")));
+        assert_eq!(date("2024-02-29.log", pattern), Some(day("2024-02-29")));
+        assert_eq!(date("#chan.20230115.txt", "#chan.%Y%m%d.txt"), Some(day("2023-01-15")));
+
+        for name in [
+            "2023-02-29.log",
+            "2023-13-01.log",
+            "2023-01-32.log",
+            "2023-01-15.txt",
+            "2023-01-15.log.bak",
+            "2023-01-15.gz",
+            "notes.log",
+            "",
+        ] {
+            assert_eq!(date(name, pattern), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn collects_log_files() {
+        let dir = directory("collect");
+        std::fs::create_dir_all(dir.join("b")).unwrap();
+        std::fs::create_dir_all(dir.join("a/nested")).unwrap();
+        for name in [
+            "b/2023-01-03.log",
+            "a/2023-01-02.log.gz",
+            "a/nested/2023-01-01.log",
+            "a/readme.txt",
+            "2023-01-05.log",
+            "2023-01-04.log",
+        ] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+        let single = directory("collect-single").join("2022-12-31.log");
+        std::fs::write(&single, "").unwrap();
+
+        // arguments in the order given, directory entries sorted by path
+        let files = collect_log_files(&[single.clone(), dir.clone()], "%Y-%m-%d.log").unwrap();
+        let expected = vec![
+            (single, day("2022-12-31")),
+            (dir.join("2023-01-04.log"), day("2023-01-04")),
+            (dir.join("2023-01-05.log"), day("2023-01-05")),
+            (dir.join("a/2023-01-02.log.gz"), day("2023-01-02")),
+            (dir.join("a/nested/2023-01-01.log"), day("2023-01-01")),
+            (dir.join("b/2023-01-03.log"), day("2023-01-03")),
+        ];
+        assert_eq!(files, expected);
+
+        assert!(collect_log_files(&[dir.join("missing/")], "%Y-%m-%d.log").unwrap().is_empty());
+    }
+
+    #[test]
+    fn reads_gzipped_files() {
+        let dir = directory("gzip");
+        let data = "[10:00:00] <amy> ünïcödé\n[10:01:00] <bob> second\n";
+        write_gz(&dir.join("2023-01-01.log.gz"), data);
+        std::fs::write(dir.join("2023-01-02.log"), data).unwrap();
+
+        assert_eq!(read_log_file(&dir.join("2023-01-01.log.gz")).unwrap(), data);
+        assert_eq!(read_log_file(&dir.join("2023-01-02.log")).unwrap(), data);
+
+        // not actually compressed
+        std::fs::write(dir.join("2023-01-03.log.gz"), data).unwrap();
+        assert!(read_log_file(&dir.join("2023-01-03.log.gz")).is_err());
+    }
+
+    #[sqlx::test]
+    async fn imports_gzipped_files(db: Pool<Postgres>) {
+        let path = directory("gzip-import").join("2023-01-01.log.gz");
+        write_gz(&path, "[10:00:00] <amy> first\n[10:01:00] <bob> second\n");
+
+        let source = source(String::new());
+        let count = import_file(db.clone(), &source, &path, day("2023-01-01")).await.unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(messages(&db, "2023-01-01").await, vec!["first", "second"]);
+    }
+
+    #[sqlx::test]
+    async fn state_falls_back_to_last_message(db: Pool<Postgres>) {
+        let source = source(String::new());
+        assert_eq!(get_state(db.clone(), &source).await.unwrap(), None);
+
+        // lines of the same second, stored out of order
+        for offset in [7, 9, 3] {
+            sqlx::query(
+                "INSERT INTO messages (msg_timestamp, msg_offset, msg_channel, msg_author, msg_body) \
+                VALUES ('2023-01-01 23:59:59', $1, '#stand-in', 'amy', 'hi')",
+            )
+            .bind(offset)
+            .execute(&db)
+            .await
+            .unwrap();
+        }
+
+        let state = get_state(db.clone(), &source).await.unwrap();
+        assert_eq!(state, Some((9, day("2023-01-01"))));
+    }
+}
@@ -0,0 +1,659 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use sqlx::{Pool, Postgres};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::{ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName};
+use tokio_rustls::TlsConnector;
+
+use crate::config::Irc;
+use crate::import;
+use crate::models::MessageKind;
+
+/// Time without a line from the server before it is pinged, and once more
+/// before the connection is given up on.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(240);
+
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(300);
+
+/// Longest line accepted from the server: 8191 bytes of IRCv3 message tags
+/// and 512 bytes of the message itself. Longer lines drop the connection.
+const MAX_LINE_LENGTH: usize = 8191 + 512;
+
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+/// A line of the IRC protocol: `[@tags] [:prefix] COMMAND [params] [:trailing]`.
+/// Tags are skipped.
+#[derive(Debug)]
+struct Line<'a> {
+    prefix: Option<&'a str>,
+    command: &'a str,
+    params: Vec<&'a str>,
+}
+
+impl<'a> Line<'a> {
+    fn parse(line: &'a str) -> Option<Line<'a>> {
+        let mut rest = line.trim_end_matches(['\r', '\n']);
+
+        if rest.starts_with('@') {
+            rest = rest.split_once(' ')?.1.trim_start_matches(' ');
+        }
+
+        let prefix = match rest.strip_prefix(':') {
+            Some(value) => {
+                let (prefix, value) = value.split_once(' ')?;
+                rest = value;
+                Some(prefix)
+            }
+            None => None,
+        };
+
+        let rest = rest.trim_start_matches(' ');
+        let (command, mut rest) = rest.split_once(' ').unwrap_or((rest, ""));
+        if command.is_empty() {
+            return None;
+        }
+
+        let mut params = Vec::new();
+        loop {
+            rest = rest.trim_start_matches(' ');
+            if rest.is_empty() {
+                break;
+            }
+            if let Some(trailing) = rest.strip_prefix(':') {
+                params.push(trailing);
+                break;
+            }
+            match rest.split_once(' ') {
+                Some((param, value)) => {
+                    params.push(param);
+                    rest = value;
+                }
+                None => {
+                    params.push(rest);
+                    break;
+                }
+            }
+        }
+
+        Some(Line {
+            prefix,
+            command,
+            params,
+        })
+    }
+
+    /// Nick of a `nick!user@host` prefix.
+    fn nick(&self) -> Option<&'a str> {
+        let prefix = self.prefix?;
+        Some(prefix.split(['!', '@']).next().unwrap_or(prefix))
+    }
+
+    fn param(&self, index: usize) -> &'a str {
+        self.params.get(index).copied().unwrap_or_default()
+    }
+}
+
+/// A message or event to be stored under `channel`.
+struct Event {
+    channel: String,
+    kind: MessageKind,
+    author: String,
+    body: String,
+}
+
+/// State of a single connection.
+struct Session<'a> {
+    config: &'a Irc,
+    nick: String,
+    /// Configured channel names by their lowercase form.
+    channels: HashMap<String, &'a str>,
+    /// Lowercase nicks in each joined channel, so quits and nick changes,
+    /// which don't name a channel, are stored in the right ones.
+    members: HashMap<String, HashSet<String>>,
+}
+
+impl<'a> Session<'a> {
+    fn new(config: &'a Irc) -> Self {
+        Self {
+            config,
+            nick: config.nick.clone(),
+            channels: config
+                .channels
+                .iter()
+                .map(|channel| (channel.to_lowercase(), channel.as_str()))
+                .collect(),
+            members: HashMap::new(),
+        }
+    }
+
+    fn register(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Some(password) = &self.config.password {
+            lines.push(format!("PASS :{}", password));
+        }
+        lines.push(format!("NICK {}", self.nick));
+        lines.push(format!("USER {} 0 * :{}", self.config.username, self.config.realname));
+        lines
+    }
+
+    /// Configured name of `channel`, if it is one of ours.
+    fn channel(&self, channel: &str) -> Option<String> {
+        self.channels.get(&channel.to_lowercase()).map(|name| name.to_string())
+    }
+
+    /// Channels `nick` is known to be in.
+    fn channels_of(&self, nick: &str) -> Vec<String> {
+        let nick = nick.to_lowercase();
+        self.members
+            .iter()
+            .filter(|(_, members)| members.contains(&nick))
+            .filter_map(|(channel, _)| self.channel(channel))
+            .collect()
+    }
+
+    /// Handles a line from the server, returning lines to send back and
+    /// events to store.
+    fn handle(&mut self, line: &Line) -> (Vec<String>, Vec<Event>) {
+        let mut replies = Vec::new();
+        let mut events = Vec::new();
+        let nick = line.nick().unwrap_or_default();
+        let event = |channel: String, kind, author: &str, body: &str| Event {
+            channel,
+            kind,
+            author: author.to_owned(),
+            body: body.to_owned(),
+        };
+
+        match line.command {
+            "PING" => replies.push(format!("PONG :{}", line.param(0))),
+            // RPL_WELCOME
+            "001" => {
+                self.nick = line.param(0).to_owned();
+                if !self.config.channels.is_empty() {
+                    replies.push(format!("JOIN {}", self.config.channels.join(",")));
+                }
+            }
+            // ERR_NICKNAMEINUSE, only sent before registration completes
+            "433" => {
+                self.nick.push('_');
+                replies.push(format!("NICK {}", self.nick));
+            }
+            // RPL_NAMREPLY
+            "353" => {
+                let members = self.members.entry(line.param(2).to_lowercase()).or_default();
+                for name in line.param(3).split(' ').filter(|name| !name.is_empty()) {
+                    let name = name.trim_start_matches(['~', '&', '@', '%', '+']);
+                    members.insert(name.to_lowercase());
+                }
+            }
+            "PRIVMSG" => {
+                if let Some(channel) = self.channel(line.param(0)) {
+                    let text = line.param(1);
+                    if let Some(action) = text.strip_prefix("\x01ACTION ") {
+                        let action = action.trim_end_matches('\x01');
+                        events.push(event(channel, MessageKind::Action, nick, action));
+                    } else if !text.starts_with('\x01') {
+                        events.push(event(channel, MessageKind::Message, nick, text));
+                    }
+                }
+            }
+            "JOIN" => {
+                if let Some(channel) = self.channel(line.param(0)) {
+                    let members = self.members.entry(channel.to_lowercase()).or_default();
+                    if nick.eq_ignore_ascii_case(&self.nick) {
+                        members.clear();
+                    }
+                    members.insert(nick.to_lowercase());
+                    events.push(event(channel, MessageKind::Join, nick, ""));
+                }
+            }
+            "PART" => {
+                if let Some(channel) = self.channel(line.param(0)) {
+                    if let Some(members) = self.members.get_mut(&channel.to_lowercase()) {
+                        members.remove(&nick.to_lowercase());
+                    }
+                    events.push(event(channel, MessageKind::Part, nick, line.param(1)));
+                }
+            }
+            "KICK" => {
+                if let Some(channel) = self.channel(line.param(0)) {
+                    let kicked = line.param(1);
+                    if let Some(members) = self.members.get_mut(&channel.to_lowercase()) {
+                        members.remove(&kicked.to_lowercase());
+                    }
+                    if kicked.eq_ignore_ascii_case(&self.nick) {
+                        replies.push(format!("JOIN {}", channel));
+                    }
+                    events.push(event(channel, MessageKind::Part, kicked, line.param(2)));
+                }
+            }
+            "QUIT" => {
+                for channel in self.channels_of(nick) {
+                    if let Some(members) = self.members.get_mut(&channel.to_lowercase()) {
+                        members.remove(&nick.to_lowercase());
+                    }
+                    events.push(event(channel, MessageKind::Quit, nick, line.param(0)));
+                }
+            }
+            "NICK" => {
+                let new_nick = line.param(0);
+                for channel in self.channels_of(nick) {
+                    if let Some(members) = self.members.get_mut(&channel.to_lowercase()) {
+                        members.remove(&nick.to_lowercase());
+                        members.insert(new_nick.to_lowercase());
+                    }
+                    events.push(event(channel, MessageKind::Nick, nick, new_nick));
+                }
+                if nick.eq_ignore_ascii_case(&self.nick) {
+                    self.nick = new_nick.to_owned();
+                }
+            }
+            "TOPIC" => {
+                if let Some(channel) = self.channel(line.param(0)) {
+                    events.push(event(channel, MessageKind::Topic, nick, line.param(1)));
+                }
+            }
+            _ => {}
+        }
+
+        (replies, events)
+    }
+}
+
+async fn connect(config: &Irc) -> Result<Box<dyn Stream>> {
+    let tcp = TcpStream::connect((config.server.as_str(), config.port)).await?;
+    if !config.tls {
+        return Ok(Box::new(tcp));
+    }
+
+    let mut roots = RootCertStore::empty();
+    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+        OwnedTrustAnchor::from_subject_spki_name_constraints(
+            anchor.subject,
+            anchor.spki,
+            anchor.name_constraints,
+        )
+    }));
+    let tls = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    let name = ServerName::try_from(config.server.as_str())?;
+    let stream = TlsConnector::from(Arc::new(tls)).connect(name, tcp).await?;
+    Ok(Box::new(stream))
+}
+
+async fn send(writer: &mut (impl AsyncWrite + Unpin), line: &str) -> Result<()> {
+    writer.write_all(format!("{}\r\n", line).as_bytes()).await?;
+    Ok(())
+}
+
+/// Runs one connection until it is closed or fails. `delay` is reset once
+/// the server accepts the registration.
+async fn session(db: &Pool<Postgres>, config: &Irc, delay: &mut Duration) -> Result<()> {
+    let stream = connect(config)
+        .await
+        .with_context(|| format!("failed to connect to {}:{}", config.server, config.port))?;
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader);
+    let mut session = Session::new(config);
+
+    for line in session.register() {
+        send(&mut writer, &line).await?;
+    }
+
+    let mut buffer = Vec::new();
+    let mut pinged = false;
+    loop {
+        buffer.clear();
+        let mut line_reader = (&mut reader).take(MAX_LINE_LENGTH as u64);
+        match tokio::time::timeout(IDLE_TIMEOUT, line_reader.read_until(b'\n', &mut buffer)).await {
+            Err(_) if !pinged => {
+                send(&mut writer, "PING :sprout").await?;
+                pinged = true;
+                continue;
+            }
+            Err(_) => bail!("connection timed out"),
+            Ok(Ok(0)) => bail!("connection closed"),
+            Ok(result) => result?,
+        };
+        pinged = false;
+
+        if buffer.len() == MAX_LINE_LENGTH && !buffer.ends_with(b"\n") {
+            bail!("line longer than {} bytes", MAX_LINE_LENGTH);
+        }
+
+        let text = String::from_utf8_lossy(&buffer);
+        let line = match Line::parse(&text) {
+            Some(v) => v,
+            None => continue,
+        };
+
+        match line.command {
+            "001" => *delay = Duration::from_secs(config.reconnect_delay),
+            "ERROR" => bail!("closed by server: {}", line.param(0)),
+            _ => {}
+        }
+
+        let (replies, events) = session.handle(&line);
+        for reply in replies {
+            send(&mut writer, &reply).await?;
+        }
+
+        let time = Utc::now().naive_utc();
+        for event in events {
+            let result = import::insert_message(
+                db.clone(),
+                &event.channel,
+                config.timezone,
+                time,
+                event.kind,
+                &event.author,
+                &event.body,
+            )
+            .await;
+
+            if let Err(err) = result {
+                eprintln!("irc: failed to store message in {}: {:#}", event.channel, err);
+            }
+        }
+    }
+}
+
+/// Stays connected to the configured IRC server, reconnecting with backoff,
+/// and stores messages and events of its channels.
+pub async fn run(db: Pool<Postgres>, config: Irc) {
+    let mut delay = Duration::from_secs(config.reconnect_delay);
+
+    loop {
+        if let Err(err) = session(&db, &config, &mut delay).await {
+            eprintln!("irc: {:#}", err);
+        }
+
+        println!("irc: reconnecting in {}s", delay.as_secs());
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    fn config() -> Irc {
+        Irc {
+            server: String::from("127.0.0.1"),
+            tls: false,
+            channels: vec![String::from("#Test"), String::from("#other")],
+            timezone: chrono_tz::UTC,
+            reconnect_delay: 0,
+            ..Irc::default()
+        }
+    }
+
+    /// Channel, kind, author and body of an event.
+    type Stored = (String, MessageKind, String, String);
+
+    /// Replies and events of `session` for the raw `line`.
+    fn handle(session: &mut Session, line: &str) -> (Vec<String>, Vec<Stored>) {
+        let (replies, events) = session.handle(&Line::parse(line).unwrap());
+        let events = events
+            .into_iter()
+            .map(|e| (e.channel, e.kind, e.author, e.body))
+            .collect();
+        (replies, events)
+    }
+
+    fn event(channel: &str, kind: MessageKind, author: &str, body: &str) -> Stored {
+        (channel.to_owned(), kind, author.to_owned(), body.to_owned())
+    }
+
+    #[test]
+    fn parses_lines() {
+        let line = Line::parse(":nick!user@host PRIVMSG #chan :hello :world\r\n").unwrap();
+        assert_eq!(line.prefix, Some("nick!user@host"));
+        assert_eq!(line.nick(), Some("nick"));
+        assert_eq!(line.command, "PRIVMSG");
+        assert_eq!(line.params, vec!["#chan", "hello :world"]);
+
+        let line = Line::parse("@time=2023-01-01T00:00:00Z;msgid=1 :irc.example 001 me :Welcome").unwrap();
+        assert_eq!(line.prefix, Some("irc.example"));
+        assert_eq!(line.nick(), Some("irc.example"));
+        assert_eq!(line.command, "001");
+        assert_eq!(line.params, vec!["me", "Welcome"]);
+
+        let line = Line::parse("PING :abc def\n").unwrap();
+        assert_eq!(line.prefix, None);
+        assert_eq!(line.nick(), None);
+        assert_eq!(line.params, vec!["abc def"]);
+
+        let line = Line::parse(":srv MODE  #chan   +o nick").unwrap();
+        assert_eq!(line.params, vec!["#chan", "+o", "nick"]);
+
+        let line = Line::parse(":a PRIVMSG #chan :").unwrap();
+        assert_eq!(line.params, vec!["#chan", ""]);
+        assert_eq!(line.param(5), "");
+
+        let line = Line::parse("QUIT").unwrap();
+        assert_eq!(line.command, "QUIT");
+        assert!(line.params.is_empty());
+
+        for line in ["", "\r\n", ":prefix", ":prefix ", "@tags", "@tags :prefix "] {
+            assert!(Line::parse(line).is_none(), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn registers_and_joins() {
+        let mut config = config();
+        config.password = Some(String::from("secret"));
+        let mut session = Session::new(&config);
+
+        assert_eq!(
+            session.register(),
+            vec!["PASS :secret", "NICK sprout", "USER sprout 0 * :Sprout IRC logger"]
+        );
+
+        let (replies, _) = handle(&mut session, ":srv 433 * sprout :Nickname is already in use");
+        assert_eq!(replies, vec!["NICK sprout_"]);
+
+        let (replies, _) = handle(&mut session, ":srv 001 sprout_ :Welcome");
+        assert_eq!(replies, vec!["JOIN #Test,#other"]);
+        assert_eq!(session.nick, "sprout_");
+
+        let (replies, _) = handle(&mut session, "PING :irc.example");
+        assert_eq!(replies, vec!["PONG :irc.example"]);
+    }
+
+    #[test]
+    fn stores_messages_and_actions() {
+        let config = config();
+        let mut session = Session::new(&config);
+
+        assert_eq!(
+            handle(&mut session, ":amy!a@h PRIVMSG #test :hello there").1,
+            vec![event("#Test", MessageKind::Message, "amy", "hello there")]
+        );
+        assert_eq!(
+            handle(&mut session, ":amy!a@h PRIVMSG #Test :\x01ACTION waves\x01").1,
+            vec![event("#Test", MessageKind::Action, "amy", "waves")]
+        );
+        // other CTCP, private messages and channels we're not in
+        assert!(handle(&mut session, ":amy!a@h PRIVMSG #Test :\x01VERSION\x01").1.is_empty());
+        assert!(handle(&mut session, ":amy!a@h PRIVMSG sprout :psst").1.is_empty());
+        assert!(handle(&mut session, ":amy!a@h PRIVMSG #elsewhere :hi").1.is_empty());
+        assert!(handle(&mut session, ":amy!a@h NOTICE #Test :notice").1.is_empty());
+    }
+
+    #[test]
+    fn tracks_channel_events() {
+        let config = config();
+        let mut session = Session::new(&config);
+        handle(&mut session, ":srv 001 sprout :Welcome");
+
+        assert_eq!(
+            handle(&mut session, ":sprout!s@h JOIN #Test").1,
+            vec![event("#Test", MessageKind::Join, "sprout", "")]
+        );
+        handle(&mut session, ":srv 353 sprout = #test :sprout @Amy +bob ~carol");
+        handle(&mut session, ":sprout!s@h JOIN :#other");
+        handle(&mut session, ":srv 353 sprout = #other :sprout amy");
+
+        assert_eq!(
+            handle(&mut session, ":dave!d@h JOIN #test").1,
+            vec![event("#Test", MessageKind::Join, "dave", "")]
+        );
+        assert_eq!(
+            handle(&mut session, ":bob!b@h PART #test :bye").1,
+            vec![event("#Test", MessageKind::Part, "bob", "bye")]
+        );
+        // bob left, so his quit is stored nowhere
+        assert!(handle(&mut session, ":bob!b@h QUIT :gone").1.is_empty());
+
+        assert_eq!(
+            handle(&mut session, ":srv TOPIC #test :new topic").1,
+            vec![event("#Test", MessageKind::Topic, "srv", "new topic")]
+        );
+
+        // amy is in both channels
+        let (_, mut events) = handle(&mut session, ":amy!a@h NICK :amelia");
+        events.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            events,
+            vec![
+                event("#Test", MessageKind::Nick, "amy", "amelia"),
+                event("#other", MessageKind::Nick, "amy", "amelia"),
+            ]
+        );
+        let (_, mut events) = handle(&mut session, ":Amelia!a@h QUIT :Quit: bye");
+        events.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            events,
+            vec![
+                event("#Test", MessageKind::Quit, "Amelia", "Quit: bye"),
+                event("#other", MessageKind::Quit, "Amelia", "Quit: bye"),
+            ]
+        );
+        assert!(handle(&mut session, ":amy!a@h QUIT :again").1.is_empty());
+
+        assert_eq!(
+            handle(&mut session, ":op!o@h KICK #test carol :spam").1,
+            vec![event("#Test", MessageKind::Part, "carol", "spam")]
+        );
+        assert!(handle(&mut session, ":carol!c@h QUIT :x").1.is_empty());
+
+        let (replies, events) = handle(&mut session, ":op!o@h KICK #test sprout :out");
+        assert_eq!(replies, vec!["JOIN #Test"]);
+        assert_eq!(events, vec![event("#Test", MessageKind::Part, "sprout", "out")]);
+
+        // our own nick change is followed
+        handle(&mut session, ":sprout!s@h NICK sprout2");
+        assert_eq!(session.nick, "sprout2");
+    }
+
+    /// A client connection accepted by the stand-in server.
+    struct Client {
+        reader: BufReader<OwnedReadHalf>,
+        writer: OwnedWriteHalf,
+    }
+
+    impl Client {
+        async fn accept(listener: &TcpListener) -> Client {
+            let accept = tokio::time::timeout(Duration::from_secs(10), listener.accept());
+            let (stream, _) = accept.await.unwrap().unwrap();
+            let (reader, writer) = stream.into_split();
+            Client {
+                reader: BufReader::new(reader),
+                writer,
+            }
+        }
+
+        /// Next line from the client, `None` once it closed the connection.
+        async fn read(&mut self) -> Option<String> {
+            let mut line = String::new();
+            let read = tokio::time::timeout(Duration::from_secs(10), self.reader.read_line(&mut line));
+            match read.await.unwrap().unwrap() {
+                0 => None,
+                _ => Some(line.trim_end().to_owned()),
+            }
+        }
+
+        async fn send(&mut self, line: &str) {
+            self.writer.write_all(format!("{}\r\n", line).as_bytes()).await.unwrap();
+        }
+
+        /// Registration as the client sends it, answered with a welcome.
+        async fn register(&mut self) {
+            assert_eq!(self.read().await.as_deref(), Some("NICK sprout"));
+            assert_eq!(
+                self.read().await.as_deref(),
+                Some("USER sprout 0 * :Sprout IRC logger")
+            );
+            self.send(":srv 001 sprout :Welcome").await;
+            assert_eq!(self.read().await.as_deref(), Some("JOIN #Test,#other"));
+        }
+
+        /// Waits until the client handled every line sent before.
+        async fn sync(&mut self, token: &str) {
+            self.send(&format!("PING :{}", token)).await;
+            assert_eq!(self.read().await, Some(format!("PONG :{}", token)));
+        }
+    }
+
+    async fn bodies(db: &Pool<Postgres>) -> Vec<(String, String)> {
+        sqlx::query_as("SELECT msg_author, msg_body FROM messages WHERE msg_channel = '#Test' ORDER BY msg_id")
+            .fetch_all(db)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test]
+    async fn stays_connected(db: Pool<Postgres>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = Irc {
+            port: listener.local_addr().unwrap().port(),
+            ..config()
+        };
+        let client = tokio::spawn(run(db.clone(), config));
+
+        let mut server = Client::accept(&listener).await;
+        server.register().await;
+        server.send(":amy!a@h PRIVMSG #test :first").await;
+        server.sync("one").await;
+        assert_eq!(bodies(&db).await, vec![("amy".to_owned(), "first".to_owned())]);
+
+        // reconnects after the server closes the connection
+        drop(server);
+        let mut server = Client::accept(&listener).await;
+        server.register().await;
+        server.send(":amy!a@h PRIVMSG #test :second").await;
+        server.sync("two").await;
+        assert_eq!(bodies(&db).await.len(), 2);
+
+        // and after an overlong line, without storing it
+        let long = format!(":amy!a@h PRIVMSG #test :{}", "x".repeat(MAX_LINE_LENGTH));
+        server.writer.write_all(long.as_bytes()).await.unwrap();
+        assert_eq!(server.read().await, None);
+
+        let mut server = Client::accept(&listener).await;
+        server.register().await;
+        let tagged = format!("@{} :amy!a@h PRIVMSG #test :tagged", "t".repeat(8000));
+        server.send(&tagged).await;
+        server.sync("three").await;
+        assert_eq!(bodies(&db).await.len(), 3);
+
+        client.abort();
+    }
+}
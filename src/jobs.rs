@@ -0,0 +1,127 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use serde::Serialize;
+
+/// Finished jobs kept around for `/logs/import/status`.
+const KEEP_FINISHED: usize = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Running,
+    Finished,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct JobStatus {
+    pub id: u64,
+    pub source: String,
+    pub state: JobState,
+    /// Day being imported, or the last one once the job has stopped.
+    pub current_date: Option<NaiveDate>,
+    pub days_done: i64,
+    pub days_remaining: i64,
+    pub rows: u64,
+    pub errors: Vec<String>,
+    /// Days that still failed after retries, see `import::failures`.
+    pub failed_days: Vec<NaiveDate>,
+    pub started_at: NaiveDateTime,
+    pub finished_at: Option<NaiveDateTime>,
+}
+
+/// An import running in the background. Cancelling takes effect between days,
+/// so a day is never left half imported. A job with days that failed ends up
+/// `Failed`, even though the other days were imported.
+pub struct ImportJob {
+    status: Mutex<JobStatus>,
+    cancelled: AtomicBool,
+}
+
+impl ImportJob {
+    pub fn status(&self) -> JobStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    pub fn update(&self, f: impl FnOnce(&mut JobStatus)) {
+        f(&mut self.status.lock().unwrap());
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Marks the job as stopped in `state`.
+    pub fn finish(&self, state: JobState) {
+        self.update(|status| {
+            status.state = state;
+            status.finished_at = Some(Utc::now().naive_utc());
+        });
+    }
+}
+
+#[derive(Default)]
+pub struct Jobs {
+    next_id: AtomicU64,
+    jobs: Mutex<BTreeMap<u64, Arc<ImportJob>>>,
+}
+
+impl Jobs {
+    /// Registers a running job importing `days` days of `source`.
+    pub fn start(&self, source: &str, days: i64) -> Arc<ImportJob> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let job = Arc::new(ImportJob {
+            status: Mutex::new(JobStatus {
+                id,
+                source: source.to_owned(),
+                state: JobState::Running,
+                current_date: None,
+                days_done: 0,
+                days_remaining: days,
+                rows: 0,
+                errors: vec![],
+                failed_days: vec![],
+                started_at: Utc::now().naive_utc(),
+                finished_at: None,
+            }),
+            cancelled: AtomicBool::new(false),
+        });
+
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.insert(id, job.clone());
+
+        let finished: Vec<u64> = jobs
+            .iter()
+            .filter(|(_, job)| job.status().state != JobState::Running)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in finished.iter().take(finished.len().saturating_sub(KEEP_FINISHED)) {
+            jobs.remove(id);
+        }
+
+        job
+    }
+
+    pub fn get(&self, id: u64) -> Option<Arc<ImportJob>> {
+        self.jobs.lock().unwrap().get(&id).cloned()
+    }
+
+    /// Statuses of all known jobs, newest first.
+    pub fn statuses(&self) -> Vec<JobStatus> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .values()
+            .rev()
+            .map(|job| job.status())
+            .collect()
+    }
+}
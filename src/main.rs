@@ -1,30 +1,45 @@
 #![allow(dead_code)]
 
-use std::{ collections::{BTreeMap, HashMap}, convert::Infallible, env, error::Error, path::Path, str::FromStr, sync::Arc, time::Instant };
+use std::{ collections::{BTreeMap, HashMap}, convert::Infallible, env, error::Error, path::Path, str::FromStr, sync::Arc };
 
+mod alerts;
+mod auth;
+mod bots;
 mod config;
 mod error;
+mod export;
+mod formats;
 mod import;
+mod irc;
+mod jobs;
 mod models;
 mod query;
+mod stream;
+mod timezone;
 
-use chrono::{NaiveDate, Utc};
+use chrono::{Datelike, NaiveDate};
+use chrono_tz::Tz;
 use config::Config;
 use error::{AnyhowError, ErrorResponse};
+use futures::{FutureExt, StreamExt};
 use handlebars::Handlebars;
-use query::{search, Expr};
-use reqwest::Client as WebClient;
+use jobs::Jobs;
+use query::{search, Cursor, Expr, FacetResult, Facets, Page, ParseError, Plan, QueryOptions, SearchPage};
 use serde::Serialize;
+use stream::Broadcaster;
 use serde_json::json;
-use sqlx::{postgres::PgListener, Pool, Postgres};
+use sqlx::{migrate::Migrator, postgres::PgListener, Pool, Postgres};
 use tokio::sync::Mutex;
 use warp::{
     reject::Rejection,
     reply::{self, WithHeader, WithStatus},
-    Filter,
+    Filter, Reply,
 };
 
-use crate::models::Message;
+use crate::models::{Message, MessageKind};
+
+/// Schema migrations from `migrations/`, embedded at build time.
+static MIGRATOR: Migrator = sqlx::migrate!();
 
 enum QueryOutput {
     PlainText,
@@ -60,6 +75,49 @@ fn with_template_engine(
     warp::any().map(move || hb.clone())
 }
 
+fn with_config(
+    config: Arc<Config>,
+) -> impl Filter<Extract = (Arc<Config>,), Error = Infallible> + Clone {
+    warp::any().map(move || config.clone())
+}
+
+/// Zone from the `tz=` parameter, or the configured one.
+fn request_timezone(params: &HashMap<String, String>, config: &Config) -> Result<Tz, Rejection> {
+    match params.get("tz") {
+        Some(name) => name.parse().map_err(|_| {
+            warp::reject::custom(ErrorResponse {
+                message: format!("Unknown time zone: {}", name),
+                status_code: warp::http::StatusCode::BAD_REQUEST,
+            })
+        }),
+        None => Ok(config.timezone),
+    }
+}
+
+fn query_options(config: &Config, timezone: Tz) -> QueryOptions {
+    QueryOptions {
+        timezone,
+        bots: config.bot_list.clone(),
+    }
+}
+
+/// Messages of a calendar day in `tz`, with times shifted to that zone.
+async fn fetch_day(pool: &Pool<Postgres>, date: NaiveDate, tz: Tz) -> Result<Vec<Message>, Rejection> {
+    let (start, end) = timezone::day_bounds(tz, date);
+
+    let result = sqlx::query_as!(
+        Message,
+        "SELECT msg_id AS id, msg_body AS body, msg_author AS author, msg_timestamp AS time, msg_offset AS offset, msg_kind AS \"kind: MessageKind\", NULL::text AS highlight FROM messages WHERE msg_timestamp >= $1 AND msg_timestamp < $2 ORDER BY msg_timestamp, msg_offset",
+        start,
+        end
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|_| warp::reject::custom(error::DatabaseError))?;
+
+    Ok(result.into_iter().map(|m| m.in_timezone(tz)).collect())
+}
+
 fn fmt_database_output(input: Vec<Message>, format: QueryOutput) -> WithStatus<WithHeader<String>> {
     match format {
         QueryOutput::PlainText => {
@@ -91,31 +149,35 @@ fn fmt_database_output(input: Vec<Message>, format: QueryOutput) -> WithStatus<W
 }
 
 async fn get_log_dates(
+    params: HashMap<String, String>,
     pool: Pool<Postgres>,
-    dates: Arc<Mutex<Vec<NaiveDate>>>,
+    dates: Arc<Mutex<HashMap<String, Vec<NaiveDate>>>>,
+    config: Arc<Config>,
 ) -> Result<impl warp::Reply, Rejection> {
+    let tz = request_timezone(&params, &config)?;
     let mut lock = dates.lock().await;
 
-    let res = if lock.len() == 0 {
+    let res = if let Some(cached) = lock.get(tz.name()) {
+        cached.to_vec()
+    } else {
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|_| warp::reject::custom(error::DatabaseError))?;
+        query::set_timezone(&mut tx, tz)
+            .await
+            .map_err(|_| warp::reject::custom(error::DatabaseError))?;
+
         let result = sqlx::query!(
-            "SELECT DISTINCT DATE(date_trunc('day', msg_timestamp)) AS dates FROM messages ORDER BY dates DESC"
+            "SELECT DISTINCT DATE(msg_timestamp AT TIME ZONE 'UTC') AS dates FROM messages ORDER BY dates DESC"
         )
-        .fetch_all(&pool)
+        .fetch_all(&mut *tx)
         .await
         .map_err(|_| warp::reject::custom(error::DatabaseError))?;
 
-        let res: Vec<NaiveDate> = result
-            .iter()
-            .filter(|a| a.dates.is_some())
-            .map(|f| f.dates.unwrap())
-            .collect();
-
-        for date in res.iter() {
-            lock.push(date.clone())
-        }
+        let res: Vec<NaiveDate> = result.iter().filter_map(|f| f.dates).collect();
+        lock.insert(tz.name().to_owned(), res.clone());
         res
-    } else {
-        lock.to_vec()
     };
 
     Ok(reply::with_status(
@@ -132,69 +194,336 @@ async fn get_log_by_date(
     path: String,
     params: HashMap<String, String>,
     pool: Pool<Postgres>,
+    config: Arc<Config>,
 ) -> Result<impl warp::Reply, Rejection> {
     let naive_date = NaiveDate::parse_from_str(&path, "%Y-%m-%d");
     let format = query_output_from_string(params.get("format").unwrap_or(&String::new()))
         .unwrap_or(QueryOutput::Json);
+    let tz = request_timezone(&params, &config)?;
 
     if let Ok(date) = naive_date {
-        let result = sqlx::query_as!(
-            Message,
-            "SELECT msg_id AS id, msg_body AS body, msg_author AS author, msg_timestamp AS time, msg_offset AS offset FROM messages WHERE DATE(msg_timestamp) = $1",
-            date
-        )
-        .fetch_all(&pool)
-        .await.map_err(|_| warp::reject::custom(error::DatabaseError))?;
-
+        let result = fetch_day(&pool, date, tz).await?;
         Ok(fmt_database_output(result, format))
     } else {
         Err(warp::reject::not_found())
     }
 }
 
+/// Server-sent events of messages as they are stored. Clients resume from
+/// the `Last-Event-ID` header, or start after the message ID in `after=`.
+/// With `q=`, only messages matching the search query are sent.
+async fn stream_logs(
+    params: HashMap<String, String>,
+    last_event_id: Option<String>,
+    pool: Pool<Postgres>,
+    config: Arc<Config>,
+    broadcaster: Broadcaster,
+) -> Result<impl warp::Reply, Rejection> {
+    let (filter, tz) = if params.contains_key("q") {
+        let (expr, tz) = parse_query(&params, &config)?;
+        let mut conn = pool
+            .acquire()
+            .await
+            .map_err(|_| warp::reject::custom(error::DatabaseError))?;
+        let filter = query::MessageFilter::new(&mut conn, expr, query_options(&config, tz))
+            .await
+            .map_err(bad_request)?;
+        (Some(filter), tz)
+    } else {
+        (None, request_timezone(&params, &config)?)
+    };
+    let after = match last_event_id.as_ref().or(params.get("after")) {
+        Some(id) => Some(
+            id.parse::<i32>()
+                .map_err(|_| bad_request(anyhow::anyhow!("Invalid message ID: {}", id)))?,
+        ),
+        None => None,
+    };
+
+    let events = stream::events(&pool, &broadcaster, after, filter, tz, config.bot_list.clone())
+        .await
+        .map_err(AnyhowError)?;
+
+    Ok(warp::sse::reply(warp::sse::keep_alive().stream(events)))
+}
+
 async fn get_today_logs(
     params: HashMap<String, String>,
     pool: Pool<Postgres>,
+    config: Arc<Config>,
 ) -> Result<impl warp::Reply, Rejection> {
-    let current_datetime = Utc::now();
-    let date: NaiveDate = current_datetime.naive_utc().into();
     let format = query_output_from_string(params.get("format").unwrap_or(&String::new()))
         .unwrap_or(QueryOutput::Json);
+    let tz = request_timezone(&params, &config)?;
 
-    let result = sqlx::query_as!(
-        Message,
-        "SELECT msg_id AS id, msg_body AS body, msg_author AS author, msg_timestamp AS time, msg_offset AS offset FROM messages WHERE DATE(msg_timestamp) = $1",
-        date
+    let result = fetch_day(&pool, timezone::today(tz), tz).await?;
+    Ok(fmt_database_output(result, format))
+}
+
+fn json_reply<T: Serialize>(value: &T) -> WithStatus<WithHeader<String>> {
+    reply::with_status(
+        reply::with_header(
+            serde_json::to_string(value).unwrap(),
+            "Content-Type",
+            "application/json; charset=utf-8",
+        ),
+        warp::http::StatusCode::OK,
     )
-    .fetch_all(&pool)
-    .await.map_err(|_| warp::reject::custom(error::DatabaseError))?;
+}
 
-    Ok(fmt_database_output(result, format))
+fn bad_request(err: anyhow::Error) -> Rejection {
+    warp::reject::custom(ErrorResponse {
+        message: err.to_string(),
+        status_code: warp::http::StatusCode::BAD_REQUEST,
+    })
+}
+
+/// Parses the `q=` parameter and resolves the time zone it should run in.
+fn parse_query(params: &HashMap<String, String>, config: &Config) -> Result<(Expr, Tz), Rejection> {
+    let query = params.get("q").ok_or_else(|| {
+        warp::reject::custom(ErrorResponse {
+            message: String::from("Search parameter is missing in URL"),
+            status_code: warp::http::StatusCode::BAD_REQUEST,
+        })
+    })?;
+
+    let expr = Expr::parse(query).map_err(|error| {
+        warp::reject::custom(error::QueryParseError {
+            query: query.clone(),
+            error,
+        })
+    })?;
+
+    let tz = query::timezone(&expr, request_timezone(params, config)?).map_err(bad_request)?;
+    Ok((expr, tz))
+}
+
+/// Page selected with the `limit=` and `cursor=` parameters.
+fn search_page(params: &HashMap<String, String>, config: &Config) -> anyhow::Result<Page> {
+    let limit = match params.get("limit") {
+        Some(limit) => match limit.parse::<usize>() {
+            Ok(limit) if limit > 0 => limit.min(config.max_search_limit),
+            _ => anyhow::bail!("Invalid limit: {}", limit),
+        },
+        None => config.search_limit,
+    };
+
+    let cursor = params.get("cursor").map(|c| Cursor::decode(c)).transpose()?;
+    Ok(Page { limit, cursor })
+}
+
+/// `path` with the request parameters, some of them replaced (`Some`)
+/// or dropped (`None`).
+fn search_link(path: &str, params: &HashMap<String, String>, changes: &[(&str, Option<&str>)]) -> String {
+    let mut params: BTreeMap<&str, &str> = params
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str()))
+        .collect();
+
+    for (key, value) in changes {
+        match value {
+            Some(value) => params.insert(key, value),
+            None => params.remove(key),
+        };
+    }
+
+    format!("{}?{}", path, serde_urlencoded::to_string(params).unwrap())
+}
+
+/// Link to the same search, starting at `cursor`.
+fn page_url(path: &str, params: &HashMap<String, String>, cursor: &Cursor) -> String {
+    search_link(path, params, &[("cursor", Some(&cursor.encode()))])
+}
+
+/// Link to the first page of the search narrowed down with `name:value`.
+fn facet_url(path: &str, params: &HashMap<String, String>, name: &str, value: &str) -> String {
+    let query = format!(
+        "{} {}:{}",
+        params.get("q").map_or("", |q| q.as_str()),
+        name,
+        query::quote(value)
+    );
+    search_link(path, params, &[("q", Some(query.trim_start())), ("cursor", None)])
+}
+
+/// Whether to count total hits and facets, which reruns the whole search.
+/// They are counted for the first page unless `facets=false` is given, and
+/// for later pages only with `facets=true`.
+fn wants_facets(params: &HashMap<String, String>, page: &Page) -> anyhow::Result<bool> {
+    match params.get("facets").map(|f| f.as_str()) {
+        None => Ok(page.cursor.is_none()),
+        Some("true") => Ok(true),
+        Some("false") => Ok(false),
+        Some(value) => anyhow::bail!("Invalid facets: {}; 'true' or 'false' expected", value),
+    }
+}
+
+/// `1234567` as `1,234,567`.
+fn thousands(n: i64) -> String {
+    let digits = n.unsigned_abs().to_string();
+    let mut output = String::new();
+
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            output.push(',');
+        }
+        output.push(digit);
+    }
+
+    if n < 0 {
+        output.insert(0, '-');
+    }
+    output
+}
+
+fn plural(n: i64, one: &str, many: &str) -> String {
+    format!("{} {}", thousands(n), if n == 1 { one } else { many })
+}
+
+#[derive(Serialize)]
+struct SearchResponse {
+    messages: Vec<Message>,
+    next: Option<String>,
+    prev: Option<String>,
+    #[serde(flatten)]
+    facets: Option<FacetResult>,
 }
 
 async fn search_logs(
     params: HashMap<String, String>,
     pool: Pool<Postgres>,
+    config: Arc<Config>,
 ) -> Result<impl warp::Reply, Rejection> {
     let format = query_output_from_string(params.get("format").unwrap_or(&String::new()))
         .unwrap_or(QueryOutput::Json);
-    let query = params.get("q").unwrap();
 
-    let expr = Expr::parse(query).map_err(|_| {
-        warp::reject::custom(error::ErrorResponse {
-            message: String::from("Malformed query"),
-            status_code: warp::http::StatusCode::BAD_REQUEST,
-        })
-    })?;
+    let (expr, tz) = parse_query(&params, &config)?;
+    let page = search_page(&params, &config).map_err(bad_request)?;
 
-    let result = search(pool, expr).await.map_err(|err| {
-        warp::reject::custom(ErrorResponse {
-            message: err.to_string(),
-            status_code: warp::http::StatusCode::BAD_REQUEST,
+    let facets = if wants_facets(&params, &page).map_err(bad_request)? {
+        let mut conn = pool
+            .acquire()
+            .await
+            .map_err(|_| warp::reject::custom(error::DatabaseError))?;
+
+        let facets = query::facets(&mut conn, expr.clone(), query_options(&config, tz))
+            .await
+            .map_err(bad_request)?;
+        Some(facets)
+    } else {
+        None
+    };
+    let SearchPage {
+        messages,
+        next,
+        prev,
+    } = search(pool, expr, query_options(&config, tz), page)
+        .await
+        .map_err(bad_request)?;
+
+    let messages: Vec<Message> = messages.into_iter().map(|m| m.in_timezone(tz)).collect();
+    let next = next.map(|c| page_url("/logs/search", &params, &c));
+    let prev = prev.map(|c| page_url("/logs/search", &params, &c));
+
+    let reply = match format {
+        QueryOutput::Json => json_reply(&SearchResponse {
+            messages,
+            next,
+            prev,
+            facets,
         })
-    })?;
+        .into_response(),
+        QueryOutput::PlainText => {
+            let links: Vec<String> = [(&next, "next"), (&prev, "prev")]
+                .into_iter()
+                .filter_map(|(url, rel)| url.as_ref().map(|url| format!("<{}>; rel=\"{}\"", url, rel)))
+                .collect();
+
+            let mut reply = fmt_database_output(messages, format).into_response();
+            if let Some(facets) = &facets {
+                reply
+                    .headers_mut()
+                    .insert("X-Total-Count", facets.total.into());
+            }
+            if !links.is_empty() {
+                reply
+                    .headers_mut()
+                    .insert("Link", links.join(", ").parse().unwrap());
+            }
+            reply
+        }
+    };
 
-    Ok(fmt_database_output(result, format))
+    Ok(reply)
+}
+
+/// Stages of query normalization and the resulting SQL. `plan=true` adds
+/// `EXPLAIN` output, `plan=analyze` runs the query with `EXPLAIN ANALYZE`,
+/// which takes an admin token since it executes the search.
+async fn explain_search(
+    params: HashMap<String, String>,
+    authorization: Option<String>,
+    pool: Pool<Postgres>,
+    config: Arc<Config>,
+) -> Result<impl warp::Reply, Rejection> {
+    let (expr, tz) = parse_query(&params, &config)?;
+    let page = search_page(&params, &config).map_err(bad_request)?;
+    let plan = match params.get("plan").map(|p| p.as_str()) {
+        None | Some("false") => Plan::None,
+        Some("true") => Plan::Explain,
+        Some("analyze") => {
+            auth::check_admin(&config, authorization.as_deref())?;
+            Plan::Analyze
+        }
+        Some(plan) => {
+            return Err(bad_request(anyhow::anyhow!(
+                "Invalid plan: {} (either 'true', 'false' or 'analyze' expected)",
+                plan
+            )))
+        }
+    };
+
+    let result = query::explain(pool, expr, query_options(&config, tz), page, plan)
+        .await
+        .map_err(bad_request)?;
+
+    Ok(json_reply(&result))
+}
+
+async fn stats_count(
+    params: HashMap<String, String>,
+    pool: Pool<Postgres>,
+    config: Arc<Config>,
+) -> Result<impl warp::Reply, Rejection> {
+    let (expr, tz) = parse_query(&params, &config)?;
+    let mut conn = pool
+        .acquire()
+        .await
+        .map_err(|_| warp::reject::custom(error::DatabaseError))?;
+
+    let result = query::count(&mut conn, expr, query_options(&config, tz))
+        .await
+        .map_err(bad_request)?;
+
+    Ok(json_reply(&result))
+}
+
+async fn stats_top(
+    params: HashMap<String, String>,
+    pool: Pool<Postgres>,
+    config: Arc<Config>,
+) -> Result<impl warp::Reply, Rejection> {
+    let (expr, tz) = parse_query(&params, &config)?;
+    let mut conn = pool
+        .acquire()
+        .await
+        .map_err(|_| warp::reject::custom(error::DatabaseError))?;
+
+    let result = query::top(&mut conn, expr, query_options(&config, tz))
+        .await
+        .map_err(bad_request)?;
+
+    Ok(json_reply(&result))
 }
 
 fn html_error<E: ToString>(error: E) -> WithTemplate<serde_json::Value> {
@@ -204,10 +533,49 @@ fn html_error<E: ToString>(error: E) -> WithTemplate<serde_json::Value> {
     }
 }
 
+fn html_parse_error(query: &str, error: &ParseError) -> WithTemplate<serde_json::Value> {
+    WithTemplate {
+        name: "search.html",
+        value: json!({
+            "error": error.to_string(),
+            "error_query": query,
+            "error_caret": format!("{}^", " ".repeat(error.column - 1)),
+        }),
+    }
+}
+
+/// Facets of the HTML search page, each value linking to the narrowed search.
+fn facet_links(params: &HashMap<String, String>, facets: &Facets) -> serde_json::Value {
+    let groups = [
+        ("Authors", "author", &facets.authors),
+        ("Days", "date", &facets.days),
+        ("Channels", "channel", &facets.channels),
+    ];
+
+    groups
+        .iter()
+        .filter(|(_, _, values)| !values.is_empty())
+        .map(|(title, function, values)| {
+            json!({
+                "title": title,
+                "values": values
+                    .iter()
+                    .map(|facet| json!({
+                        "value": facet.value,
+                        "count": thousands(facet.count),
+                        "url": facet_url("/search", params, function, &facet.value),
+                    }))
+                    .collect::<Vec<_>>(),
+            })
+        })
+        .collect()
+}
+
 pub async fn view_search_as_html(
     params: HashMap<String, String>,
     hb: Arc<Handlebars<'_>>,
     pool: Pool<Postgres>,
+    config: Arc<Config>,
 ) -> Result<impl warp::Reply, Rejection> {
     let query = params.get("q");
     if query.is_none() {
@@ -222,71 +590,182 @@ pub async fn view_search_as_html(
         return Ok(render(html_error("Empty expression string"), hb.clone()));
     }
 
-    let expression = Expr::parse(query);
+    let expr = match Expr::parse(query) {
+        Ok(expr) => expr,
+        Err(err) => return Ok(render(html_parse_error(query, &err), hb.clone())),
+    };
 
-    match expression {
-        Ok(expr) => match search(pool, expr).await {
-            Ok(messages) => {
-                let mut message_groups: BTreeMap<NaiveDate, Vec<Message>> = BTreeMap::new();
+    let tz = match request_timezone(&params, &config)
+        .map_err(|_| anyhow::anyhow!("Unknown time zone"))
+        .and_then(|default| query::timezone(&expr, default))
+    {
+        Ok(tz) => tz,
+        Err(err) => return Ok(render(html_error(err), hb.clone())),
+    };
 
-                for message in &messages {
-                    let date = message.time.date();
+    let page = match search_page(&params, &config) {
+        Ok(page) => page,
+        Err(err) => return Ok(render(html_error(err), hb.clone())),
+    };
 
-                    message_groups
-                        .entry(date)
-                        .or_insert(vec![])
-                        .push(message.clone());
-                }
+    let facets = match wants_facets(&params, &page) {
+        Ok(true) => {
+            let mut conn = pool
+                .acquire()
+                .await
+                .map_err(|_| warp::reject::custom(error::DatabaseError))?;
 
-                let mut message_results: Vec<models::MessageResults> = message_groups
-                    .iter()
-                    .map(|(date, messages)| models::MessageResults {
-                        date: date.clone(),
-                        messages: messages
-                            .iter()
-                            .map(|x| models::MessageTemplate::from(x.clone()))
-                            .collect(),
-                    })
-                    .collect();
-
-                message_results.reverse();
-
-                let template = if !message_results.is_empty() {
-                    WithTemplate {
-                        name: "search.html",
-                        value: json!({ "messages": message_results }),
-                    }
-                } else {
-                    html_error("No results")
-                };
+            match query::facets(&mut conn, expr.clone(), query_options(&config, tz)).await {
+                Ok(facets) => Some(facets),
+                Err(err) => return Ok(render(html_error(err), hb.clone())),
+            }
+        }
+        Ok(false) => None,
+        Err(err) => return Ok(render(html_error(err), hb.clone())),
+    };
 
-                Ok(render(template, hb.clone()))
+    match search(pool, expr, query_options(&config, tz), page).await {
+        Ok(SearchPage {
+            messages,
+            next,
+            prev,
+        }) => {
+            let mut message_groups: BTreeMap<NaiveDate, Vec<Message>> = BTreeMap::new();
+
+            for message in messages {
+                let message = message.in_timezone(tz);
+
+                message_groups
+                    .entry(message.time.date())
+                    .or_default()
+                    .push(message);
             }
-            Err(err) => Ok(render(html_error(err), hb.clone())),
-        },
+
+            let mut message_results: Vec<models::MessageResults> = message_groups
+                .iter()
+                .map(|(date, messages)| models::MessageResults {
+                    date: *date,
+                    messages: messages
+                        .iter()
+                        .map(|x| models::MessageTemplate::from(x.clone()))
+                        .map(|m| m.with_bots(&config.bot_list))
+                        .collect(),
+                })
+                .collect();
+
+            message_results.reverse();
+
+            let template = if !message_results.is_empty() {
+                WithTemplate {
+                    name: "search.html",
+                    value: json!({
+                        "messages": message_results,
+                        "summary": facets.as_ref().map(|facets| format!(
+                            "{} across {}",
+                            plural(facets.total, "result", "results"),
+                            plural(facets.total_days, "day", "days")
+                        )),
+                        "facets": facets.as_ref().map(|facets| facet_links(&params, &facets.facets)),
+                        "next": next.map(|c| page_url("/search", &params, &c)),
+                        "prev": prev.map(|c| page_url("/search", &params, &c)),
+                    }),
+                }
+            } else {
+                html_error("No results")
+            };
+
+            Ok(render(template, hb.clone()))
+        }
         Err(err) => Ok(render(html_error(err), hb.clone())),
     }
 }
 
+pub async fn view_stats_as_html(
+    params: HashMap<String, String>,
+    hb: Arc<Handlebars<'_>>,
+    pool: Pool<Postgres>,
+    config: Arc<Config>,
+) -> Result<impl warp::Reply, Rejection> {
+    let in_stats = |template: WithTemplate<serde_json::Value>| WithTemplate {
+        name: "stats.html",
+        ..template
+    };
+
+    let query = match params.get("q") {
+        Some(query) if !query.is_empty() => query,
+        Some(_) => return Ok(render(in_stats(html_error("Empty expression string")), hb.clone())),
+        None => {
+            return Ok(render(
+                in_stats(html_error("Search parameter is missing in URL")),
+                hb.clone(),
+            ))
+        }
+    };
+
+    let expr = match Expr::parse(query) {
+        Ok(expr) => expr,
+        Err(err) => return Ok(render(in_stats(html_parse_error(query, &err)), hb.clone())),
+    };
+
+    let tz = match request_timezone(&params, &config)
+        .map_err(|_| anyhow::anyhow!("Unknown time zone"))
+        .and_then(|default| query::timezone(&expr, default))
+    {
+        Ok(tz) => tz,
+        Err(err) => return Ok(render(in_stats(html_error(err)), hb.clone())),
+    };
+
+    let mut conn = pool
+        .acquire()
+        .await
+        .map_err(|_| warp::reject::custom(error::DatabaseError))?;
+
+    let template = match query::top(&mut conn, expr, query_options(&config, tz)).await {
+        Ok(stats) => WithTemplate {
+            name: "stats.html",
+            value: json!({ "query": query, "stats": stats }),
+        },
+        Err(err) => in_stats(html_error(err)),
+    };
+
+    Ok(render(template, hb.clone()))
+}
+
 pub async fn view_log_as_html(
     path: String,
+    params: HashMap<String, String>,
     hb: Arc<Handlebars<'_>>,
     pool: Pool<Postgres>,
+    config: Arc<Config>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    let date = NaiveDate::parse_from_str(&path, "%Y-%m-%d").unwrap_or({
-        let current_datetime = Utc::now();
-        current_datetime.naive_utc().into()
-    });
+    let tz = match request_timezone(&params, &config) {
+        Ok(tz) => tz,
+        Err(_) => return Ok(render(html_error("Unknown time zone"), hb.clone())),
+    };
 
-    let result = sqlx::query_as!(
-        models::MessageTemplate,
-        "SELECT msg_id AS id, msg_body AS body, msg_author AS author, msg_timestamp::time AS time, msg_offset AS offset FROM messages WHERE DATE(msg_timestamp) = $1",
-        date
-    )
-    .fetch_all(&pool)
-    .await.map_err(|_| warp::reject::custom(error::DatabaseError))?;
+    let date = NaiveDate::parse_from_str(&path, "%Y-%m-%d").unwrap_or_else(|_| timezone::today(tz));
+
+    let result: Vec<models::MessageTemplate> = fetch_day(&pool, date, tz)
+        .await?
+        .into_iter()
+        .map(models::MessageTemplate::from)
+        .map(|m| m.with_bots(&config.bot_list))
+        .collect();
 
-    let template = if !result.is_empty() {
+    // today's log keeps growing, so the page follows `/logs/stream`
+    let template = if date == timezone::today(tz) {
+        let error = result
+            .is_empty()
+            .then(|| format!("No messages yet for date: {}", date));
+        WithTemplate {
+            name: "index.html",
+            value: json!({
+                "messages": result,
+                "error": error,
+                "live": { "date": date, "after": result.iter().map(|m| m.id).max() },
+            }),
+        }
+    } else if !result.is_empty() {
         WithTemplate {
             name: "index.html",
             value: json!({ "messages": result }),
@@ -298,79 +777,373 @@ pub async fn view_log_as_html(
     Ok(render(template, hb.clone()))
 }
 
-pub async fn import(params: HashMap<String, String>, db: Pool<Postgres>) -> Result<impl warp::Reply, warp::Rejection> {
-    let _guard = match import::LOCK.try_lock() {
-        Ok(v) => v,
-        Err(_) => {
-            return Err(warp::reject::custom(ErrorResponse {
-                message: "Import already running".to_string(),
-                status_code: warp::http::StatusCode::BAD_REQUEST,
-            }))
-        }
-    };
+/// Source given by `source=`, with its format overridden by `format=`.
+fn import_source(params: &HashMap<String, String>, config: &Config) -> Result<config::Source, Rejection> {
+    let source = params.get("source").map(|name| name.as_str());
+    let source = config.source(source).ok_or_else(|| {
+        warp::reject::custom(ErrorResponse {
+            message: format!("Unknown source: {}", source.unwrap_or_default()),
+            status_code: warp::http::StatusCode::BAD_REQUEST,
+        })
+    })?;
+    let mut source = source.clone();
+    if let Some(format) = params.get("format") {
+        source.format = format.clone();
+    }
+    formats::find(&source.format).map_err(bad_request)?;
+    Ok(source)
+}
 
-    let today = Utc::now().naive_utc();
-    let mut cut_offset = -1;
+fn lock_import() -> Result<tokio::sync::MutexGuard<'static, ()>, Rejection> {
+    import::LOCK.try_lock().map_err(|_| {
+        warp::reject::custom(ErrorResponse {
+            message: "Import already running".to_string(),
+            status_code: warp::http::StatusCode::BAD_REQUEST,
+        })
+    })
+}
 
+/// Runs `days` of `source` as a background job holding `guard`, the import
+/// lock, and replies with the job ID.
+fn start_import(
+    db: Pool<Postgres>,
+    jobs: &Jobs,
+    source: config::Source,
+    days: Vec<NaiveDate>,
+    cut_offset: i32,
+    guard: tokio::sync::MutexGuard<'static, ()>,
+) -> impl warp::Reply {
+    let job = jobs.start(&source.name, days.len() as i64);
+    let id = job.status().id;
+
+    let json = json!({
+        "id": id,
+        "source": source.name,
+        "start": days.first(),
+        "days": days.len(),
+        "status": format!("/logs/import/status?id={}", id),
+    });
+
+    // The lock is held until the job stops, not just for this request.
+    tokio::spawn(import::run(db, source, days, cut_offset, job).map(move |_| drop(guard)));
+
+    reply::with_status(
+        reply::with_header(
+            json.to_string(),
+            "Content-Type",
+            "application/json; charset=utf-8",
+        ),
+        warp::http::StatusCode::ACCEPTED,
+    )
+}
+
+/// Starts importing a source in the background, from `date=` or where the
+/// previous import stopped, and returns the job ID.
+pub async fn import(
+    params: HashMap<String, String>,
+    db: Pool<Postgres>,
+    config: Arc<Config>,
+    jobs: Arc<Jobs>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let source = import_source(&params, &config)?;
+    let guard = lock_import()?;
+
+    let mut cut_offset = -1;
 
     let long_lived_value = String::new();
     let date = params.get("date").unwrap_or(&long_lived_value);
 
     let start = if !date.is_empty() {
-        NaiveDate::from_str(&date).map_err(|op| AnyhowError(op.into()))?
+        NaiveDate::from_str(date).map_err(|op| bad_request(op.into()))?
     } else {
-        let (o, d) = import::get_latest_msg(db.clone())
+        let (o, d) = import::get_state(db.clone(), &source)
             .await
-            .map_err(|op| AnyhowError(op.into()))?
-            .ok_or(anyhow::anyhow!("cannot get start date"))
-            .map_err(|op| AnyhowError(op.into()))?;
+            .map_err(AnyhowError)?
+            .ok_or_else(|| bad_request(anyhow::anyhow!("cannot get start date")))?;
         cut_offset = o;
         d
     };
 
-    let web = WebClient::new();
-    let instant = Instant::now();
-    let mut date = start;
-    let mut count = 0;
+    let days = import::days_since(&source, start);
+    Ok(start_import(db, &jobs, source, days, cut_offset, guard))
+}
 
-    while date <= today.into() {
-        count += import::download_and_insert_logs(db.clone(), &web, date, cut_offset)
-            .await
-            .map_err(|op| AnyhowError(op.into()))?;
-        cut_offset = -1;
-        date = date.succ_opt().unwrap();
+/// Days of the source given by `source=` that failed to import.
+async fn import_failures(
+    params: HashMap<String, String>,
+    db: Pool<Postgres>,
+    config: Arc<Config>,
+) -> Result<impl warp::Reply, Rejection> {
+    let source = import_source(&params, &config)?;
+    let failures = import::failures(db, &source).await.map_err(AnyhowError)?;
+    Ok(json_reply(&failures))
+}
+
+/// Starts importing the failed days of the source given by `source=` again.
+async fn import_retry(
+    params: HashMap<String, String>,
+    db: Pool<Postgres>,
+    config: Arc<Config>,
+    jobs: Arc<Jobs>,
+) -> Result<impl warp::Reply, Rejection> {
+    let source = import_source(&params, &config)?;
+    let guard = lock_import()?;
+
+    let days = import::failures(db.clone(), &source)
+        .await
+        .map_err(AnyhowError)?
+        .into_iter()
+        .map(|failure| failure.date)
+        .collect();
+
+    Ok(start_import(db, &jobs, source, days, -1, guard))
+}
+
+/// Job looked up by the `id=` parameter.
+fn find_job(params: &HashMap<String, String>, jobs: &Jobs) -> Result<Arc<jobs::ImportJob>, Rejection> {
+    params
+        .get("id")
+        .and_then(|id| id.parse().ok())
+        .and_then(|id| jobs.get(id))
+        .ok_or_else(|| {
+            warp::reject::custom(ErrorResponse {
+                message: String::from("Unknown import job"),
+                status_code: warp::http::StatusCode::NOT_FOUND,
+            })
+        })
+}
+
+/// Status of the job given by `id=`, or of all recent jobs.
+async fn import_status(
+    params: HashMap<String, String>,
+    jobs: Arc<Jobs>,
+) -> Result<impl warp::Reply, Rejection> {
+    if params.contains_key("id") {
+        Ok(json_reply(&find_job(&params, &jobs)?.status()))
+    } else {
+        Ok(json_reply(&jobs.statuses()))
     }
+}
 
-    let json = json!({
-        "count": count,
-        "elapsed_time": instant.elapsed().as_millis()
+/// Stops the job given by `id=` once its current day is imported.
+async fn import_cancel(
+    params: HashMap<String, String>,
+    jobs: Arc<Jobs>,
+) -> Result<impl warp::Reply, Rejection> {
+    let job = find_job(&params, &jobs)?;
+    job.cancel();
+    Ok(json_reply(&job.status()))
+}
+
+/// Date in the parameter `name`, if given, in years 1 through 9999.
+fn date_param(params: &HashMap<String, String>, name: &str) -> Result<Option<NaiveDate>, Rejection> {
+    match params.get(name) {
+        Some(date) => NaiveDate::from_str(date)
+            .ok()
+            .filter(|date| (1..=9999).contains(&date.year()))
+            .map(Some)
+            .ok_or_else(|| bad_request(anyhow::anyhow!("Invalid date in {}=: {}", name, date))),
+        None => Ok(None),
+    }
+}
+
+/// Days `from=` through `to=`, today by default, of `channel=` or of every
+/// channel as a download in `format=`: `log`, `ndjson` (the default), `csv`
+/// or `html`. The output is streamed a day at a time.
+async fn export_logs(
+    params: HashMap<String, String>,
+    db: Pool<Postgres>,
+    config: Arc<Config>,
+) -> Result<impl warp::Reply, Rejection> {
+    let timezone = request_timezone(&params, &config)?;
+    let format = params
+        .get("format")
+        .map_or(Ok(export::ExportFormat::Ndjson), |format| format.parse())
+        .map_err(bad_request)?;
+
+    let from = date_param(&params, "from")?
+        .ok_or_else(|| bad_request(anyhow::anyhow!("Start date is missing in URL")))?;
+    let to = date_param(&params, "to")?.unwrap_or_else(|| timezone::today(timezone));
+    if from > to {
+        return Err(bad_request(anyhow::anyhow!("Start date is after end date")));
+    }
+
+    let file_name = format!("sprout-{}-{}.{}", from, to, format.extension());
+    let export = export::Export {
+        from,
+        to,
+        channel: params.get("channel").cloned(),
+        timezone,
+        format,
+    };
+
+    let body = export::export(db, export).inspect(|chunk| {
+        if let Err(err) = chunk {
+            eprintln!("export failed: {:#}", err);
+        }
     });
 
-    Ok(reply::with_status(
-        reply::with_header(
-            json.to_string(),
-            "Content-Type",
-            "application/json; charset=utf-8",
-        ),
-        warp::http::StatusCode::OK,
-    ))
+    Ok(warp::http::Response::builder()
+        .header("Content-Type", format.content_type())
+        .header(
+            "Content-Disposition",
+            format!("attachment; filename=\"{}\"", file_name),
+        )
+        .body(warp::hyper::Body::wrap_stream(body))
+        .unwrap())
+}
+
+/// Saved searches with the state of their alerts.
+async fn list_searches(db: Pool<Postgres>) -> Result<impl warp::Reply, Rejection> {
+    let searches = alerts::list(db).await.map_err(AnyhowError)?;
+    Ok(json_reply(&searches))
+}
+
+/// Saves the query in `q=` as the search called `name=`, replacing the
+/// query of an existing search of that name.
+async fn save_search(
+    params: HashMap<String, String>,
+    db: Pool<Postgres>,
+    config: Arc<Config>,
+) -> Result<impl warp::Reply, Rejection> {
+    let name = match params.get("name") {
+        Some(name) if !name.is_empty() => name,
+        _ => return Err(bad_request(anyhow::anyhow!("Search name is missing in URL"))),
+    };
+    parse_query(&params, &config)?;
+
+    let search = alerts::save(db, name, &params["q"]).await.map_err(AnyhowError)?;
+    Ok(json_reply(&search))
+}
+
+/// Deletes the search called `name=`.
+async fn delete_search(
+    params: HashMap<String, String>,
+    db: Pool<Postgres>,
+) -> Result<impl warp::Reply, Rejection> {
+    let name = params.get("name").cloned().unwrap_or_default();
+    if !alerts::delete(db, &name).await.map_err(AnyhowError)? {
+        return Err(warp::reject::custom(ErrorResponse {
+            message: String::from("Unknown saved search"),
+            status_code: warp::http::StatusCode::NOT_FOUND,
+        }));
+    }
+
+    Ok(json_reply(&json!({ "deleted": name })))
+}
+
+/// `logger-viewer import-files [--source NAME] [--format FORMAT] [--pattern PATTERN] PATH...`
+///
+/// Imports log files, and directories of them, into a configured source,
+/// optionally reading them in another format than the source's.
+/// The day of each file is taken from its name, `%Y-%m-%d.log` by default;
+/// gzip-compressed files may additionally end with `.gz`.
+async fn import_files(pool: Pool<Postgres>, config: &Config, args: Vec<String>) -> Result<(), Box<dyn Error>> {
+    let mut source = None;
+    let mut pattern = String::from("%Y-%m-%d.log");
+    let mut format = None;
+    let mut paths = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--source" => source = Some(args.next().ok_or("--source needs a value")?),
+            "--pattern" => pattern = args.next().ok_or("--pattern needs a value")?,
+            "--format" => format = Some(args.next().ok_or("--format needs a value")?),
+            _ => paths.push(std::path::PathBuf::from(arg)),
+        }
+    }
+
+    if paths.is_empty() {
+        return Err("usage: logger-viewer import-files [--source NAME] [--format FORMAT] [--pattern PATTERN] PATH...".into());
+    }
+
+    let mut source = config
+        .source(source.as_deref())
+        .ok_or_else(|| format!("unknown source: {}", source.unwrap_or_default()))?
+        .clone();
+    if let Some(format) = format {
+        source.format = format;
+    }
+    formats::find(&source.format)?;
+    let source = &source;
+
+    let files = import::collect_log_files(&paths, &pattern)?;
+    if files.is_empty() {
+        return Err(format!("no files matching '{}' found", pattern).into());
+    }
+
+    let _guard = import::LOCK.lock().await;
+    let mut total = 0;
+    let mut failed = 0;
+
+    for (path, date) in files {
+        match import::import_file(pool.clone(), source, &path, date).await {
+            Ok(count) => {
+                println!("{} ({}): {} messages", path.display(), date, count);
+                total += count;
+            }
+            Err(err) => {
+                println!("{} ({}): {:#}", path.display(), date, err);
+                failed += 1;
+            }
+        }
+    }
+
+    println!("imported {} messages into '{}', {} files failed", total, source.name, failed);
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let config = Config::new_from_file();
+    let config = Config::new_from_file()?;
     config.save()?;
 
+    // `logger-viewer new-token` prints a token for admin endpoints and its hash.
+    if env::args().nth(1).as_deref() == Some("new-token") {
+        let token = auth::new_token();
+        println!("token: {}", token);
+        println!("add to admin_tokens in config.toml: \"{}\"", auth::hash_token(&token));
+        return Ok(());
+    }
+
     let pool = sqlx::PgPool::connect(&config.postgres_url).await?;
+
+    // `logger-viewer migrate` only brings the schema up to date,
+    // `logger-viewer reindex` recomputes stored text-search vectors.
+    match env::args().nth(1).as_deref() {
+        Some("migrate") => {
+            MIGRATOR.run(&pool).await?;
+            println!("database schema is up to date");
+            return Ok(());
+        }
+        Some("reindex") => {
+            MIGRATOR.run(&pool).await?;
+            let count = import::reindex(pool).await?;
+            println!("reindexed {} messages", count);
+            return Ok(());
+        }
+        Some("import-files") => {
+            MIGRATOR.run(&pool).await?;
+            return import_files(pool, &config, env::args().skip(2).collect()).await;
+        }
+        _ => {}
+    }
+
+    if config.migrate {
+        MIGRATOR.run(&pool).await?;
+    }
+
     let mut listener = PgListener::connect(&config.postgres_url).await?;
 
-    listener.listen("chan0").await?;
+    listener.listen(import::CHANNEL).await?;
 
     let mut hb = Handlebars::new();
     hb.register_template_file("index.html", "template/index.handlebars")?;
     hb.register_template_file("search.html", "template/search.handlebars")?;
+    hb.register_template_file("stats.html", "template/stats.handlebars")?;
 
-    let dates = Arc::new(Mutex::new(Vec::new()));
+    let dates = Arc::new(Mutex::new(HashMap::new()));
 
     let timer = timer::Timer::new();
     let dates_timer = dates.clone();
@@ -385,74 +1158,270 @@ async fn main() -> Result<(), Box<dyn Error>> {
             });
         })
     };
+    if let Some(irc) = config.irc.clone() {
+        tokio::spawn(irc::run(pool.clone(), irc));
+    }
+
+    let jobs = Arc::new(Jobs::default());
+    let runtime = tokio::runtime::Handle::current();
+
+    let _import_guards: Vec<timer::Guard> = config
+        .sources
+        .iter()
+        .filter_map(|source| Some((source.clone(), source.import_interval?)))
+        .map(|(source, interval)| {
+            let pool = pool.clone();
+            let jobs = jobs.clone();
+            let runtime = runtime.clone();
+
+            timer.schedule_repeating(chrono::Duration::minutes(interval.into()), move || {
+                runtime.spawn(import::scheduled(pool.clone(), source.clone(), jobs.clone()));
+            })
+        })
+        .collect();
+
+    let config = Arc::new(config);
+
+    // retry alert deliveries that failed once they are due again
+    let _alert_guard = config.alert_webhook.clone().map(|url| {
+        let pool = pool.clone();
+        let runtime = runtime.clone();
+
+        timer.schedule_repeating(chrono::Duration::minutes(1), move || {
+            runtime.spawn(alerts::deliver(pool.clone(), url.clone()));
+        })
+    });
+
+    // drop cached dates as soon as new messages are stored, pass the
+    // messages on to `/logs/stream` and alert on saved searches they match
+    let broadcaster = Broadcaster::default();
+    let dates_listener = dates.clone();
+    let broadcaster_listener = broadcaster.clone();
+    let pool_listener = pool.clone();
+    let config_listener = config.clone();
+    tokio::spawn(async move {
+        loop {
+            let notification = match listener.recv().await {
+                Ok(v) => v,
+                Err(err) => {
+                    eprintln!("listener: {}", err);
+                    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+
+            dates_listener.lock().await.clear();
+
+            let imported: import::Imported = match serde_json::from_str(notification.payload()) {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+            if let Err(err) = broadcaster_listener.publish(&pool_listener, &imported).await {
+                eprintln!("listener: failed to publish messages: {:#}", err);
+            }
+
+            if let Some(url) = config_listener.alert_webhook.clone() {
+                let pool = pool_listener.clone();
+                let config = config_listener.clone();
+                tokio::spawn(async move {
+                    match alerts::check(pool.clone(), &config, &imported).await {
+                        Ok(0) => {}
+                        Ok(_) => alerts::deliver(pool, url).await,
+                        Err(err) => eprintln!("alerts: failed to check saved searches: {:#}", err),
+                    }
+                });
+            }
+        }
+    });
+
     let db_filter = warp::any().map(move || pool.clone());
     let dates_filter = warp::any().map(move || dates.clone());
+    let jobs_filter = warp::any().map(move || jobs.clone());
+    let broadcaster_filter = warp::any().map(move || broadcaster.clone());
     let static_files = env::current_dir()?.join(Path::new("static"));
     let hb = Arc::new(hb);
+    let bind = (config.bind_address, config.port);
 
     if static_files.exists() {
         let log_route = warp::path!("logs" / String)
             .and(warp::query::<HashMap<String, String>>())
             .and(db_filter.clone())
+            .and(with_config(config.clone()))
             .and_then(get_log_by_date);
 
+        let log_stream_route = warp::path!("logs" / "stream")
+            .and(warp::query::<HashMap<String, String>>())
+            .and(warp::header::optional::<String>("last-event-id"))
+            .and(db_filter.clone())
+            .and(with_config(config.clone()))
+            .and(broadcaster_filter)
+            .and_then(stream_logs);
+
         let log_search_route = warp::path!("logs" / "search")
             .and(warp::query::<HashMap<String, String>>())
             .and(db_filter.clone())
+            .and(with_config(config.clone()))
             .and_then(search_logs);
 
+        let log_explain_route = warp::path!("logs" / "search" / "explain")
+            .and(warp::query::<HashMap<String, String>>())
+            .and(warp::header::optional::<String>("authorization"))
+            .and(db_filter.clone())
+            .and(with_config(config.clone()))
+            .and_then(explain_search);
+
         let log_today_route = warp::path!("logs" / "latest")
             .and(warp::query::<HashMap<String, String>>())
             .and(db_filter.clone())
+            .and(with_config(config.clone()))
             .and_then(get_today_logs);
 
         let log_total_dates = warp::path!("dates")
+            .and(warp::query::<HashMap<String, String>>())
             .and(db_filter.clone())
             .and(dates_filter)
+            .and(with_config(config.clone()))
             .and_then(get_log_dates);
 
         let log_import = warp::path!("logs" / "import")
+            .and(warp::post())
+            .and(auth::admin(config.clone()))
+            .and(warp::query::<HashMap<String, String>>())
+            .and(db_filter.clone())
+            .and(with_config(config.clone()))
+            .and(jobs_filter.clone())
+            .and_then(import);
+
+        let log_import_failures = warp::path!("logs" / "import" / "failures")
+            .and(auth::admin(config.clone()))
+            .and(warp::query::<HashMap<String, String>>())
             .and(db_filter.clone())
+            .and(with_config(config.clone()))
+            .and_then(import_failures);
+
+        let log_import_retry = warp::path!("logs" / "import" / "retry")
+            .and(warp::post())
+            .and(auth::admin(config.clone()))
+            .and(warp::query::<HashMap<String, String>>())
+            .and(db_filter.clone())
+            .and(with_config(config.clone()))
+            .and(jobs_filter.clone())
+            .and_then(import_retry);
+
+        let log_import_status = warp::path!("logs" / "import" / "status")
+            .and(auth::admin(config.clone()))
+            .and(warp::query::<HashMap<String, String>>())
+            .and(jobs_filter.clone())
+            .and_then(import_status);
+
+        let log_import_cancel = warp::path!("logs" / "import" / "cancel")
+            .and(warp::post())
+            .and(auth::admin(config.clone()))
             .and(warp::query::<HashMap<String, String>>())
-            .and_then(move |db: Pool<Postgres>, query: HashMap<String, String>| import(query, db));
+            .and(jobs_filter.clone())
+            .and_then(import_cancel);
+
+        let export_route = warp::path!("export")
+            .and(warp::query::<HashMap<String, String>>())
+            .and(db_filter.clone())
+            .and(with_config(config.clone()))
+            .and_then(export_logs);
+
+        let searches_list = warp::path!("searches")
+            .and(warp::get())
+            .and(auth::admin(config.clone()))
+            .and(db_filter.clone())
+            .and_then(list_searches);
+
+        let searches_save = warp::path!("searches")
+            .and(warp::post())
+            .and(auth::admin(config.clone()))
+            .and(warp::query::<HashMap<String, String>>())
+            .and(db_filter.clone())
+            .and(with_config(config.clone()))
+            .and_then(save_search);
+
+        let searches_delete = warp::path!("searches" / "delete")
+            .and(warp::post())
+            .and(auth::admin(config.clone()))
+            .and(warp::query::<HashMap<String, String>>())
+            .and(db_filter.clone())
+            .and_then(delete_search);
 
         let log_interface = warp::path!(String)
             .and_then(|segment: String| async move {
-                if segment != "search" {
+                if !["search", "stats", "searches", "export"].contains(&segment.as_str()) {
                     Ok(segment)
                 } else {
                     Err(warp::reject::not_found())
                 }
             })
+            .and(warp::query::<HashMap<String, String>>())
             .and(with_template_engine(hb.clone()))
             .and(db_filter.clone())
+            .and(with_config(config.clone()))
             .and_then(view_log_as_html);
 
         let log_interface_index = warp::path::end()
-            .and(warp::any().map(|| String::new()))
+            .and(warp::any().map(String::new))
+            .and(warp::query::<HashMap<String, String>>())
             .and(with_template_engine(hb.clone()))
             .and(db_filter.clone())
+            .and(with_config(config.clone()))
             .and_then(view_log_as_html);
 
         let log_interface_search = warp::path!("search")
             .and(warp::query::<HashMap<String, String>>())
             .and(with_template_engine(hb.clone()))
             .and(db_filter.clone())
+            .and(with_config(config.clone()))
             .and_then(view_search_as_html);
 
+        let stats_count_route = warp::path!("stats" / "count")
+            .and(warp::query::<HashMap<String, String>>())
+            .and(db_filter.clone())
+            .and(with_config(config.clone()))
+            .and_then(stats_count);
+
+        let stats_top_route = warp::path!("stats" / "top")
+            .and(warp::query::<HashMap<String, String>>())
+            .and(db_filter.clone())
+            .and(with_config(config.clone()))
+            .and_then(stats_top);
+
+        let stats_interface = warp::path!("stats")
+            .and(warp::query::<HashMap<String, String>>())
+            .and(with_template_engine(hb.clone()))
+            .and(db_filter.clone())
+            .and(with_config(config.clone()))
+            .and_then(view_stats_as_html);
+
         warp::serve(
             warp::fs::dir(static_files)
                 .or(log_import)
+                .or(log_import_status)
+                .or(log_import_failures)
+                .or(log_import_retry)
+                .or(log_import_cancel)
+                .or(export_route)
+                .or(searches_list)
+                .or(searches_save)
+                .or(searches_delete)
                 .or(log_interface_index)
+                .or(log_stream_route)
                 .or(log_route)
                 .or(log_today_route)
                 .or(log_search_route)
+                .or(log_explain_route)
                 .or(log_total_dates)
                 .or(log_interface)
                 .or(log_interface_search)
+                .or(stats_count_route)
+                .or(stats_top_route)
+                .or(stats_interface)
                 .recover(error::handle_rejection_json),
         )
-        .run((config.bind_address, config.port))
+        .run(bind)
         .await;
     } else {
         eprintln!(
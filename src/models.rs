@@ -1,5 +1,88 @@
-use chrono::NaiveDate;
-use serde::Serialize;
+use std::str::FromStr;
+
+use chrono::{NaiveDate, Timelike};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::bots::BotList;
+use crate::timezone;
+
+/// Markers `ts_headline` puts around matched words.
+pub const HIGHLIGHT_START: char = '\u{E000}';
+pub const HIGHLIGHT_STOP: char = '\u{E001}';
+
+/// What a log line records. Events keep their nick in `author` and in `body`
+/// the part reason or quit message, the new nick, or the new topic.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+pub enum MessageKind {
+    Message,
+    Action,
+    Join,
+    Part,
+    Quit,
+    Nick,
+    Topic,
+}
+
+impl MessageKind {
+    pub const ALL: [MessageKind; 7] = [
+        MessageKind::Message,
+        MessageKind::Action,
+        MessageKind::Join,
+        MessageKind::Part,
+        MessageKind::Quit,
+        MessageKind::Nick,
+        MessageKind::Topic,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MessageKind::Message => "message",
+            MessageKind::Action => "action",
+            MessageKind::Join => "join",
+            MessageKind::Part => "part",
+            MessageKind::Quit => "quit",
+            MessageKind::Nick => "nick",
+            MessageKind::Topic => "topic",
+        }
+    }
+
+    /// Prefix IRC clients show in place of `<nick>`.
+    pub fn marker(self) -> Option<&'static str> {
+        match self {
+            MessageKind::Message => None,
+            MessageKind::Action => Some("*"),
+            MessageKind::Join => Some("-->"),
+            MessageKind::Part | MessageKind::Quit => Some("<--"),
+            MessageKind::Nick | MessageKind::Topic => Some("--"),
+        }
+    }
+
+    /// Words between the nick and the body of an event.
+    pub fn verb(self) -> &'static str {
+        match self {
+            MessageKind::Message | MessageKind::Action => "",
+            MessageKind::Join => "has joined",
+            MessageKind::Part => "has left",
+            MessageKind::Quit => "has quit",
+            MessageKind::Nick => "is now known as",
+            MessageKind::Topic => "has changed the topic to:",
+        }
+    }
+}
+
+impl FromStr for MessageKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<MessageKind> {
+        MessageKind::ALL
+            .into_iter()
+            .find(|kind| kind.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| anyhow::anyhow!("unknown message kind '{}'", s))
+    }
+}
 
 #[derive(Serialize, Debug, Clone)]
 pub struct Message {
@@ -8,17 +91,69 @@ pub struct Message {
     pub author: String,
     pub body: String,
     pub offset: i32,
+    pub kind: MessageKind,
+    /// Fragments of `body` around search matches, as produced by `ts_headline`.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_highlight"
+    )]
+    pub highlight: Option<String>,
+}
+
+/// A piece of highlighted text; `matched` pieces are the search hits.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Highlight {
+    pub text: String,
+    pub matched: bool,
+}
+
+pub fn split_highlight(headline: &str) -> Vec<Highlight> {
+    let mut parts = vec![];
+    let mut matched = false;
+
+    for piece in headline.split([HIGHLIGHT_START, HIGHLIGHT_STOP]) {
+        if !piece.is_empty() {
+            parts.push(Highlight {
+                text: piece.to_owned(),
+                matched,
+            });
+        }
+        matched = !matched;
+    }
+
+    parts
+}
+
+fn serialize_highlight<S>(highlight: &Option<String>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    highlight.as_deref().map(split_highlight).serialize(serializer)
+}
+
+impl Message {
+    /// Shifts the stored UTC timestamp to wall-clock time in `tz`.
+    pub fn in_timezone(mut self, tz: Tz) -> Message {
+        self.time = timezone::utc_to_local(tz, self.time);
+        self
+    }
 }
 
 impl From<Message> for MessageTemplate {
     fn from(value: Message) -> Self {
-        let time = value.time.time();
+        // shown as `HH:MM:SS`, also for live messages stored with fractions
+        let time = value.time.time().with_nanosecond(0).unwrap();
         MessageTemplate {
             id: value.id,
             time: Some(time),
             author: value.author,
             body: value.body,
             offset: value.offset,
+            kind: value.kind,
+            marker: value.kind.marker(),
+            verb: value.kind.verb(),
+            is_bot: false,
+            highlight: value.highlight.as_deref().map(split_highlight),
         }
     }
 }
@@ -30,6 +165,19 @@ pub struct MessageTemplate {
     pub author: String,
     pub body: String,
     pub offset: i32,
+    pub kind: MessageKind,
+    /// Set for events, which are shown as `marker nick verb body`.
+    pub marker: Option<&'static str>,
+    pub verb: &'static str,
+    pub is_bot: bool,
+    pub highlight: Option<Vec<Highlight>>,
+}
+
+impl MessageTemplate {
+    pub fn with_bots(mut self, bots: &BotList) -> MessageTemplate {
+        self.is_bot = bots.is_bot(&self.author);
+        self
+    }
 }
 
 #[derive(Serialize, Debug, Clone)]
@@ -0,0 +1,92 @@
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+/// Value a result is ordered by, ahead of `msg_timestamp` and `msg_id`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SortKey {
+    /// `sort:time`, and `sort:relevance` for queries without phrases.
+    Time,
+    /// `sort:relevance`; bits of the `f64` rank, so it survives encoding exactly.
+    Rank(u64),
+    /// `sort:random`; the seed keeps the order stable across pages.
+    Random { seed: u32, hash: String },
+}
+
+/// Position of a single result, handed out as an opaque string in
+/// `next`/`prev` links. Pages start right after (or, when `backward`,
+/// right before) the result it points at.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Cursor {
+    pub key: SortKey,
+    pub time: NaiveDateTime,
+    pub id: i32,
+    pub backward: bool,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap())
+    }
+
+    pub fn decode(value: &str) -> Result<Cursor> {
+        URL_SAFE_NO_PAD
+            .decode(value)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .context("Invalid cursor")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let time = NaiveDateTime::parse_from_str("2023-01-31 12:34:56.789", "%Y-%m-%d %H:%M:%S%.f").unwrap();
+        for (key, backward) in [
+            (SortKey::Time, false),
+            (SortKey::Rank(0.0607927f64.to_bits()), true),
+            (SortKey::Rank(f64::MIN_POSITIVE.to_bits()), false),
+            (SortKey::Random { seed: u32::MAX, hash: "0cc175b9c0f1b6a831c399e269772661".to_owned() }, true),
+        ] {
+            let cursor = Cursor { key, time, id: i32::MAX, backward };
+            let encoded = cursor.encode();
+            assert!(encoded.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_'));
+            assert_eq!(Cursor::decode(&encoded).unwrap(), cursor);
+        }
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        let encode = |json: &str| URL_SAFE_NO_PAD.encode(json);
+        let valid = Cursor {
+            key: SortKey::Time,
+            time: NaiveDateTime::parse_from_str("2023-01-31 12:34:56", "%Y-%m-%d %H:%M:%S").unwrap(),
+            id: 1,
+            backward: false,
+        }
+        .encode();
+
+        for value in [
+            String::new(),
+            "!!!".to_owned(),
+            "not a cursor".to_owned(),
+            // padded and standard alphabets are not what `encode` gives
+            format!("{}==", valid),
+            valid.replace('_', "/").replace('-', "+") + "+/",
+            valid[..valid.len() - 3].to_owned(),
+            encode("not json"),
+            encode("{}"),
+            encode(r#"{"key":"Time","time":"2023-01-31T12:34:56","id":"1","backward":false}"#),
+            encode(r#"{"key":"Time","time":"yesterday","id":1,"backward":false}"#),
+            encode(r#"{"key":"Oldest","time":"2023-01-31T12:34:56","id":1,"backward":false}"#),
+            encode(r#"{"key":{"Rank":-1},"time":"2023-01-31T12:34:56","id":1,"backward":false}"#),
+            encode(r#"{"key":"Time","time":"2023-01-31T12:34:56","id":4294967296,"backward":false}"#),
+        ] {
+            assert!(Cursor::decode(&value).is_err(), "{}", value);
+        }
+    }
+}
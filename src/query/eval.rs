@@ -0,0 +1,958 @@
+//! Evaluates queries against single messages in-process, for filtering
+//! messages as they arrive without asking the database about each one.
+//!
+//! Filters are compiled the way [`build_filter`](super::build_filter) builds
+//! SQL, so a message matches exactly when the SQL would select it. Phrases
+//! are turned into `tsquery` values by the database once, when the filter is
+//! compiled, and then matched against the stored `msg_tsv` of each message.
+//! Relative dates like `date:today` are resolved at that point as well.
+//!
+//! Regular expressions of `regex:`, `iregex:` and `similarto:` run on the
+//! `regex` crate, which lacks some PostgreSQL features: see [`check_regex`].
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use anyhow::{bail, Context, Result};
+use regex::{Regex, RegexBuilder};
+use sqlx::postgres::PgConnection;
+use sqlx::{Executor, Row as _};
+
+use super::{
+    build_tsquery, can_separate, leave_funcs_only, leave_tsqueries_only, Bindings, ExecWrapper,
+    Expr, QueryBuilder, QueryOptions,
+};
+use crate::models::Message;
+
+/// A function filter compiled for a single value.
+pub type Matcher = Box<dyn Fn(&Row) -> bool + Send + Sync>;
+
+/// A stored message with the columns filters look at besides [`Message`].
+/// Times of `message` are in UTC, as stored.
+pub struct Row {
+    pub message: Message,
+    pub channel: String,
+    pub tsv: TsVector,
+}
+
+/// What functions may look at while being compiled.
+pub struct MatchContext {
+    pub options: QueryOptions,
+    /// Primary nicks by their aliases.
+    aliases: HashMap<String, String>,
+}
+
+impl MatchContext {
+    /// Nicks `author:<nick>` matches: the nick itself, the primary nick it
+    /// is an alias of and every alias of that primary nick.
+    pub fn author_names(&self, nick: &str) -> HashSet<String> {
+        let primary = self.aliases.get(nick).map(String::as_str).unwrap_or(nick);
+        let mut names: HashSet<String> = self
+            .aliases
+            .iter()
+            .filter(|(_, p)| p.as_str() == primary)
+            .map(|(secondary, _)| secondary.clone())
+            .collect();
+        names.insert(nick.to_owned());
+        names.insert(primary.to_owned());
+        names
+    }
+}
+
+/// Regex matching what `LIKE pattern` matches, with `\` escaping the next
+/// character.
+pub fn like_regex(pattern: &str, case_insensitive: bool) -> Result<Regex> {
+    let mut regex = String::from("^");
+    let mut chars = pattern.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '%' => regex.push_str(".*"),
+            '_' => regex.push('.'),
+            '\\' => {
+                let escaped = chars
+                    .next()
+                    .context("LIKE pattern must not end with escape character")?;
+                regex.push_str(&regex::escape(&escaped.to_string()));
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    regex.push('$');
+    Ok(RegexBuilder::new(&regex)
+        .case_insensitive(case_insensitive)
+        .dot_matches_new_line(true)
+        .build()?)
+}
+
+/// Rejects regular expressions that PostgreSQL and the `regex` crate would
+/// read differently: backreferences and lookaround, which `regex` lacks, and
+/// `\b` and `\B`, a backspace and a backslash in PostgreSQL but word
+/// boundaries in `regex`.
+pub fn check_regex(pattern: &str) -> Result<()> {
+    let mut chars = pattern.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('1'..='9') => bail!("backreferences are not supported here"),
+                Some(c @ ('b' | 'B')) => bail!("'\\{}' is not supported here", c),
+                _ => {}
+            },
+            // classes like `[(?=)]` hold no operators, and `]` right after
+            // the opening bracket does not close it
+            '[' => {
+                chars.next_if_eq(&'^');
+                chars.next_if_eq(&']');
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        ']' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '(' if chars.next_if_eq(&'?').is_some() => {
+                chars.next_if_eq(&'<');
+                if matches!(chars.peek(), Some('=' | '!')) {
+                    bail!("lookahead and lookbehind are not supported here");
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Regex matching what `SIMILAR TO pattern` matches: `%` and `_` as in
+/// `LIKE`, regular expression operators otherwise, anchored at both ends.
+pub fn similar_regex(pattern: &str) -> Result<Regex> {
+    check_regex(pattern)?;
+
+    let mut regex = String::from("^(?:");
+    let mut chars = pattern.chars();
+    let mut in_brackets = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let escaped = chars
+                    .next()
+                    .context("SIMILAR TO pattern must not end with escape character")?;
+                // class escapes like `\d` keep their meaning
+                if escaped.is_ascii_alphanumeric() {
+                    regex.push('\\');
+                    regex.push(escaped);
+                } else {
+                    regex.push_str(&regex::escape(&escaped.to_string()));
+                }
+            }
+            _ if in_brackets => {
+                if c == ']' {
+                    in_brackets = false;
+                }
+                regex.push(c);
+            }
+            '[' => {
+                in_brackets = true;
+                regex.push(c);
+            }
+            '%' => regex.push_str(".*"),
+            '_' => regex.push('.'),
+            '(' => regex.push_str("(?:"),
+            '|' | '*' | '+' | '?' | '{' | '}' | ')' | ',' => regex.push(c),
+            c if c.is_ascii_digit() => regex.push(c),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    regex.push_str(")$");
+    Ok(RegexBuilder::new(&regex).dot_matches_new_line(true).build()?)
+}
+
+/// Lexemes of a `tsvector` with their positions, parsed from its text form
+/// like `'cat':2 'sat':3,5`.
+#[derive(Clone, Debug, Default)]
+pub struct TsVector(HashMap<String, BTreeSet<u32>>);
+
+impl TsVector {
+    pub fn parse(text: &str) -> TsVector {
+        let mut lexemes = HashMap::new();
+        let mut chars = text.chars().peekable();
+
+        loop {
+            while chars.next_if(|c| *c != '\'').is_some() {}
+            if chars.next().is_none() {
+                break;
+            }
+
+            let lexeme = quoted(&mut chars);
+            let mut positions = BTreeSet::new();
+            if chars.next_if_eq(&':').is_some() {
+                for position in take_while(&mut chars, |c| c != ' ').split(',') {
+                    let digits: String = position.chars().take_while(char::is_ascii_digit).collect();
+                    if let Ok(position) = digits.parse() {
+                        positions.insert(position);
+                    }
+                }
+            }
+
+            lexemes.insert(lexeme, positions);
+        }
+
+        TsVector(lexemes)
+    }
+}
+
+type Chars<'a> = std::iter::Peekable<std::str::Chars<'a>>;
+
+/// Reads up to the closing quote of a lexeme, `''` and `\` escaping quotes.
+fn quoted(chars: &mut Chars) -> String {
+    let mut value = String::new();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => value.extend(chars.next()),
+            '\'' if chars.next_if_eq(&'\'').is_some() => value.push('\''),
+            '\'' => break,
+            c => value.push(c),
+        }
+    }
+
+    value
+}
+
+fn take_while(chars: &mut Chars, p: impl Fn(char) -> bool) -> String {
+    let mut value = String::new();
+    while let Some(c) = chars.next_if(|c| p(*c)) {
+        value.push(c);
+    }
+    value
+}
+
+impl TsVector {
+    /// Positions of `lexeme`, or of every lexeme starting with it if
+    /// `prefix` is set.
+    fn positions(&self, lexeme: &str, prefix: bool) -> BTreeSet<u32> {
+        if !prefix {
+            return self.0.get(lexeme).cloned().unwrap_or_default();
+        }
+
+        self.0
+            .iter()
+            .filter(|(key, _)| key.starts_with(lexeme))
+            .flat_map(|(_, positions)| positions.iter().copied())
+            .collect()
+    }
+}
+
+/// A `tsquery`, parsed from its text form like `'cat' <-> 'sat' & !'dog'`.
+#[derive(Clone, Debug)]
+pub enum TsQuery {
+    /// A query without lexemes, e.g. of a phrase made of stop words only.
+    Empty,
+    /// A lexeme, matching every lexeme it starts if it is a prefix (`:*`).
+    /// Weights are ignored, since stored vectors have none.
+    Lexeme(String, bool),
+    Not(Box<TsQuery>),
+    And(Box<TsQuery>, Box<TsQuery>),
+    Or(Box<TsQuery>, Box<TsQuery>),
+    /// Right operand `distance` positions after the left one.
+    Phrase(Box<TsQuery>, Box<TsQuery>, u32),
+}
+
+/// Where a query matches within phrase operators, as PostgreSQL's
+/// `TS_phrase_execute` computes it.
+struct PhraseMatch {
+    /// Positions of the last lexeme of each match.
+    positions: BTreeSet<u32>,
+    /// Stands for every position except `positions`.
+    negated: bool,
+    /// Positions a match spans before its last one.
+    width: u32,
+}
+
+impl PhraseMatch {
+    fn shifted(&self, offset: u32) -> BTreeSet<u32> {
+        self.positions.iter().map(|p| p + offset).collect()
+    }
+}
+
+impl TsQuery {
+    pub fn parse(text: &str) -> Result<TsQuery> {
+        let mut chars = text.chars().peekable();
+        skip_spaces(&mut chars);
+        if chars.peek().is_none() {
+            return Ok(TsQuery::Empty);
+        }
+
+        let query = parse_or(&mut chars)?;
+        skip_spaces(&mut chars);
+        if let Some(c) = chars.peek() {
+            bail!("unexpected '{}' in tsquery '{}'", c, text);
+        }
+        Ok(query)
+    }
+
+    /// Whether `tsv @@ query` holds.
+    pub fn matches(&self, tsv: &TsVector) -> bool {
+        match self {
+            TsQuery::Empty => false,
+            TsQuery::Lexeme(lexeme, prefix) => !tsv.positions(lexeme, *prefix).is_empty(),
+            TsQuery::Not(inner) => !inner.matches(tsv),
+            TsQuery::And(a, b) => a.matches(tsv) && b.matches(tsv),
+            TsQuery::Or(a, b) => a.matches(tsv) || b.matches(tsv),
+            TsQuery::Phrase(..) => {
+                let found = self.phrase_match(tsv);
+                found.negated || !found.positions.is_empty()
+            }
+        }
+    }
+
+    /// Where the query matches within a phrase operator.
+    fn phrase_match(&self, tsv: &TsVector) -> PhraseMatch {
+        let (a, b) = match self {
+            TsQuery::Empty => {
+                return PhraseMatch {
+                    positions: BTreeSet::new(),
+                    negated: false,
+                    width: 0,
+                }
+            }
+            TsQuery::Lexeme(lexeme, prefix) => {
+                return PhraseMatch {
+                    positions: tsv.positions(lexeme, *prefix),
+                    negated: false,
+                    width: 0,
+                }
+            }
+            TsQuery::Not(inner) => {
+                let found = inner.phrase_match(tsv);
+                return PhraseMatch {
+                    negated: !found.negated,
+                    ..found
+                };
+            }
+            TsQuery::Phrase(a, b, _) | TsQuery::And(a, b) | TsQuery::Or(a, b) => (a, b),
+        };
+
+        let (a, b) = (a.phrase_match(tsv), b.phrase_match(tsv));
+
+        // the right operand of a phrase must end `distance` positions after
+        // the left one, operands of `&` and `|` are aligned at their ends
+        let (width, left, right) = match self {
+            TsQuery::Phrase(_, _, distance) => {
+                (distance + a.width + b.width, a.shifted(distance + b.width), b.shifted(0))
+            }
+            _ => {
+                let width = a.width.max(b.width);
+                (width, a.shifted(width - a.width), b.shifted(width - b.width))
+            }
+        };
+
+        let (positions, negated) = match (matches!(self, TsQuery::Or(..)), a.negated, b.negated) {
+            // !a & !b = !(a | b)
+            (false, true, true) => (&left | &right, true),
+            (false, true, false) => (&right - &left, false),
+            (false, false, true) => (&left - &right, false),
+            (false, false, false) => (&left & &right, false),
+            // !a | !b = !(a & b)
+            (true, true, true) => (&left & &right, true),
+            (true, true, false) => (&left - &right, true),
+            (true, false, true) => (&right - &left, true),
+            (true, false, false) => (&left | &right, false),
+        };
+
+        PhraseMatch {
+            positions,
+            negated,
+            width,
+        }
+    }
+}
+
+fn skip_spaces(chars: &mut Chars) {
+    while chars.next_if_eq(&' ').is_some() {}
+}
+
+// Operators bind in the order `!`, `<->`, `&`, `|`, tightest first.
+
+fn parse_or(chars: &mut Chars) -> Result<TsQuery> {
+    let mut query = parse_and(chars)?;
+    loop {
+        skip_spaces(chars);
+        if chars.next_if_eq(&'|').is_none() {
+            return Ok(query);
+        }
+        query = TsQuery::Or(Box::new(query), Box::new(parse_and(chars)?));
+    }
+}
+
+fn parse_and(chars: &mut Chars) -> Result<TsQuery> {
+    let mut query = parse_phrase(chars)?;
+    loop {
+        skip_spaces(chars);
+        if chars.next_if_eq(&'&').is_none() {
+            return Ok(query);
+        }
+        query = TsQuery::And(Box::new(query), Box::new(parse_phrase(chars)?));
+    }
+}
+
+fn parse_phrase(chars: &mut Chars) -> Result<TsQuery> {
+    let mut query = parse_not(chars)?;
+    loop {
+        skip_spaces(chars);
+        if chars.next_if_eq(&'<').is_none() {
+            return Ok(query);
+        }
+
+        let distance = take_while(chars, |c| c != '>');
+        chars.next();
+        let distance = match distance.as_str() {
+            "-" => 1,
+            n => n.parse().context("invalid phrase distance in tsquery")?,
+        };
+        query = TsQuery::Phrase(Box::new(query), Box::new(parse_not(chars)?), distance);
+    }
+}
+
+fn parse_not(chars: &mut Chars) -> Result<TsQuery> {
+    skip_spaces(chars);
+    match chars.next() {
+        Some('!') => Ok(TsQuery::Not(Box::new(parse_not(chars)?))),
+        Some('(') => {
+            let query = parse_or(chars)?;
+            skip_spaces(chars);
+            if chars.next() != Some(')') {
+                bail!("unbalanced parentheses in tsquery");
+            }
+            Ok(query)
+        }
+        Some('\'') => {
+            let lexeme = quoted(chars);
+            let mut prefix = false;
+            if chars.next_if_eq(&':').is_some() {
+                prefix = take_while(chars, |c| c.is_ascii_alphabetic() || c == '*').contains('*');
+            }
+            Ok(TsQuery::Lexeme(lexeme, prefix))
+        }
+        c => bail!("unexpected {:?} in tsquery", c),
+    }
+}
+
+enum Filter {
+    True,
+    False,
+    Not(Box<Filter>),
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+    Func(Matcher),
+    /// Index into [`MessageFilter::tsqueries`].
+    Phrase(usize),
+}
+
+/// A query compiled for matching messages one by one, see the module
+/// documentation.
+pub struct MessageFilter {
+    filter: Filter,
+    tsqueries: Vec<TsQuery>,
+}
+
+impl MessageFilter {
+    pub async fn new(db: &mut PgConnection, expr: Expr, options: QueryOptions) -> Result<MessageFilter> {
+        let aliases: Vec<(String, String)> =
+            sqlx::query_as("SELECT alias_secondary, alias_primary FROM aliases")
+                .fetch_all(&mut *db)
+                .await?;
+
+        let context = MatchContext {
+            options: options.clone(),
+            aliases: aliases.into_iter().collect(),
+        };
+
+        let mut bindings = Bindings::new(options);
+        let mut phrases = vec![];
+        let filter = compile(&context, &mut bindings, &mut phrases, expr.normalize()?)?;
+
+        let mut tsqueries = vec![];
+        if !phrases.is_empty() {
+            let mut query = QueryBuilder::default();
+            query.sql("SELECT ARRAY[");
+            for (i, phrase) in phrases.iter().enumerate() {
+                if i > 0 {
+                    query.sql(", ");
+                }
+                query.sql("(");
+                query.append(phrase);
+                query.sql(")::text");
+            }
+            query.sql("]::text[]");
+
+            let row = db.fetch_one(ExecWrapper(&query, bindings)).await?;
+            for text in row.get::<Vec<String>, _>(0) {
+                tsqueries.push(TsQuery::parse(&text)?);
+            }
+        }
+
+        Ok(MessageFilter { filter, tsqueries })
+    }
+
+    pub fn matches(&self, row: &Row) -> bool {
+        self.eval(&self.filter, row)
+    }
+
+    fn eval(&self, filter: &Filter, row: &Row) -> bool {
+        match filter {
+            Filter::True => true,
+            Filter::False => false,
+            Filter::Not(inner) => !self.eval(inner, row),
+            Filter::And(inner) => inner.iter().all(|f| self.eval(f, row)),
+            Filter::Or(inner) => inner.iter().any(|f| self.eval(f, row)),
+            Filter::Func(matcher) => matcher(row),
+            Filter::Phrase(i) => self.tsqueries[*i].matches(&row.tsv),
+        }
+    }
+}
+
+/// Mirrors `build_filter`, collecting the `tsquery` of each phrase filter.
+fn compile(
+    context: &MatchContext,
+    bindings: &mut Bindings,
+    phrases: &mut Vec<QueryBuilder>,
+    expr: Expr,
+) -> Result<Filter> {
+    if !can_separate(&expr) {
+        let list = |exprs: Vec<Expr>, bindings: &mut Bindings, phrases: &mut Vec<QueryBuilder>| {
+            exprs
+                .into_iter()
+                .map(|e| compile(context, bindings, phrases, e))
+                .collect::<Result<Vec<_>>>()
+        };
+
+        return match expr {
+            Expr::And(inner) => Ok(Filter::And(list(inner, bindings, phrases)?)),
+            Expr::Or(inner) => Ok(Filter::Or(list(inner, bindings, phrases)?)),
+            _ => unreachable!(),
+        };
+    }
+
+    let is_or = expr.is_or();
+    let f_expr = leave_funcs_only(expr.clone()).reduce();
+    let p_expr = leave_tsqueries_only(expr).reduce();
+
+    let mut phrase = |expr| {
+        let mut partial = QueryBuilder::default();
+        build_tsquery(&mut partial, bindings, expr)?;
+        phrases.push(partial);
+        Ok::<_, anyhow::Error>(Filter::Phrase(phrases.len() - 1))
+    };
+
+    let filter = if is_or {
+        if f_expr == Expr::True || p_expr == Expr::True {
+            Filter::True
+        } else if f_expr == Expr::False && p_expr == Expr::False {
+            Filter::False
+        } else if f_expr == Expr::Empty {
+            phrase(p_expr)?
+        } else if p_expr == Expr::Empty {
+            compile_func(context, f_expr)?
+        } else {
+            Filter::Or(vec![phrase(p_expr)?, compile_func(context, f_expr)?])
+        }
+    } else if f_expr == Expr::False || p_expr == Expr::False {
+        Filter::False
+    } else if f_expr == Expr::True && p_expr == Expr::True {
+        Filter::True
+    } else if f_expr == Expr::Empty || f_expr == Expr::True {
+        phrase(p_expr)?
+    } else if p_expr == Expr::Empty || p_expr == Expr::True {
+        compile_func(context, f_expr)?
+    } else {
+        Filter::And(vec![phrase(p_expr)?, compile_func(context, f_expr)?])
+    };
+
+    Ok(filter)
+}
+
+/// Mirrors `build_func_filter`.
+fn compile_func(context: &MatchContext, expr: Expr) -> Result<Filter> {
+    let list = |exprs: Vec<Expr>| {
+        exprs
+            .into_iter()
+            .map(|e| compile_func(context, e))
+            .collect::<Result<Vec<_>>>()
+    };
+
+    let filter = match expr {
+        Expr::Func(name, value) => match name.as_str() {
+            "bots" => compile_bots(context, &value)?,
+            "sort" | "order" | "tz" => Filter::True,
+            _ => Filter::Func(super::functions::matcher(context, name, value)?),
+        },
+
+        Expr::Not(inner) => Filter::Not(Box::new(compile_func(context, *inner)?)),
+        Expr::And(inner) => Filter::And(list(inner)?),
+        Expr::Or(inner) => Filter::Or(list(inner)?),
+
+        Expr::Then(_) => bail!("THEN in functional context (this is probably a bug)"),
+        Expr::Phrase(_) => bail!("phrase in functional context (this is probably a bug)"),
+
+        Expr::True => Filter::True,
+        Expr::False | Expr::Empty => Filter::False,
+    };
+
+    Ok(filter)
+}
+
+/// Mirrors `build_bots_filter`.
+fn compile_bots(context: &MatchContext, value: &str) -> Result<Filter> {
+    let negate = match value {
+        "include" => return Ok(Filter::True),
+        "exclude" => true,
+        "only" => false,
+        _ => bail!(
+            "bad 'bots' function argument: either 'exclude', 'include' or 'only' expected"
+        ),
+    };
+
+    let bots = context.options.bots.clone();
+    let filter = Filter::Func(Box::new(move |row: &Row| bots.is_bot(&row.message.author)));
+    Ok(if negate {
+        Filter::Not(Box::new(filter))
+    } else {
+        filter
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+    use sqlx::PgPool;
+
+    use super::super::set_timezone;
+    use super::*;
+    use crate::bots::BotList;
+    use crate::models::MessageKind;
+
+    const VECTOR: &str = "'cat':2 'mat':6 'on':4 'sat':3 'the':1,5";
+
+    /// `VECTOR @@ query` as PostgreSQL 15 evaluates it.
+    const TSQUERIES: &[(&str, bool)] = &[
+        ("'cat' <-> 'sat'", true),
+        ("'sat' <-> 'cat'", false),
+        ("'cat' <2> 'on'", true),
+        ("'cat' <3> 'on'", false),
+        ("'the' <4> 'the'", true),
+        ("'cat' <0> 'cat'", true),
+        ("'cat' <-> 'sat' <-> 'on'", true),
+        ("'cat' <-> ( 'sat' <-> 'on' )", true),
+        ("'sat' <-> 'on' <-> 'the' <-> 'mat'", true),
+        ("'cat' <2> ( 'on' <-> 'the' )", true),
+        // negation inside phrases
+        ("'cat' <-> !'sat'", false),
+        ("'cat' <-> !'on'", true),
+        ("!'cat' <-> 'sat'", false),
+        ("!'the' <-> 'sat'", true),
+        ("!'dog' <-> 'cat'", true),
+        ("'cat' <-> !'dog'", true),
+        ("'the' <-> !'cat'", true),
+        ("!'cat' <-> !'sat'", true),
+        ("!'dog' <-> !'cow'", true),
+        ("!( 'the' <-> 'cat' ) <-> 'sat'", false),
+        ("'on' <-> !( 'the' <-> 'mat' )", false),
+        ("'on' <-> !( 'the' <-> 'cat' )", true),
+        ("!( 'cat' <-> 'sat' )", false),
+        ("!( 'cat' <-> 'on' )", true),
+        // `&` and `|` inside phrases
+        ("'cat' <-> ( 'sat' | 'on' )", true),
+        ("'cat' <-> ( 'on' | 'mat' )", false),
+        ("( 'cat' & 'sat' ) <-> 'on'", false),
+        ("( 'cat' & 'the' ) <-> 'sat'", false),
+        ("( 'cat' | 'dog' ) <-> 'sat'", true),
+        ("( !'cat' & !'dog' ) <-> 'on'", true),
+        ("( !'cat' | 'on' ) <-> 'mat'", true),
+        // prefixes
+        ("'ca':*", true),
+        ("'x':*", false),
+        ("'ca':* <-> 'sat'", true),
+        ("'s':* <-> 'o':*", true),
+        ("'m':* <-> 'cat'", false),
+        ("!'x':* <-> 'cat'", true),
+        // plain boolean queries
+        ("'cat' & !'dog'", true),
+        ("!'cat' | 'mat'", true),
+        ("'dog'", false),
+        ("!'dog'", true),
+        ("!!'cat'", true),
+    ];
+
+    /// `text LIKE pattern`, `text ILIKE pattern`.
+    const LIKE: &[(&str, &str, bool, bool)] = &[
+        ("abc", "abc", true, true),
+        ("abc", "ABC", false, true),
+        ("abc", "a%", true, true),
+        ("abc", "%c", true, true),
+        ("abc", "%b", false, false),
+        ("abc", "a_c", true, true),
+        ("abc", "a_", false, false),
+        ("a\nc", "a_c", true, true),
+        ("100%", "100\\%", true, true),
+        ("1000", "100\\%", false, false),
+        ("a_c", "a\\_c", true, true),
+        ("abc", "a\\_c", false, false),
+        ("a\\c", "a\\\\c", true, true),
+        ("a.c", "a.c", true, true),
+        ("abc", "a.c", false, false),
+        ("a|b", "a|b", true, true),
+        ("a", "a|b", false, false),
+        ("a(b)*", "a(b)*", true, true),
+        ("ab", "a\\b", true, true),
+    ];
+
+    /// `text SIMILAR TO pattern`.
+    const SIMILAR: &[(&str, &str, bool)] = &[
+        ("abc", "abc", true),
+        ("abc", "ABC", false),
+        ("abc", "a%", true),
+        ("abc", "_b_", true),
+        ("abc", "b", false),
+        ("a", "a|b", true),
+        ("b", "a|b", true),
+        ("ab", "a|b", false),
+        ("xb", "x(a|b)", true),
+        ("x", "x(a|b)", false),
+        ("aaa", "a*", true),
+        ("", "a*", true),
+        ("aa", "a{2}", true),
+        ("aaa", "a{2,}", true),
+        ("a", "a{2,}", false),
+        ("ab", "ab?", true),
+        ("abb", "ab+", true),
+        ("a.c", "a.c", true),
+        ("abc", "a.c", false),
+        ("^a$", "^a$", true),
+        ("a", "[a-c]", true),
+        ("d", "[a-c]", false),
+        ("a|", "[|]", false),
+        ("|", "[|]", true),
+        ("100%", "100\\%", true),
+        ("1000", "100\\%", false),
+        ("a_", "a\\_", true),
+        ("ab", "a\\_", false),
+        ("a|b", "a\\|b", true),
+        ("a", "a\\|b", false),
+        ("a7", "a\\d", true),
+        ("ab", "a\\d", false),
+        ("a b", "a\\sb", true),
+        ("a\nb", "a_b", true),
+    ];
+
+    #[test]
+    fn tsqueries() {
+        let vector = TsVector::parse(VECTOR);
+        for (query, expected) in TSQUERIES {
+            let parsed = TsQuery::parse(query).unwrap();
+            assert_eq!(parsed.matches(&vector), *expected, "{}", query);
+        }
+
+        assert!(!TsQuery::parse("").unwrap().matches(&vector));
+        assert!(TsQuery::parse("'it''s'").unwrap().matches(&TsVector::parse("'it''s':1")));
+        assert!(TsQuery::parse("'cat':AB").unwrap().matches(&vector));
+        assert!(TsQuery::parse("'cat' <x> 'sat'").is_err());
+        assert!(TsQuery::parse("( 'cat'").is_err());
+        assert!(TsQuery::parse("'cat' 'sat'").is_err());
+    }
+
+    #[test]
+    fn like_patterns() {
+        for (text, pattern, like, ilike) in LIKE {
+            assert_eq!(like_regex(pattern, false).unwrap().is_match(text), *like, "{:?} LIKE {:?}", text, pattern);
+            assert_eq!(like_regex(pattern, true).unwrap().is_match(text), *ilike, "{:?} ILIKE {:?}", text, pattern);
+        }
+
+        assert!(like_regex("a\\", false).is_err());
+    }
+
+    #[test]
+    fn similar_patterns() {
+        for (text, pattern, expected) in SIMILAR {
+            let regex = similar_regex(pattern).unwrap();
+            assert_eq!(regex.is_match(text), *expected, "{:?} SIMILAR TO {:?}", text, pattern);
+        }
+
+        assert!(similar_regex("a\\").is_err());
+    }
+
+    #[test]
+    fn unsupported_regexes() {
+        for pattern in ["(a)\\1", "a(?=b)", "a(?!b)", "(?<=a)b", "(?<!a)b", "\\bword\\b", "a\\B"] {
+            assert!(check_regex(pattern).is_err(), "{}", pattern);
+            assert!(similar_regex(pattern).is_err(), "{}", pattern);
+        }
+
+        for pattern in ["a\\\\1", "(?i)abc", "(?:a|b)", "(?<name>a)", "\\d+\\s\\w", "[(?=)]"] {
+            assert!(check_regex(pattern).is_ok(), "{}", pattern);
+        }
+    }
+
+    /// Keeps the tables above honest.
+    #[sqlx::test]
+    async fn tables_agree_with_postgres(db: PgPool) {
+        for (query, expected) in TSQUERIES {
+            let (matches,): (bool,) = sqlx::query_as("SELECT $1::tsvector @@ $2::tsquery")
+                .bind(VECTOR)
+                .bind(query)
+                .fetch_one(&db)
+                .await
+                .unwrap();
+            assert_eq!(matches, *expected, "{}", query);
+        }
+
+        for (text, pattern, like, ilike) in LIKE {
+            let (l, i): (bool, bool) = sqlx::query_as("SELECT $1 LIKE $2, $1 ILIKE $2")
+                .bind(text)
+                .bind(pattern)
+                .fetch_one(&db)
+                .await
+                .unwrap();
+            assert_eq!((l, i), (*like, *ilike), "{:?} LIKE {:?}", text, pattern);
+        }
+
+        for (text, pattern, expected) in SIMILAR {
+            let (matches,): (bool,) = sqlx::query_as("SELECT $1 SIMILAR TO $2")
+                .bind(text)
+                .bind(pattern)
+                .fetch_one(&db)
+                .await
+                .unwrap();
+            assert_eq!(matches, *expected, "{:?} SIMILAR TO {:?}", text, pattern);
+        }
+    }
+
+    /// Messages selected by the SQL filter of `query`, by ID.
+    async fn select(db: &PgPool, query: &str, options: QueryOptions) -> Vec<i32> {
+        let mut sql = QueryBuilder::default();
+        let mut bindings = Bindings::new(options.clone());
+        sql.sql("SELECT msg_id FROM messages WHERE ");
+        let expr = Expr::parse(query).unwrap().normalize().unwrap();
+        super::super::build_filter(&mut sql, &mut bindings, &mut vec![], expr).unwrap();
+        sql.sql(" ORDER BY msg_id");
+
+        let mut tx = db.begin().await.unwrap();
+        set_timezone(&mut tx, options.timezone).await.unwrap();
+        let rows = tx.fetch_all(ExecWrapper(&sql, bindings)).await.unwrap();
+        rows.iter().map(|row| row.get(0)).collect()
+    }
+
+    /// Every function filter, matched one message at a time and in SQL.
+    #[sqlx::test]
+    async fn matchers_agree_with_sql(db: PgPool) {
+        let messages = [
+            ("2023-03-31 10:00:00", "#chan", "alice", MessageKind::Message, "The cat sat on the mat"),
+            ("2023-03-31 21:30:00", "#chan", "ali", MessageKind::Message, "100% sure_thing"),
+            ("2023-04-01 09:15:00", "#other", "bob", MessageKind::Action, "waves at Alice"),
+            ("2023-04-01 22:30:00", "#chan", "bob", MessageKind::Join, ""),
+            ("2023-04-02 08:00:00", "#chan", "GitBot", MessageKind::Message, "build 42 passed"),
+        ];
+        for (time, channel, author, kind, body) in messages {
+            let time = NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S").unwrap();
+            sqlx::query(
+                "INSERT INTO messages (msg_timestamp, msg_offset, msg_channel, msg_author, msg_kind, msg_body) \
+                 VALUES ($1, 0, $2, $3, $4, $5)",
+            )
+            .bind(time)
+            .bind(channel)
+            .bind(author)
+            .bind(kind.as_str())
+            .bind(body)
+            .execute(&db)
+            .await
+            .unwrap();
+        }
+        sqlx::query("INSERT INTO aliases VALUES ('alice', 'ali'), ('alice', 'al')")
+            .execute(&db)
+            .await
+            .unwrap();
+
+        let rows: Vec<Row> = sqlx::query(
+            "SELECT msg_id, msg_timestamp, msg_author, msg_body, msg_offset, msg_kind, msg_channel, msg_tsv::text \
+             FROM messages ORDER BY msg_id",
+        )
+        .fetch_all(&db)
+        .await
+        .unwrap()
+        .iter()
+        .map(|row| Row {
+            message: Message {
+                id: row.get(0),
+                time: row.get(1),
+                author: row.get(2),
+                body: row.get(3),
+                offset: row.get(4),
+                kind: row.get::<String, _>(5).parse().unwrap(),
+                highlight: None,
+            },
+            channel: row.get(6),
+            tsv: TsVector::parse(row.get(7)),
+        })
+        .collect();
+
+        let options = QueryOptions {
+            timezone: chrono_tz::Europe::Berlin,
+            bots: BotList::new(&[String::from("*bot")]),
+        };
+
+        let queries: &[(&str, &[i32])] = &[
+            ("author:alice", &[1, 2]),
+            ("author:al", &[1, 2]),
+            ("author:bob", &[3, 4]),
+            ("raw:alice", &[1]),
+            ("channel:#other", &[3]),
+            ("kind:action", &[3]),
+            ("NOT kind:message", &[3, 4]),
+            ("length:0", &[4]),
+            ("length:>14", &[1, 2, 5]),
+            ("length:<=15", &[2, 3, 4, 5]),
+            ("contains:Alice", &[3]),
+            ("icontains:alice", &[3]),
+            ("like:%cat%", &[1]),
+            ("like:%\\_thing", &[2]),
+            ("ilike:the%", &[1]),
+            ("regex:\\d+ passed", &[5]),
+            ("regex:^W", &[]),
+            ("iregex:^W", &[3]),
+            ("similarto:\"%(cat|dog)%\"", &[1]),
+            ("similarto:\\d%", &[2]),
+            ("date:2023-04-01", &[3]),
+            ("date:<2023-04-01", &[1, 2]),
+            ("date:>=2023-04-01", &[3, 4, 5]),
+            ("time:>=23:00", &[2]),
+            ("time:<11:00", &[4, 5]),
+            ("datetime:>2023-04-01T11:15:00", &[4, 5]),
+            ("bots:only", &[5]),
+            ("bots:exclude", &[1, 2, 3, 4]),
+            ("cat sat", &[1]),
+            ("\"cat sat\"", &[1]),
+            ("cat OR kind:join", &[1, 4]),
+            ("NOT wave", &[1, 2, 4, 5]),
+        ];
+
+        for (query, expected) in queries {
+            assert_eq!(&select(&db, query, options.clone()).await, expected, "SQL of {}", query);
+
+            let mut conn = db.acquire().await.unwrap();
+            let expr = Expr::parse(query).unwrap();
+            let filter = MessageFilter::new(&mut conn, expr, options.clone()).await.unwrap();
+            let matched: Vec<i32> = rows
+                .iter()
+                .filter(|row| filter.matches(row))
+                .map(|row| row.message.id)
+                .collect();
+            assert_eq!(&matched, expected, "{}", query);
+        }
+    }
+}
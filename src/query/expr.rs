@@ -2,7 +2,7 @@ use std::fmt::{self, Debug, Formatter};
 
 use anyhow::{anyhow, Result};
 
-use crate::query::parser::parse;
+use crate::query::parser::{parse, ParseError};
 
 #[derive(Clone, Eq, Ord, PartialEq, PartialOrd)]
 pub enum Expr {
@@ -59,32 +59,23 @@ impl Debug for Expr {
 }
 
 impl Expr {
-    pub fn parse(input: &str) -> Result<Expr> {
-        parse(input).map_err(|_| anyhow!("malformed query"))
+    pub fn parse(input: &str) -> std::result::Result<Expr, ParseError> {
+        parse(input)
     }
 
     pub fn is_compound(&self) -> bool {
-        match self {
-            Expr::Then(..) => true,
-            Expr::And(..) => true,
-            Expr::Or(..) => true,
-            Expr::Not(..) => true,
-            _ => false,
-        }
+        matches!(
+            self,
+            Expr::Then(..) | Expr::And(..) | Expr::Or(..) | Expr::Not(..)
+        )
     }
 
     pub fn is_and(&self) -> bool {
-        match self {
-            Expr::And(..) => true,
-            _ => false,
-        }
+        matches!(self, Expr::And(..))
     }
 
     pub fn is_or(&self) -> bool {
-        match self {
-            Expr::Or(..) => true,
-            _ => false,
-        }
+        matches!(self, Expr::Or(..))
     }
 
     pub fn has_phrases(&self) -> bool {
@@ -109,9 +100,21 @@ impl Expr {
         }
     }
 
+    /// Whether the function `key` appears anywhere in the expression.
+    pub fn has_func(&self, key: &str) -> bool {
+        match self {
+            Expr::Func(k, _) => k == key,
+            Expr::Not(inner) => inner.has_func(key),
+            Expr::Then(inner) | Expr::And(inner) | Expr::Or(inner) => {
+                inner.iter().any(|e| e.has_func(key))
+            }
+            _ => false,
+        }
+    }
+
     pub fn get_func(&self, key: &str) -> Option<&str> {
         match self {
-            Expr::Func(k, v) if k == key => Some(&v),
+            Expr::Func(k, v) if k == key => Some(v),
             Expr::And(inner) => inner.iter().flat_map(|e| e.get_func(key)).next(),
             _ => None,
         }
@@ -128,6 +131,7 @@ impl Expr {
         Ok(())
     }
 
+    #[allow(clippy::wrong_self_convention)]
     pub fn to_nnf(self) -> Expr {
         let is_or = self.is_or();
         let is_and = self.is_and();
@@ -186,10 +190,10 @@ impl Expr {
 
         fn has_conflict(list: &[Expr]) -> bool {
             for expr in list {
-                if list.iter().any(|other| match other {
-                    Expr::Not(inner) if &**inner == expr => true,
-                    _ => false,
-                }) {
+                if list
+                    .iter()
+                    .any(|other| matches!(other, Expr::Not(inner) if &**inner == expr))
+                {
                     return true;
                 }
             }
@@ -263,7 +267,7 @@ impl Expr {
 
         fn expand_vec(exprs: &mut Vec<Expr>, limit: &mut usize) -> Result<bool> {
             let left_pos = exprs.iter().position(|e| match e {
-                Expr::Or(inner) => should_expand(&inner),
+                Expr::Or(inner) => should_expand(inner),
                 _ => false,
             });
 
@@ -374,9 +378,12 @@ impl Expr {
 
             Expr::Or(mut exprs) => {
                 for expr in &mut exprs {
-                    if expr.get_func("sort").is_some() || expr.get_func("order").is_some() {
+                    if expr.get_func("sort").is_some()
+                        || expr.get_func("order").is_some()
+                        || expr.get_func("tz").is_some()
+                    {
                         return Err(anyhow!(
-                            "sorting functions inside OR operands are disallowed"
+                            "sorting and time zone functions inside OR operands are disallowed"
                         ));
                     }
 
@@ -387,7 +394,7 @@ impl Expr {
             }
 
             Expr::Func(key, value) => match key.as_str() {
-                "sort" | "order" | "bots" if level > 1 => {
+                "sort" | "order" | "bots" | "tz" if level > 1 => {
                     Err(anyhow!("`{}` function should be at the top level", key))
                 }
                 _ => Ok(Expr::Func(key, value)),
@@ -18,7 +18,12 @@ fn author(query: &mut QueryBuilder, bindings: &mut Bindings, value: String) -> R
     Ok(())
 }
 
-function!("author", author);
+fn author_matcher(context: &MatchContext, value: String) -> Result<Matcher> {
+    let names = context.author_names(&value);
+    Ok(Box::new(move |row: &Row| names.contains(&row.message.author)))
+}
+
+function!("author", author, author_matcher);
 
 fn rawauthor(query: &mut QueryBuilder, bindings: &mut Bindings, value: String) -> Result<()> {
     query.sql("msg_author = ");
@@ -26,4 +31,8 @@ fn rawauthor(query: &mut QueryBuilder, bindings: &mut Bindings, value: String) -
     Ok(())
 }
 
-function!("raw", rawauthor);
+fn rawauthor_matcher(_: &MatchContext, value: String) -> Result<Matcher> {
+    Ok(Box::new(move |row: &Row| row.message.author == value))
+}
+
+function!("raw", rawauthor, rawauthor_matcher);
@@ -6,4 +6,8 @@ fn channel(query: &mut QueryBuilder, bindings: &mut Bindings, value: String) ->
     Ok(())
 }
 
-function!("channel", channel);
+fn channel_matcher(_: &MatchContext, value: String) -> Result<Matcher> {
+    Ok(Box::new(move |row: &Row| row.channel == value))
+}
+
+function!("channel", channel, channel_matcher);
@@ -1,19 +1,32 @@
 use super::*;
+use crate::query::eval::like_regex;
+
+fn pattern(value: &str) -> String {
+    format!("%{}%", value.replace("%", "%%").replace("_", "__"))
+}
 
 fn contains(query: &mut QueryBuilder, bindings: &mut Bindings, value: String) -> Result<()> {
-    let pat = format!("%{}%", value.replace("%", "%%").replace("_", "__"));
     query.sql("msg_body LIKE ");
-    query.binding(bindings, pat);
+    query.binding(bindings, pattern(&value));
     Ok(())
 }
 
-function!("contains", contains);
+fn contains_matcher(_: &MatchContext, value: String) -> Result<Matcher> {
+    let regex = like_regex(&pattern(&value), false)?;
+    Ok(Box::new(move |row: &Row| regex.is_match(&row.message.body)))
+}
+
+function!("contains", contains, contains_matcher);
 
 fn icontains(query: &mut QueryBuilder, bindings: &mut Bindings, value: String) -> Result<()> {
-    let pat = format!("%{}%", value.replace("%", "%%").replace("_", "__"));
     query.sql("msg_body ILIKE ");
-    query.binding(bindings, pat);
+    query.binding(bindings, pattern(&value));
     Ok(())
 }
 
-function!("icontains", icontains);
+fn icontains_matcher(_: &MatchContext, value: String) -> Result<Matcher> {
+    let regex = like_regex(&pattern(&value), true)?;
+    Ok(Box::new(move |row: &Row| regex.is_match(&row.message.body)))
+}
+
+function!("icontains", icontains, icontains_matcher);
@@ -1,71 +1,438 @@
 use super::*;
-use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use crate::timezone::{local_to_utc, utc_to_local};
+use std::cmp::Ordering;
+use chrono::{Datelike, Duration, Months, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Utc};
+use chrono_tz::Tz;
 
-fn date(query: &mut QueryBuilder, bindings: &mut Bindings, value: String) -> Result<()> {
-    let valid_opers = ["!=", ">=", "<=", "=", "<", ">"];
-    for oper in &valid_opers {
-        if !value.starts_with(oper) {
-            continue;
+const OPERATORS: [&str; 6] = ["!=", ">=", "<=", "=", "<", ">"];
+
+fn split_operator(value: &str) -> (&'static str, &str) {
+    for oper in OPERATORS {
+        if let Some(rest) = value.strip_prefix(oper) {
+            return (oper, rest);
         }
-        let date = value[oper.len()..]
-            .parse::<NaiveDate>()
-            .context("Invalid date")?;
-        query.sql("msg_timestamp::date ");
-        query.sql(oper);
-        query.binding(bindings, date);
-        return Ok(());
-    }
-
-    let date = value.parse::<NaiveDate>().context("Invalid date")?;
-    query.sql("msg_timestamp::date =");
-    query.binding(bindings, date);
-    Ok(())
+    }
+
+    ("=", value)
+}
+
+/// A half-open `[start, end)` span of time. Missing bounds are unbounded.
+#[derive(Debug, PartialEq, Eq)]
+struct Period {
+    start: Option<NaiveDateTime>,
+    end: Option<NaiveDateTime>,
+    /// Given as an offset like `-7d`, so `>` means "within the last 7 days",
+    /// including the day (or second) the offset falls on.
+    relative: bool,
 }
 
-function!("date", date);
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Precision {
+    Day,
+    Second,
+}
 
-fn time(query: &mut QueryBuilder, bindings: &mut Bindings, value: String) -> Result<()> {
-    let valid_opers = ["!=", ">=", "<=", "=", "<", ">"];
-    for oper in &valid_opers {
-        if !value.starts_with(oper) {
-            continue;
+/// Parses offsets into the past like `-7d` or `-3h`.
+///
+/// Units: `s`, `m` (minutes), `h`, `d`, `w`, `mo` (months) and `y`.
+fn parse_relative(value: &str, now: NaiveDateTime) -> Option<NaiveDateTime> {
+    let rest = value.strip_prefix('-')?;
+    let split = rest.find(|c: char| !c.is_ascii_digit())?;
+    let (amount, unit) = rest.split_at(split);
+    let amount: u32 = amount.parse().ok()?;
+
+    let duration = match unit {
+        "s" => Duration::seconds(amount.into()),
+        "m" => Duration::minutes(amount.into()),
+        "h" => Duration::hours(amount.into()),
+        "d" => Duration::days(amount.into()),
+        "w" => Duration::weeks(amount.into()),
+        "mo" => return now.checked_sub_months(Months::new(amount)),
+        "y" => return now.checked_sub_months(Months::new(amount.checked_mul(12)?)),
+        _ => return None,
+    };
+
+    now.checked_sub_signed(duration)
+}
+
+/// Parses a whole day, month (`2023-05`) or year (`2022`), as well as
+/// `today`, `yesterday` and relative offsets, into the days it covers.
+fn parse_date_span(value: &str, now: NaiveDateTime) -> Option<(NaiveDate, NaiveDate)> {
+    let today = now.date();
+
+    match value {
+        "today" => return Some((today, today.succ_opt()?)),
+        "yesterday" => return Some((today.pred_opt()?, today)),
+        _ => {}
+    }
+
+    if let Some(datetime) = parse_relative(value, now) {
+        let date = datetime.date();
+        return Some((date, date.succ_opt()?));
+    }
+
+    if let Ok(date) = value.parse::<NaiveDate>() {
+        return Some((date, date.succ_opt()?));
+    }
+
+    let parts: Vec<&str> = value.split('-').collect();
+    match parts.as_slice() {
+        [year] if year.len() == 4 => {
+            let start = NaiveDate::from_ymd_opt(year.parse().ok()?, 1, 1)?;
+            Some((start, start.with_year(start.year() + 1)?))
+        }
+        [year, month] if year.len() == 4 && month.len() == 2 => {
+            let start = NaiveDate::from_ymd_opt(year.parse().ok()?, month.parse().ok()?, 1)?;
+            Some((start, start.checked_add_months(Months::new(1))?))
         }
-        let time = value[oper.len()..]
-            .parse::<NaiveTime>()
-            .context("Invalid time")?;
-        query.sql("msg_timestamp::time ");
-        query.sql(oper);
-        query.binding(bindings, time);
-        return Ok(());
+        _ => None,
     }
+}
+
+fn parse_point(
+    value: &str,
+    precision: Precision,
+    now: NaiveDateTime,
+) -> Option<(NaiveDateTime, NaiveDateTime)> {
+    if precision == Precision::Second {
+        let formats = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S"];
+        let datetime = formats
+            .iter()
+            .find_map(|fmt| NaiveDateTime::parse_from_str(value, fmt).ok())
+            .or_else(|| parse_relative(value, now).and_then(|t| t.with_nanosecond(0)));
+
+        if let Some(datetime) = datetime {
+            return Some((datetime, datetime + Duration::seconds(1)));
+        }
+    }
+
+    let (start, end) = parse_date_span(value, now)?;
+    Some((start.and_time(NaiveTime::MIN), end.and_time(NaiveTime::MIN)))
+}
+
+/// Parses a single point or an `a..b` range, where either side may be omitted.
+/// Values are wall-clock times in `tz`; the resulting bounds are in UTC.
+fn parse_period(value: &str, precision: Precision, tz: Tz) -> Option<Period> {
+    let now = Utc::now().with_timezone(&tz).naive_local();
+    parse_period_at(value, precision, tz, now)
+}
+
+/// [`parse_period`] with relative values resolved against `now`, in `tz`.
+fn parse_period_at(value: &str, precision: Precision, tz: Tz, now: NaiveDateTime) -> Option<Period> {
+    let point = |value| parse_point(value, precision, now);
+
+    match value.split_once("..") {
+        Some((start, end)) => {
+            let start = match start {
+                "" => None,
+                start => Some(point(start)?.0),
+            };
+            let end = match end {
+                "" => None,
+                end => Some(point(end)?.1),
+            };
+            Some(Period {
+                start: start.map(|t| local_to_utc(tz, t)),
+                end: end.map(|t| local_to_utc(tz, t)),
+                relative: false,
+            })
+        }
+        None => {
+            let (start, end) = point(value)?;
+            Some(Period {
+                start: Some(local_to_utc(tz, start)),
+                end: Some(local_to_utc(tz, end)),
+                relative: parse_relative(value, now).is_some(),
+            })
+        }
+    }
+}
+
+fn bound(
+    query: &mut QueryBuilder,
+    bindings: &mut Bindings,
+    oper: &str,
+    value: Option<NaiveDateTime>,
+    unbounded: &str,
+) {
+    match value {
+        Some(value) => {
+            query.sql("msg_timestamp ");
+            query.sql(oper);
+            query.sql(" ");
+            query.binding(bindings, value);
+        }
+        None => query.sql(unbounded),
+    }
+}
+
+/// `>` means after the end of an absolute period, but since the start of a
+/// relative one: `date:>-7d` is the last 7 days, not the 6 after them.
+fn period_filter(query: &mut QueryBuilder, bindings: &mut Bindings, oper: &str, period: Period) {
+    match oper {
+        "=" | "!=" => {
+            if oper == "!=" {
+                query.sql("NOT ");
+            }
+            query.sql("(");
+            bound(query, bindings, ">=", period.start, "TRUE");
+            query.sql(" AND ");
+            bound(query, bindings, "<", period.end, "TRUE");
+            query.sql(")");
+        }
+        "<" => bound(query, bindings, "<", period.start, "FALSE"),
+        "<=" => bound(query, bindings, "<", period.end, "TRUE"),
+        ">" if period.relative => bound(query, bindings, ">=", period.start, "TRUE"),
+        ">" => bound(query, bindings, ">=", period.end, "FALSE"),
+        ">=" => bound(query, bindings, ">=", period.start, "TRUE"),
+        _ => unreachable!(),
+    }
+}
+
+/// Evaluates `period_filter` on a single message.
+fn period_matcher(oper: &'static str, period: Period) -> Matcher {
+    Box::new(move |row: &Row| {
+        let time = row.message.time;
+        let after_start = period.start.is_none_or(|start| time >= start);
+        let before_end = period.end.is_none_or(|end| time < end);
+        match oper {
+            "=" => after_start && before_end,
+            "!=" => !(after_start && before_end),
+            "<" => period.start.is_some_and(|start| time < start),
+            "<=" => before_end,
+            ">" if period.relative => after_start,
+            ">" => period.end.is_some_and(|end| time >= end),
+            ">=" => after_start,
+            _ => unreachable!(),
+        }
+    })
+}
 
+fn date(query: &mut QueryBuilder, bindings: &mut Bindings, value: String) -> Result<()> {
+    let (oper, value) = split_operator(&value);
+    let period = parse_period(value, Precision::Day, bindings.options.timezone)
+        .context("Invalid date")?;
+    period_filter(query, bindings, oper, period);
+    Ok(())
+}
+
+fn date_matcher(context: &MatchContext, value: String) -> Result<Matcher> {
+    let (oper, value) = split_operator(&value);
+    let period = parse_period(value, Precision::Day, context.options.timezone)
+        .context("Invalid date")?;
+    Ok(period_matcher(oper, period))
+}
+
+function!("date", date, date_matcher);
+
+fn time(query: &mut QueryBuilder, bindings: &mut Bindings, value: String) -> Result<()> {
+    let (oper, value) = split_operator(&value);
     let time = value.parse::<NaiveTime>().context("Invalid time")?;
-    query.sql("msg_timestamp::time =");
+    // Relies on the session time zone set by `set_timezone`.
+    query.sql("(msg_timestamp AT TIME ZONE 'UTC')::time ");
+    query.sql(oper);
     query.binding(bindings, time);
     Ok(())
 }
 
-function!("time", time);
+fn time_matcher(context: &MatchContext, value: String) -> Result<Matcher> {
+    let (oper, value) = split_operator(&value);
+    let time = value.parse::<NaiveTime>().context("Invalid time")?;
+    let tz = context.options.timezone;
+    Ok(Box::new(move |row: &Row| {
+        let ordering = utc_to_local(tz, row.message.time).time().cmp(&time);
+        match oper {
+            "!=" => ordering != Ordering::Equal,
+            ">=" => ordering != Ordering::Less,
+            "<=" => ordering != Ordering::Greater,
+            "<" => ordering == Ordering::Less,
+            ">" => ordering == Ordering::Greater,
+            _ => ordering == Ordering::Equal,
+        }
+    }))
+}
+
+function!("time", time, time_matcher);
 
 fn datetime(query: &mut QueryBuilder, bindings: &mut Bindings, value: String) -> Result<()> {
-    let valid_opers = ["!=", ">=", "<=", "=", "<", ">"];
-    let fmt = "%Y-%m-%d %H:%M:%S";
-    for oper in &valid_opers {
-        if !value.starts_with(oper) {
-            continue;
+    let (oper, value) = split_operator(&value);
+    let period = parse_period(value, Precision::Second, bindings.options.timezone)
+        .context("Invalid datetime")?;
+    period_filter(query, bindings, oper, period);
+    Ok(())
+}
+
+fn datetime_matcher(context: &MatchContext, value: String) -> Result<Matcher> {
+    let (oper, value) = split_operator(&value);
+    let period = parse_period(value, Precision::Second, context.options.timezone)
+        .context("Invalid datetime")?;
+    Ok(period_matcher(oper, period))
+}
+
+function!("datetime", datetime, datetime_matcher);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn day(value: &str) -> NaiveDate {
+        value.parse().unwrap()
+    }
+
+    const NOW: &str = "2023-03-31 15:30:45";
+
+    #[test]
+    fn relative_offsets() {
+        let now = at(NOW);
+        let relative = |value| parse_relative(value, now);
+
+        assert_eq!(relative("-10s"), Some(at("2023-03-31 15:30:35")));
+        assert_eq!(relative("-90m"), Some(at("2023-03-31 14:00:45")));
+        assert_eq!(relative("-3h"), Some(at("2023-03-31 12:30:45")));
+        assert_eq!(relative("-7d"), Some(at("2023-03-24 15:30:45")));
+        assert_eq!(relative("-2w"), Some(at("2023-03-17 15:30:45")));
+        // clamped to the end of the shorter month
+        assert_eq!(relative("-1mo"), Some(at("2023-02-28 15:30:45")));
+        assert_eq!(relative("-1y"), Some(at("2022-03-31 15:30:45")));
+
+        for value in ["7d", "-d", "-7", "-7x", "-", "--7d", "-7 d", "-99999999999y"] {
+            assert_eq!(relative(value), None, "{}", value);
         }
-        let datetime =
-            NaiveDateTime::parse_from_str(&value[oper.len()..], fmt).context("Invalid datetime")?;
-        query.sql("msg_timestamp ");
-        query.sql(oper);
-        query.binding(bindings, datetime);
-        return Ok(());
     }
 
-    let datetime = NaiveDateTime::parse_from_str(&value, fmt).context("Invalid datetime")?;
-    query.sql("msg_timestamp =");
-    query.binding(bindings, datetime);
-    Ok(())
-}
+    #[test]
+    fn date_spans() {
+        let now = at(NOW);
+        let span = |value| parse_date_span(value, now);
+
+        assert_eq!(span("today"), Some((day("2023-03-31"), day("2023-04-01"))));
+        assert_eq!(span("yesterday"), Some((day("2023-03-30"), day("2023-03-31"))));
+        assert_eq!(span("-7d"), Some((day("2023-03-24"), day("2023-03-25"))));
+        assert_eq!(span("2023-05-17"), Some((day("2023-05-17"), day("2023-05-18"))));
+        assert_eq!(span("2023-12"), Some((day("2023-12-01"), day("2024-01-01"))));
+        assert_eq!(span("2024-02"), Some((day("2024-02-01"), day("2024-03-01"))));
+        assert_eq!(span("2022"), Some((day("2022-01-01"), day("2023-01-01"))));
 
-function!("datetime", datetime);
+        for value in ["2023-13", "2023-5", "23", "2023-02-30", "tomorrow", ""] {
+            assert_eq!(span(value), None, "{}", value);
+        }
+    }
+
+    #[test]
+    fn ranges() {
+        let now = at(NOW);
+        let period = |value, precision| parse_period_at(value, precision, Tz::UTC, now).unwrap();
+
+        assert_eq!(
+            period("2023-01-01..2023-01-31", Precision::Day),
+            Period {
+                start: Some(at("2023-01-01 00:00:00")),
+                end: Some(at("2023-02-01 00:00:00")),
+                relative: false,
+            }
+        );
+        assert_eq!(
+            period("2023-01..2023-02", Precision::Day),
+            Period {
+                start: Some(at("2023-01-01 00:00:00")),
+                end: Some(at("2023-03-01 00:00:00")),
+                relative: false,
+            }
+        );
+        assert_eq!(
+            period("2023-01-01..", Precision::Day),
+            Period {
+                start: Some(at("2023-01-01 00:00:00")),
+                end: None,
+                relative: false,
+            }
+        );
+        assert_eq!(
+            period("..2023-01-31", Precision::Day),
+            Period {
+                start: None,
+                end: Some(at("2023-02-01 00:00:00")),
+                relative: false,
+            }
+        );
+        assert_eq!(
+            period("2023-01-01 10:00:00..2023-01-01T11:00:00", Precision::Second),
+            Period {
+                start: Some(at("2023-01-01 10:00:00")),
+                end: Some(at("2023-01-01 11:00:01")),
+                relative: false,
+            }
+        );
+        assert_eq!(
+            period("-3h", Precision::Second),
+            Period {
+                start: Some(at("2023-03-31 12:30:45")),
+                end: Some(at("2023-03-31 12:30:46")),
+                relative: true,
+            }
+        );
+        // a day for `date:`
+        assert_eq!(
+            period("-7d", Precision::Day),
+            Period {
+                start: Some(at("2023-03-24 00:00:00")),
+                end: Some(at("2023-03-25 00:00:00")),
+                relative: true,
+            }
+        );
+        assert!(!period("today", Precision::Day).relative);
+
+        for value in ["2023-01-01...", "2023-01-01..x", "x..2023-01-01", "2023-01-01 10:00:00"] {
+            assert!(parse_period_at(value, Precision::Day, Tz::UTC, now).is_none(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn ranges_in_time_zone() {
+        let tz: Tz = "Europe/Helsinki".parse().unwrap();
+        let period = parse_period_at("2023-01-01..2023-07-01", Precision::Day, tz, at(NOW)).unwrap();
+
+        // EET in winter, EEST in summer
+        assert_eq!(period.start, Some(at("2022-12-31 22:00:00")));
+        assert_eq!(period.end, Some(at("2023-07-01 21:00:00")));
+    }
+
+    fn filter(oper: &str, period: Period) -> (String, Vec<String>) {
+        let mut query = QueryBuilder::default();
+        let mut bindings = Bindings::default();
+        period_filter(&mut query, &mut bindings, oper, period);
+        (query.sql, bindings.values)
+    }
+
+    #[test]
+    fn comparisons() {
+        let period = |relative| Period {
+            start: Some(at("2023-03-24 00:00:00")),
+            end: Some(at("2023-03-25 00:00:00")),
+            relative,
+        };
+        let start = vec!["2023-03-24T00:00:00".to_owned()];
+        let end = vec!["2023-03-25T00:00:00".to_owned()];
+
+        assert_eq!(filter(">", period(false)), ("msg_timestamp >= $1".to_owned(), end.clone()));
+        assert_eq!(filter(">", period(true)), ("msg_timestamp >= $1".to_owned(), start.clone()));
+        assert_eq!(filter(">=", period(true)), ("msg_timestamp >= $1".to_owned(), start.clone()));
+        assert_eq!(filter("<", period(true)), ("msg_timestamp < $1".to_owned(), start));
+        assert_eq!(filter("<=", period(false)), ("msg_timestamp < $1".to_owned(), end));
+        assert_eq!(
+            filter("!=", period(false)).0,
+            "NOT (msg_timestamp >= $1 AND msg_timestamp < $2)"
+        );
+
+        let open = Period {
+            start: None,
+            end: None,
+            relative: false,
+        };
+        assert_eq!(filter("=", open).0, "(TRUE AND TRUE)");
+    }
+}
@@ -0,0 +1,22 @@
+use super::*;
+use crate::models::MessageKind;
+
+fn parse_kind(value: &str) -> Result<MessageKind> {
+    value.parse().context(
+        "bad 'kind' function argument: one of 'message', 'action', 'join', 'part', 'quit', 'nick' or 'topic' expected",
+    )
+}
+
+fn kind(query: &mut QueryBuilder, bindings: &mut Bindings, value: String) -> Result<()> {
+    let kind = parse_kind(&value)?;
+    query.sql("msg_kind = ");
+    query.binding(bindings, kind.as_str());
+    Ok(())
+}
+
+fn kind_matcher(_: &MatchContext, value: String) -> Result<Matcher> {
+    let kind = parse_kind(&value)?;
+    Ok(Box::new(move |row: &Row| row.message.kind == kind))
+}
+
+function!("kind", kind, kind_matcher);
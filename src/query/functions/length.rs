@@ -1,23 +1,39 @@
 use super::*;
 
-fn length(query: &mut QueryBuilder, bindings: &mut Bindings, value: String) -> Result<()> {
+fn parse_length(value: &str) -> Result<(&'static str, i32)> {
     let valid_opers = ["!=", ">=", "<=", "=", "<", ">"];
-    for oper in &valid_opers {
-        if !value.starts_with(oper) {
-            continue;
+    for oper in valid_opers {
+        if let Some(rest) = value.strip_prefix(oper) {
+            let length = rest.parse::<i32>().context("Invalid integer")?;
+            return Ok((oper, length));
         }
-        query.sql("char_length(msg_body) ");
-        query.sql(oper);
-        let length = value[oper.len()..]
-            .parse::<i32>()
-            .context("Invalid integer")?;
-        query.binding(bindings, length);
-        return Ok(());
     }
     let length = value.parse::<i32>().context("Invalid integer")?;
-    query.sql("char_length(msg_body) =");
+    Ok(("=", length))
+}
+
+fn length(query: &mut QueryBuilder, bindings: &mut Bindings, value: String) -> Result<()> {
+    let (oper, length) = parse_length(&value)?;
+    query.sql("char_length(msg_body) ");
+    query.sql(oper);
     query.binding(bindings, length);
     Ok(())
 }
 
-function!("length", length);
+fn length_matcher(_: &MatchContext, value: String) -> Result<Matcher> {
+    let (oper, length) = parse_length(&value)?;
+    let length = i64::from(length);
+    Ok(Box::new(move |row: &Row| {
+        let actual = row.message.body.chars().count() as i64;
+        match oper {
+            "!=" => actual != length,
+            ">=" => actual >= length,
+            "<=" => actual <= length,
+            "<" => actual < length,
+            ">" => actual > length,
+            _ => actual == length,
+        }
+    }))
+}
+
+function!("length", length, length_matcher);
@@ -1,4 +1,5 @@
 use super::*;
+use crate::query::eval::like_regex;
 
 fn like(query: &mut QueryBuilder, bindings: &mut Bindings, value: String) -> Result<()> {
     query.sql("msg_body LIKE ");
@@ -6,7 +7,12 @@ fn like(query: &mut QueryBuilder, bindings: &mut Bindings, value: String) -> Res
     Ok(())
 }
 
-function!("like", like);
+fn like_matcher(_: &MatchContext, value: String) -> Result<Matcher> {
+    let regex = like_regex(&value, false)?;
+    Ok(Box::new(move |row: &Row| regex.is_match(&row.message.body)))
+}
+
+function!("like", like, like_matcher);
 
 fn ilike(query: &mut QueryBuilder, bindings: &mut Bindings, value: String) -> Result<()> {
     query.sql("msg_body ILIKE ");
@@ -14,4 +20,9 @@ fn ilike(query: &mut QueryBuilder, bindings: &mut Bindings, value: String) -> Re
     Ok(())
 }
 
-function!("ilike", ilike);
+fn ilike_matcher(_: &MatchContext, value: String) -> Result<Matcher> {
+    let regex = like_regex(&value, true)?;
+    Ok(Box::new(move |row: &Row| regex.is_match(&row.message.body)))
+}
+
+function!("ilike", ilike, ilike_matcher);
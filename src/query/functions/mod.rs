@@ -1,8 +1,9 @@
 macro_rules! function {
-    ($name:literal, $func:ident) => {
+    ($name:literal, $func:ident, $matcher:ident) => {
         inventory::submit!(SearchFunction {
             name: $name,
-            handler: $func
+            handler: $func,
+            matcher: $matcher
         });
     };
 }
@@ -11,17 +12,21 @@ mod author;
 mod channel;
 mod contains;
 mod datetime;
+mod kind;
 mod length;
 mod like;
 mod regex;
 mod similarto;
 
+use super::eval::{MatchContext, Matcher, Row};
 use super::{Bindings, QueryBuilder, Result};
 use anyhow::{anyhow, Context};
 
 struct SearchFunction {
     name: &'static str,
     handler: fn(&mut QueryBuilder, &mut Bindings, String) -> Result<()>,
+    /// Evaluates the same filter as `handler` on a single message.
+    matcher: fn(&MatchContext, String) -> Result<Matcher>,
 }
 
 inventory::collect!(SearchFunction);
@@ -40,3 +45,13 @@ pub fn handle(
 
     Err(anyhow!("unknown function '{}'", key))
 }
+
+pub fn matcher(context: &MatchContext, key: String, value: String) -> Result<Matcher> {
+    for func in inventory::iter::<SearchFunction> {
+        if key.eq_ignore_ascii_case(func.name) {
+            return (func.matcher)(context, value);
+        }
+    }
+
+    Err(anyhow!("unknown function '{}'", key))
+}
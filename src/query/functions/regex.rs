@@ -1,4 +1,16 @@
 use super::*;
+use crate::query::eval::check_regex;
+use ::regex::RegexBuilder;
+
+fn body_regex(value: &str, case_insensitive: bool) -> Result<Matcher> {
+    check_regex(value)?;
+    let regex = RegexBuilder::new(value)
+        .case_insensitive(case_insensitive)
+        .dot_matches_new_line(true)
+        .build()
+        .context("Invalid regular expression")?;
+    Ok(Box::new(move |row: &Row| regex.is_match(&row.message.body)))
+}
 
 fn regex(query: &mut QueryBuilder, bindings: &mut Bindings, value: String) -> Result<()> {
     query.sql("msg_body ~ ");
@@ -6,7 +18,11 @@ fn regex(query: &mut QueryBuilder, bindings: &mut Bindings, value: String) -> Re
     Ok(())
 }
 
-function!("regex", regex);
+fn regex_matcher(_: &MatchContext, value: String) -> Result<Matcher> {
+    body_regex(&value, false)
+}
+
+function!("regex", regex, regex_matcher);
 
 fn iregex(query: &mut QueryBuilder, bindings: &mut Bindings, value: String) -> Result<()> {
     query.sql("msg_body ~* ");
@@ -14,4 +30,8 @@ fn iregex(query: &mut QueryBuilder, bindings: &mut Bindings, value: String) -> R
     Ok(())
 }
 
-function!("iregex", iregex);
+fn iregex_matcher(_: &MatchContext, value: String) -> Result<Matcher> {
+    body_regex(&value, true)
+}
+
+function!("iregex", iregex, iregex_matcher);
@@ -1,4 +1,5 @@
 use super::*;
+use crate::query::eval::similar_regex;
 
 fn similarto(query: &mut QueryBuilder, bindings: &mut Bindings, value: String) -> Result<()> {
     query.sql("msg_body SIMILAR TO ");
@@ -6,4 +7,9 @@ fn similarto(query: &mut QueryBuilder, bindings: &mut Bindings, value: String) -
     Ok(())
 }
 
-function!("similarto", similarto);
+fn similarto_matcher(_: &MatchContext, value: String) -> Result<Matcher> {
+    let regex = similar_regex(&value)?;
+    Ok(Box::new(move |row: &Row| regex.is_match(&row.message.body)))
+}
+
+function!("similarto", similarto, similarto_matcher);
@@ -1,11 +1,18 @@
+mod cursor;
+pub mod eval;
 mod expr;
 mod functions;
 mod parser;
+use crate::bots::BotList;
 use crate::models;
 
+pub use self::cursor::{Cursor, SortKey};
+pub use self::eval::MessageFilter;
 pub use self::expr::Expr;
+pub use self::parser::{quote, ParseError};
 
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
+use chrono_tz::Tz;
 
 use sqlx::database::{HasStatement, HasArguments};
 use sqlx::encode::Encode;
@@ -13,11 +20,33 @@ use sqlx::postgres::{PgArguments, PgConnection, Postgres};
 use sqlx::{prelude::*, Arguments, Pool};
 use sqlx::{Execute, Type};
 use futures::StreamExt;
+use serde::Serialize;
+use std::fmt::Debug;
+
+/// Settings a query is built with, besides the expression itself.
+#[derive(Clone, Default)]
+pub struct QueryOptions {
+    /// Zone in which dates and times given in the query are interpreted.
+    pub timezone: Tz,
+    pub bots: BotList,
+}
 
 #[derive(Default)]
 pub struct Bindings {
     arguments: PgArguments,
     len: usize,
+    /// Debug representations of the bound values, for `explain`.
+    values: Vec<String>,
+    pub options: QueryOptions,
+}
+
+impl Bindings {
+    pub fn new(options: QueryOptions) -> Bindings {
+        Bindings {
+            options,
+            ..Default::default()
+        }
+    }
 }
 
 #[derive(Default)]
@@ -36,12 +65,13 @@ impl QueryBuilder {
 
     pub fn binding_id<T>(&mut self, bindings: &mut Bindings, val: T) -> usize
     where
-        T: Type<Postgres> + for<'a> Encode<'a, Postgres> + std::marker::Send,
+        T: Type<Postgres> + for<'a> Encode<'a, Postgres> + std::marker::Send + Debug,
     {
         bindings.len += 1;
         let id = bindings.len;
+        bindings.values.push(format!("{:?}", val));
     
-        let mut arguments = std::mem::replace(&mut bindings.arguments, PgArguments::default());
+        let mut arguments = std::mem::take(&mut bindings.arguments);
         arguments.add(val);
         bindings.arguments = arguments;
     
@@ -50,12 +80,13 @@ impl QueryBuilder {
 
     pub fn binding<T>(&mut self, bindings: &mut Bindings, val: T)
     where
-        T: Type<Postgres> + for<'a> Encode<'a, Postgres> + std::marker::Send,
+        T: Type<Postgres> + for<'a> Encode<'a, Postgres> + std::marker::Send + Debug,
     {
         bindings.len += 1;
         let id = bindings.len;
+        bindings.values.push(format!("{:?}", val));
         self.sql.push_str(&format!("${}", id));
-        let mut arguments = std::mem::replace(&mut bindings.arguments, PgArguments::default());
+        let mut arguments = std::mem::take(&mut bindings.arguments);
         arguments.add(val);
         bindings.arguments = arguments;
     }
@@ -168,10 +199,35 @@ fn build_tsquery(query: &mut QueryBuilder, bindings: &mut Bindings, expr: Expr)
     Ok(())
 }
 
+fn build_bots_filter(query: &mut QueryBuilder, bindings: &mut Bindings, value: &str) -> Result<()> {
+    let negate = match value {
+        "include" => {
+            query.sql("TRUE");
+            return Ok(());
+        }
+        "exclude" => true,
+        "only" => false,
+        _ => bail!(
+            "bad 'bots' function argument: either 'exclude', 'include' or 'only' expected"
+        ),
+    };
+
+    if negate {
+        query.sql("NOT ");
+    }
+
+    let patterns = bindings.options.bots.like_patterns();
+    query.sql("(msg_author ILIKE ANY(");
+    query.binding(bindings, patterns);
+    query.sql("))");
+    Ok(())
+}
+
 fn build_func_filter(query: &mut QueryBuilder, bindings: &mut Bindings, expr: Expr) -> Result<()> {
     match expr {
         Expr::Func(name, value) => match name.as_str() {
-            "bots" | "sort" | "order" => query.sql("TRUE"),
+            "bots" => build_bots_filter(query, bindings, &value)?,
+            "sort" | "order" | "tz" => query.sql("TRUE"),
             _ => self::functions::handle(query, bindings, name, value)?,
         },
 
@@ -202,7 +258,7 @@ fn build_phrase_filter(
 ) -> Result<()> {
     let mut partial = QueryBuilder::default();
     build_tsquery(&mut partial, bindings, expr)?;
-    query.sql("to_tsvector('russian', msg_body) @@ (");
+    query.sql("msg_tsv @@ (");
     query.append(&partial);
     query.sql(")");
     tsqueries.push(partial);
@@ -278,50 +334,140 @@ pub fn build_filter(
 }
 
 fn relevance(query: &mut QueryBuilder, tsqueries: &[QueryBuilder]) {
+    for (i, q) in tsqueries.iter().enumerate() {
+        if i > 0 {
+            query.sql(" + ");
+        }
+        query.sql("ts_rank(msg_tsv, ");
+        query.append(q);
+        query.sql(")");
+    }
+}
+
+/// Matched words of every phrase filter, marked up with `ts_headline`.
+/// Produces NULL when the query has no phrases.
+fn headline(query: &mut QueryBuilder, bindings: &mut Bindings, tsqueries: &[QueryBuilder]) {
     if tsqueries.is_empty() {
-        query.sql("msg_timestamp");
+        query.sql("NULL::text");
+        return;
     }
 
+    query.sql("ts_headline('russian', msg_body, ");
+
     for (i, q) in tsqueries.iter().enumerate() {
         if i > 0 {
-            query.sql(" + ");
+            query.sql(" || ");
         }
-        query.sql("ts_rank(to_tsvector('russian', msg_body), ");
-        query.append(&q);
+        query.sql("(");
+        query.append(q);
         query.sql(")");
     }
+
+    query.sql(", ");
+    query.binding(
+        bindings,
+        format!(
+            "StartSel={}, StopSel={}, MaxFragments=3, FragmentDelimiter=\" ... \"",
+            models::HIGHLIGHT_START,
+            models::HIGHLIGHT_STOP
+        ),
+    );
+    query.sql(")");
+}
+
+/// Sets the session time zone until the end of the current transaction.
+///
+/// Local dates and times are taken by casting `msg_timestamp AT TIME ZONE 'UTC'`,
+/// which follows this setting. Passing the zone to `AT TIME ZONE` directly is
+/// not an option: PostgreSQL reads names like `EET` as fixed-offset abbreviations.
+pub async fn set_timezone(db: &mut PgConnection, timezone: Tz) -> Result<()> {
+    sqlx::query("SELECT set_config('TimeZone', $1, true)")
+        .bind(timezone.name())
+        .execute(db)
+        .await?;
+    Ok(())
 }
 
-fn should_exclude_bots(value: &str) -> Result<bool> {
-    match value {
-        "exclude" => Ok(true),
-        "include" => Ok(false),
-        _ => {
-            bail!("bad 'bots' function argument: either 'exclude' (default) or 'include' expected")
+/// Zone requested with the `tz:` function, falling back to `default`.
+pub fn timezone(expr: &Expr, default: Tz) -> Result<Tz> {
+    match expr.get_func("tz") {
+        Some(name) => name
+            .parse()
+            .map_err(|_| anyhow!("bad 'tz' function argument: unknown time zone '{}'", name)),
+        None => Ok(default),
+    }
+}
+
+/// `expr AND extra`, keeping top-level functions of `expr` at the top level.
+fn and_also(expr: Expr, extra: Expr) -> Expr {
+    match expr {
+        Expr::And(mut exprs) => {
+            exprs.push(extra);
+            Expr::And(exprs)
         }
+        expr => Expr::And(vec![expr, extra]),
     }
 }
 
-pub async fn search(
-    db: Pool<Postgres>,
+/// Adds `bots:<value>` unless the query already has a `bots` function.
+fn with_default_bots(expr: Expr, value: &str) -> Expr {
+    if expr.get_func("bots").is_some() {
+        return expr;
+    }
+
+    and_also(expr, Expr::Func("bots".to_owned(), value.to_owned()))
+}
+
+/// Which slice of the results [`search`] returns.
+pub struct Page {
+    pub limit: usize,
+    pub cursor: Option<Cursor>,
+}
+
+/// One page of search results, with cursors of the neighbouring pages.
+pub struct SearchPage {
+    pub messages: Vec<models::Message>,
+    pub next: Option<Cursor>,
+    pub prev: Option<Cursor>,
+}
+
+/// Pushes `(key, msg_timestamp, msg_id)`, leaving the key out when results
+/// are ordered by time alone.
+fn sort_columns(query: &mut QueryBuilder, key: Option<&QueryBuilder>, suffix: &str) {
+    if let Some(key) = key {
+        query.append(key);
+        query.sql(suffix);
+        query.sql(", ");
+    }
+
+    query.sql("msg_timestamp");
+    query.sql(suffix);
+    query.sql(", msg_id");
+    query.sql(suffix);
+}
+
+/// Builds the query behind [`search`], returning it with its bindings and the
+/// kind of sort key it selects.
+fn build_search(
     expr: Expr,
-) -> Result<Vec<models::Message>> {
+    options: QueryOptions,
+    page: &Page,
+) -> Result<(QueryBuilder, Bindings, SortKey)> {
     let mut query = QueryBuilder::default();
-    let mut bindings = Bindings::default();
+    let mut bindings = Bindings::new(options);
 
     let sort = expr
         .get_func("sort")
-        .unwrap_or_else(|| "relevance")
+        .unwrap_or("relevance")
         .to_owned();
 
-    let order = expr.get_func("order").unwrap_or_else(|| "desc").to_owned();
+    let order = expr.get_func("order").unwrap_or("desc").to_owned();
 
-    query.sql(
-        "SELECT msg_id, msg_offset, msg_author, msg_body, msg_timestamp \
-         FROM messages \
-         LEFT JOIN aliases ON alias_secondary = msg_author \
-         WHERE ",
-    );
+    let descending = match order.as_str() {
+        "asc" => false,
+        "desc" => true,
+        _ => bail!("bad 'order' function argument: either 'desc' (default) or 'asc' expected"),
+    };
 
     let mut tsqueries = vec![];
     let mut filter = QueryBuilder::default();
@@ -332,55 +478,172 @@ pub async fn search(
         expr.normalize()?,
     )?;
 
-    query.append(&filter);
-    query.sql(" ORDER BY ");
-
-    match sort.as_str() {
-        "time" => {
-            query.sql("msg_timestamp");
-        },
+    let mut key = QueryBuilder::default();
+    let kind = match sort.as_str() {
+        "time" => SortKey::Time,
+        "relevance" if tsqueries.is_empty() => SortKey::Time,
         "relevance" => {
-            relevance(&mut query, &tsqueries);
+            key.sql("(");
+            relevance(&mut key, &tsqueries);
+            key.sql(")::float8");
+            SortKey::Rank(0)
         }
         "random" => {
-            query.sql("RANDOM()");
+            let seed = match &page.cursor {
+                Some(Cursor {
+                    key: SortKey::Random { seed, .. },
+                    ..
+                }) => *seed,
+                _ => rand::random(),
+            };
+            key.sql("md5(");
+            key.binding(&mut bindings, seed.to_string());
+            key.sql(" || msg_id::text)");
+            SortKey::Random {
+                seed,
+                hash: String::new(),
+            }
         }
         _ => bail!("bad 'sort' function argument: either 'time', 'relevance' or 'random' expected")
+    };
+    let key = (kind != SortKey::Time).then_some(&key);
+
+    query.sql("SELECT msg_id, msg_offset, msg_author, msg_body, msg_timestamp, msg_kind, ");
+    headline(&mut query, &mut bindings, &tsqueries);
+    query.sql(", ");
+    match key {
+        Some(key) => query.append(key),
+        None => query.sql("NULL"),
     }
+    query.sql(
+        " FROM messages \
+         LEFT JOIN aliases ON alias_secondary = msg_author \
+         WHERE (",
+    );
 
-    match order.as_str() {
-        "asc" => query.sql(" ASC"),
-        "desc" => query.sql(" DESC"),
-        _ => bail!("bad 'order' function argument: either 'desc' (default) or 'asc' expected"),
+    query.append(&filter);
+    query.sql(")");
+
+    let backward = page.cursor.as_ref().is_some_and(|cursor| cursor.backward);
+    // Walking back means reading the opposite order, then flipping the page.
+    let descending = descending != backward;
+
+    if let Some(cursor) = &page.cursor {
+        query.sql(" AND (");
+        sort_columns(&mut query, key, "");
+        query.sql(if descending { ") < (" } else { ") > (" });
+        match (&cursor.key, &kind) {
+            (SortKey::Time, SortKey::Time) => {}
+            (SortKey::Rank(bits), SortKey::Rank(_)) => {
+                query.binding(&mut bindings, f64::from_bits(*bits));
+                query.sql(", ");
+            }
+            (SortKey::Random { hash, .. }, SortKey::Random { .. }) => {
+                query.binding(&mut bindings, hash.clone());
+                query.sql(", ");
+            }
+            _ => bail!("cursor does not match the sort order of the query"),
+        }
+        query.binding(&mut bindings, cursor.time);
+        query.sql(", ");
+        query.binding(&mut bindings, cursor.id);
+        query.sql(")");
     }
 
-    query.sql(" LIMIT 1000");
-    println!("{}", query.sql);
+    query.sql(" ORDER BY ");
+    sort_columns(&mut query, key, if descending { " DESC" } else { " ASC" });
+    query.sql(format!(" LIMIT {}", page.limit + 1));
+
+    Ok((query, bindings, kind))
+}
 
-    let mut messages = Vec::new();
-    let mut rows = db.fetch(ExecWrapper(&query, bindings));
+pub async fn search(
+    db: Pool<Postgres>,
+    expr: Expr,
+    options: QueryOptions,
+    page: Page,
+) -> Result<SearchPage> {
+    let timezone = options.timezone;
+    let backward = page.cursor.as_ref().is_some_and(|cursor| cursor.backward);
+    let (query, bindings, kind) = build_search(expr, options, &page)?;
+
+    let mut tx = db.begin().await?;
+    set_timezone(&mut tx, timezone).await?;
+
+    let mut results = Vec::new();
+    let mut rows = tx.fetch(ExecWrapper(&query, bindings));
     while let Some(Ok(row)) = rows.next().await {
-        messages.push(models::Message {
-            id: row.get(0),
-            author: row.get(2),
-            body: row.get(3),
-            time: row.get(4),
-            offset: row.get(1)
+        let key = match &kind {
+            SortKey::Time => SortKey::Time,
+            SortKey::Rank(_) => SortKey::Rank(row.get::<f64, _>(7).to_bits()),
+            SortKey::Random { seed, .. } => SortKey::Random {
+                seed: *seed,
+                hash: row.get(7),
+            },
+        };
+
+        results.push((
+            models::Message {
+                id: row.get(0),
+                author: row.get(2),
+                body: row.get(3),
+                time: row.get(4),
+                offset: row.get(1),
+                kind: row.get(5),
+                highlight: row.get(6),
+            },
+            key,
+        ))
+    }
+
+    let more = results.len() > page.limit;
+    results.truncate(page.limit);
+    if backward {
+        results.reverse();
+    }
+
+    let cursor = |result: Option<&(models::Message, SortKey)>, backward| {
+        result.map(|(message, key)| Cursor {
+            key: key.clone(),
+            time: message.time,
+            id: message.id,
+            backward,
         })
+    };
+
+    let has_next = more || backward;
+    let has_prev = if backward { more } else { page.cursor.is_some() };
+
+    Ok(SearchPage {
+        next: cursor(results.last().filter(|_| has_next), false),
+        prev: cursor(results.first().filter(|_| has_prev), true),
+        messages: results.into_iter().map(|(message, _)| message).collect(),
+    })
+}
+
+/// Restricts statistics to what people said, unless the query asks for
+/// particular kinds of lines itself.
+fn with_default_kinds(expr: Expr) -> Expr {
+    if expr.has_func("kind") {
+        return expr;
     }
 
-    Ok(messages)
+    let kind = |kind: &str| Expr::Func("kind".to_owned(), kind.to_owned());
+    and_also(expr, Expr::Or(vec![kind("message"), kind("action")]))
 }
 
+/// Counts matching messages and authors. Bots are excluded unless the query
+/// says otherwise with `bots:include` or `bots:only`; joins, parts and other
+/// events unless it has a `kind` function.
 pub async fn count(
     db: &mut PgConnection,
-    bot_list: Vec<String>,
     expr: Expr,
+    options: QueryOptions,
 ) -> Result<CountResult> {
-    let exclude_bots = should_exclude_bots(expr.get_func("bots").unwrap_or_else(|| "exclude"))?;
-
+    let expr = with_default_kinds(with_default_bots(expr, "exclude"));
+    let timezone = options.timezone;
     let mut query = QueryBuilder::default();
-    let mut bindings = Bindings::default();
+    let mut bindings = Bindings::new(options);
 
     #[rustfmt::skip]
     query.sql(
@@ -403,15 +666,10 @@ pub async fn count(
 
     query.append(&filter);
 
-    if exclude_bots {
-        query.sql(" AND msg_author != ALL(");
-        query.binding(&mut bindings, bot_list);
-        query.sql(")");
-    }
-
-    println!("{}", query.sql);
+    let mut tx = Connection::begin(db).await?;
+    set_timezone(&mut tx, timezone).await?;
 
-    let mut rows = db.fetch(ExecWrapper(&query, bindings));
+    let mut rows = tx.fetch(ExecWrapper(&query, bindings));
     let row = rows.next().await.unwrap();
     let row = row?;
     Ok(CountResult {
@@ -421,11 +679,13 @@ pub async fn count(
     })
 }
 
-pub async fn top(db: &mut PgConnection, bot_list: Vec<String>, expr: Expr) -> Result<TopResult> {
-    let exclude_bots = should_exclude_bots(expr.get_func("bots").unwrap_or_else(|| "exclude"))?;
-
+/// Most active authors among matching messages, with the same bot and event
+/// handling as [`count`].
+pub async fn top(db: &mut PgConnection, expr: Expr, options: QueryOptions) -> Result<TopResult> {
+    let expr = with_default_kinds(with_default_bots(expr, "exclude"));
+    let timezone = options.timezone;
     let mut query = QueryBuilder::default();
-    let mut bindings = Bindings::default();
+    let mut bindings = Bindings::new(options.clone());
 
     #[rustfmt::skip]
     query.sql(
@@ -446,23 +706,20 @@ pub async fn top(db: &mut PgConnection, bot_list: Vec<String>, expr: Expr) -> Re
     )?;
 
     query.append(&filter);
-
-    if exclude_bots {
-        query.sql(" AND msg_author != ALL(");
-        query.binding(&mut bindings, bot_list.clone());
-        query.sql(")");
-    }
-
     query.sql("  GROUP BY author ORDER BY count(msg_body) DESC LIMIT 6");
 
-    println!("{}", query.sql);
+    let count = count(db, expr, options).await?;
 
-    let count = count(db, bot_list, expr).await?;
+    let mut tx = Connection::begin(db).await?;
+    set_timezone(&mut tx, timezone).await?;
 
     let mut top = Vec::new();
-    let mut rows = db.fetch(ExecWrapper(&query, bindings));
+    let mut rows = tx.fetch(ExecWrapper(&query, bindings));
     while let Some(Ok(row)) = rows.next().await {
-        top.push((row.get(0), row.get(1)));
+        top.push(TopAuthor {
+            author: row.get(0),
+            count: row.get(1),
+        });
     }
 
     Ok(TopResult {
@@ -473,16 +730,213 @@ pub async fn top(db: &mut PgConnection, bot_list: Vec<String>, expr: Expr) -> Re
     })
 }
 
-#[derive(Clone, Debug)]
+/// How much of the query plan [`explain`] asks PostgreSQL for.
+pub enum Plan {
+    None,
+    Explain,
+    Analyze,
+}
+
+/// The expression after one step of [`Expr::normalize`].
+#[derive(Clone, Debug, Serialize)]
+pub struct ExplainStage {
+    pub stage: &'static str,
+    pub expr: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Explain {
+    pub stages: Vec<ExplainStage>,
+    /// Whether phrases and functions of the normalized expression are
+    /// filtered separately, see `can_separate`.
+    pub separable: bool,
+    pub sql: Option<String>,
+    pub params: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plan: Option<Vec<String>>,
+    /// Error of the stage that failed; later stages are left out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Shows how [`search`] turns `expr` into SQL, without running it
+/// unless `plan` is [`Plan::Analyze`].
+pub async fn explain(
+    db: Pool<Postgres>,
+    expr: Expr,
+    options: QueryOptions,
+    page: Page,
+    plan: Plan,
+) -> Result<Explain> {
+    let mut explain = Explain::default();
+    let stage = |stage, expr: &Expr| ExplainStage {
+        stage,
+        expr: format!("{:?}", expr),
+    };
+
+    type Step = fn(Expr) -> Result<Expr>;
+    let steps: [(&str, Step); 4] = [
+        ("validate", Expr::validate),
+        ("to_nnf", |e| Ok(e.to_nnf())),
+        ("reduce", |e| Ok(e.reduce())),
+        ("expand", Expr::expand),
+    ];
+
+    explain.stages.push(stage("parse", &expr));
+    let mut current = expr.clone();
+    for (name, step) in steps {
+        match step(current) {
+            Ok(next) => {
+                explain.stages.push(stage(name, &next));
+                current = next;
+            }
+            Err(err) => {
+                explain.error = Some(err.to_string());
+                return Ok(explain);
+            }
+        }
+    }
+    explain.separable = can_separate(&current);
+
+    let timezone = options.timezone;
+    let (query, bindings, _) = match build_search(expr, options, &page) {
+        Ok(built) => built,
+        Err(err) => {
+            explain.error = Some(err.to_string());
+            return Ok(explain);
+        }
+    };
+
+    explain.sql = Some(query.sql.clone());
+    explain.params = bindings.values.clone();
+
+    let mut statement = QueryBuilder::default();
+    match plan {
+        Plan::None => return Ok(explain),
+        Plan::Explain => statement.sql("EXPLAIN "),
+        Plan::Analyze => statement.sql("EXPLAIN ANALYZE "),
+    }
+    statement.append(&query);
+
+    let mut tx = db.begin().await?;
+    set_timezone(&mut tx, timezone).await?;
+
+    let mut lines = Vec::new();
+    let mut rows = tx.fetch(ExecWrapper(&statement, bindings));
+    while let Some(row) = rows.next().await {
+        lines.push(row?.get(0));
+    }
+
+    explain.plan = Some(lines);
+    Ok(explain)
+}
+
+/// Number of values kept per facet.
+const FACET_SIZE: usize = 10;
+
+/// Total hits of a search and their breakdown by author (aliases merged),
+/// local day and channel.
+pub async fn facets(
+    db: &mut PgConnection,
+    expr: Expr,
+    options: QueryOptions,
+) -> Result<FacetResult> {
+    let timezone = options.timezone;
+    let mut query = QueryBuilder::default();
+    let mut bindings = Bindings::new(options);
+
+    let mut tsqueries = vec![];
+    let mut filter = QueryBuilder::default();
+    build_filter(
+        &mut filter,
+        &mut bindings,
+        &mut tsqueries,
+        expr.normalize()?,
+    )?;
+
+    #[rustfmt::skip]
+    query.sql(
+        "SELECT author, day::text, channel, count(*), GROUPING(author, day, channel) \
+         FROM (\
+            SELECT coalesce(alias_primary, msg_author) AS author, \
+                   DATE(msg_timestamp AT TIME ZONE 'UTC') AS day, \
+                   msg_channel AS channel \
+            FROM messages \
+            LEFT JOIN aliases ON alias_secondary = msg_author \
+            WHERE ",
+    );
+    query.append(&filter);
+    query.sql(") matches GROUP BY GROUPING SETS ((author), (day), (channel), ())");
+
+    let mut tx = Connection::begin(db).await?;
+    set_timezone(&mut tx, timezone).await?;
+
+    let mut result = FacetResult::default();
+    let facets = &mut result.facets;
+    let mut rows = tx.fetch(ExecWrapper(&query, bindings));
+    while let Some(row) = rows.next().await {
+        let row = row?;
+        let count = row.get(3);
+
+        // GROUPING() sets a bit for every column left out of the set.
+        let (facet, value) = match row.get::<i32, _>(4) {
+            0b011 => (&mut facets.authors, row.get(0)),
+            0b101 => (&mut facets.days, row.get(1)),
+            0b110 => (&mut facets.channels, row.get(2)),
+            _ => {
+                result.total = count;
+                continue;
+            }
+        };
+
+        facet.push(Facet { value, count });
+    }
+
+    result.total_days = facets.days.len() as i64;
+    for facet in [&mut facets.authors, &mut facets.days, &mut facets.channels] {
+        facet.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+        facet.truncate(FACET_SIZE);
+    }
+
+    Ok(result)
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Facet {
+    pub value: String,
+    pub count: i64,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Facets {
+    pub authors: Vec<Facet>,
+    pub days: Vec<Facet>,
+    pub channels: Vec<Facet>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct FacetResult {
+    pub total: i64,
+    pub total_days: i64,
+    pub facets: Facets,
+}
+
+#[derive(Clone, Debug, Serialize)]
 pub struct CountResult {
     pub total_messages: i64,
     pub total_users: i64,
     pub total_users_raw: i64,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
+pub struct TopAuthor {
+    pub author: String,
+    pub count: i64,
+}
+
+#[derive(Clone, Debug, Serialize)]
 pub struct TopResult {
-    pub top: Vec<(String, i64)>,
+    pub top: Vec<TopAuthor>,
     pub total_messages: i64,
     pub total_users: i64,
     pub total_users_raw: i64,
@@ -495,3 +949,107 @@ pub struct Message {
     pub body: String,
     pub timestamp: chrono::NaiveDateTime,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Walks all pages of `query`, forward from the start and then backward
+    /// from the last page, giving the ids of each page in both directions.
+    async fn pages(db: &Pool<Postgres>, query: &str, limit: usize) -> (Vec<Vec<i32>>, Vec<Vec<i32>>) {
+        let options = || QueryOptions {
+            timezone: chrono_tz::UTC,
+            bots: BotList::default(),
+        };
+        let fetch = |cursor| async move {
+            let page = Page { limit, cursor };
+            search(db.clone(), Expr::parse(query).unwrap(), options(), page).await.unwrap()
+        };
+        let ids = |page: &SearchPage| page.messages.iter().map(|m| m.id).collect::<Vec<_>>();
+
+        let mut forward = vec![];
+        let mut page = fetch(None).await;
+        assert!(page.prev.is_none());
+        loop {
+            forward.push(ids(&page));
+            match page.next.take() {
+                Some(next) => page = fetch(Some(next)).await,
+                None => break,
+            }
+        }
+
+        let mut backward = vec![];
+        while let Some(prev) = page.prev.take() {
+            page = fetch(Some(prev)).await;
+            backward.push(ids(&page));
+        }
+
+        (forward, backward)
+    }
+
+    #[sqlx::test]
+    async fn pages_through_timestamp_ties(db: Pool<Postgres>) {
+        // ten messages, three to a minute
+        sqlx::query(
+            "INSERT INTO messages (msg_timestamp, msg_offset, msg_channel, msg_author, msg_body) \
+            SELECT '2023-01-01 10:00:00'::timestamp + (i / 3) * interval '1 minute', i, '#chan', 'amy', 'hello' \
+            FROM generate_series(0, 9) i",
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let ordered = |order: &'static str| {
+            let db = db.clone();
+            async move {
+                let sql = format!("SELECT msg_id FROM messages ORDER BY msg_timestamp {order}, msg_id {order}");
+                sqlx::query_scalar::<_, i32>(&sql).fetch_all(&db).await.unwrap()
+            }
+        };
+        let desc = ordered("DESC").await;
+        let asc = ordered("ASC").await;
+
+        for limit in 1..=4 {
+            for (query, expected) in [
+                ("sort:time", &desc),
+                ("sort:time order:asc", &asc),
+                // equal ranks fall back to the time order
+                ("hello sort:relevance", &desc),
+                ("hello sort:relevance order:asc", &asc),
+            ] {
+                let (forward, backward) = pages(&db, query, limit).await;
+                assert!(forward.iter().all(|page| !page.is_empty() && page.len() <= limit));
+                assert_eq!(&forward.concat(), expected, "{} limit:{}", query, limit);
+
+                let back: Vec<_> = forward.iter().rev().skip(1).cloned().collect();
+                assert_eq!(backward, back, "{} limit:{} backward", query, limit);
+            }
+
+            let (forward, _) = pages(&db, "sort:random", limit).await;
+            let mut ids = forward.concat();
+            ids.sort();
+            assert_eq!(ids, asc, "sort:random limit:{}", limit);
+        }
+    }
+
+    #[test]
+    fn cursors_of_other_orders_are_rejected() {
+        let options = || QueryOptions {
+            timezone: chrono_tz::UTC,
+            bots: BotList::default(),
+        };
+        let page = Page {
+            limit: 10,
+            cursor: Some(Cursor {
+                key: SortKey::Time,
+                time: chrono::NaiveDate::from_ymd_opt(2023, 1, 1).unwrap().and_hms_opt(10, 0, 0).unwrap(),
+                id: 1,
+                backward: false,
+            }),
+        };
+
+        assert!(build_search(Expr::parse("sort:time").unwrap(), options(), &page).is_ok());
+        assert!(build_search(Expr::parse("hello sort:relevance").unwrap(), options(), &page).is_err());
+        assert!(build_search(Expr::parse("sort:random").unwrap(), options(), &page).is_err());
+    }
+}
@@ -1,20 +1,114 @@
+use std::fmt::{self, Display, Formatter};
+
 use unic_ucd_category::GeneralCategory;
 
 use crate::query::expr::Expr;
 
-type Result<T> = std::result::Result<T, ()>;
+type Result<T> = std::result::Result<T, ParseError>;
 
-fn is_special_char(c: char) -> bool {
-    match c {
-        '(' | ')' | ':' | '\'' | '"' => true,
-        _ => false,
+/// Maximum nesting of parentheses and `NOT`s.
+const MAX_DEPTH: usize = 32;
+
+/// Maximum number of phrases and functions in a single query.
+const MAX_TERMS: usize = 128;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    UnclosedQuote(char),
+    Expected {
+        expected: Vec<&'static str>,
+        found: Option<String>,
+    },
+    TooDeep,
+    TooManyTerms,
+}
+
+/// A query syntax error pointing at the offending spot of the input.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// Byte offset into the query string.
+    pub offset: usize,
+    /// 1-based column, counted in characters.
+    pub column: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl ParseError {
+    pub fn expected(&self) -> Vec<String> {
+        match &self.kind {
+            ParseErrorKind::UnclosedQuote(quote) => vec![format!("'{}'", quote)],
+            ParseErrorKind::Expected { expected, .. } => {
+                expected.iter().map(|s| s.to_string()).collect()
+            }
+            ParseErrorKind::TooDeep | ParseErrorKind::TooManyTerms => vec![],
+        }
+    }
+
+    pub fn found(&self) -> Option<String> {
+        match &self.kind {
+            ParseErrorKind::Expected { found, .. } => found.clone(),
+            _ => None,
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ParseErrorKind::UnclosedQuote(_) => {
+                write!(f, "unclosed quote starting at column {}", self.column)
+            }
+            ParseErrorKind::Expected { expected, found } => {
+                write!(f, "expected ")?;
+
+                for (i, token) in expected.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " or ")?;
+                    }
+                    write!(f, "{}", token)?;
+                }
+
+                match found {
+                    Some(found) => write!(f, " at column {}, found '{}'", self.column, found),
+                    None => write!(f, " at column {}, found end of query", self.column),
+                }
+            }
+            ParseErrorKind::TooDeep => write!(
+                f,
+                "query is nested deeper than {} levels at column {}",
+                MAX_DEPTH, self.column
+            ),
+            ParseErrorKind::TooManyTerms => write!(
+                f,
+                "query has more than {} terms at column {}",
+                MAX_TERMS, self.column
+            ),
+        }
     }
 }
 
+impl std::error::Error for ParseError {}
+
+fn is_special_char(c: char) -> bool {
+    matches!(c, '(' | ')' | ':' | '\'' | '"')
+}
+
 fn is_word_char(c: char) -> bool {
     !is_whitespace(c) && !is_special_char(c)
 }
 
+/// Function arguments run up to a space, a parenthesis or a comma, so values
+/// like `2023-01-01..2023-01-31` or `10:30:00` need no quoting.
+fn is_argument_char(c: char) -> bool {
+    use GeneralCategory::*;
+
+    !matches!(c, '(' | ')' | ',' | ';')
+        && !matches!(
+            GeneralCategory::of(c),
+            SpaceSeparator | LineSeparator | ParagraphSeparator
+        )
+}
+
 fn is_whitespace(c: char) -> bool {
     if is_special_char(c) {
         return false;
@@ -22,32 +116,70 @@ fn is_whitespace(c: char) -> bool {
 
     use GeneralCategory::*;
 
-    match GeneralCategory::of(c) {
-        OpenPunctuation | ClosePunctuation | InitialPunctuation | FinalPunctuation
-        | OtherPunctuation | SpaceSeparator | LineSeparator | ParagraphSeparator => true,
-        _ => false,
-    }
+    matches!(
+        GeneralCategory::of(c),
+        OpenPunctuation
+            | ClosePunctuation
+            | InitialPunctuation
+            | FinalPunctuation
+            | OtherPunctuation
+            | SpaceSeparator
+            | LineSeparator
+            | ParagraphSeparator
+    )
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum TokenKind {
+    Word(String),
+    String(String),
+    Colon,
+    LParen,
+    RParen,
+    And,
+    Or,
+    Then,
+    Not,
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    kind: TokenKind,
+    /// Byte offsets of the token in the query string.
+    start: usize,
+    end: usize,
+    column: usize,
 }
 
-fn is_keyword(s: &str) -> bool {
-    match s {
-        "AND" | "THEN" | "OR" | "NOT" => true,
-        _ => false,
+impl TokenKind {
+    /// Keywords are recognized only when they make up a whole word, so
+    /// `ORANGE` stays a word. A quoted `"AND"` is a phrase, not an operator.
+    fn from_word(word: &str) -> TokenKind {
+        match word {
+            "AND" => TokenKind::And,
+            "OR" => TokenKind::Or,
+            "THEN" => TokenKind::Then,
+            "NOT" => TokenKind::Not,
+            _ => TokenKind::Word(word.to_owned()),
+        }
+    }
+
+    fn is_keyword(&self) -> bool {
+        matches!(
+            self,
+            TokenKind::And | TokenKind::Or | TokenKind::Then | TokenKind::Not
+        )
     }
 }
 
-struct Input<'a> {
+struct Lexer<'a> {
     full: &'a str,
     remaining: &'a str,
     position: usize,
     offset: usize,
 }
 
-impl Input<'_> {
-    fn has_remaining(&self) -> bool {
-        !self.remaining.is_empty()
-    }
-
+impl Lexer<'_> {
     fn peek(&self) -> Option<char> {
         self.remaining.chars().next()
     }
@@ -63,219 +195,556 @@ impl Input<'_> {
         Some(char)
     }
 
-    fn next_expect(&mut self, pred: impl FnOnce(char) -> bool) -> Result<char> {
-        let val = self.next();
-
-        match val {
-            Some(c) if pred(c) => Ok(c),
-            _ => Err(()),
+    fn skip_whitespace(&mut self) {
+        while self.peek().filter(|&c| is_whitespace(c)).is_some() {
+            self.next();
         }
     }
 
-    fn skip(&mut self, n: usize) {
-        for _ in 0..n {
+    fn lex_word(&mut self) -> TokenKind {
+        let start = self.offset;
+
+        while self.peek().filter(|&c| is_word_char(c)).is_some() {
             self.next();
         }
+
+        TokenKind::from_word(&self.full[start..self.offset])
     }
-}
 
-fn parse_word(input: &mut Input<'_>) -> String {
-    let start_offset = input.offset;
-    let mut end_offset = start_offset;
+    fn lex_argument(&mut self) -> TokenKind {
+        let start = self.offset;
 
-    while input.peek().filter(|&c| is_word_char(c)).is_some() {
-        input.next();
-        end_offset = input.offset;
+        while self.peek().filter(|&c| is_argument_char(c)).is_some() {
+            self.next();
+        }
+
+        TokenKind::Word(self.full[start..self.offset].to_owned())
     }
 
-    input.full[start_offset..end_offset].to_owned()
-}
+    fn lex_string(&mut self, quotation_mark: char) -> Result<TokenKind> {
+        let mut string = String::new();
+        let (offset, column) = (self.offset, self.position + 1);
+        self.next();
+
+        while let Some(c) = self.peek() {
+            match c {
+                '\\' => {
+                    self.next();
+                    let next = self.peek();
+
+                    if next == Some('\'') || next == Some('"') || next == Some('\\') {
+                        self.next();
+                        string.push(next.unwrap());
+                    } else {
+                        string.push('\\');
+                    }
+                }
 
-fn parse_string(input: &mut Input<'_>) -> Result<String> {
-    let mut string = String::new();
-    let quotation_mark = input.next_expect(|c| c == '\'' || c == '"')?;
-
-    while let Some(c) = input.peek() {
-        match c {
-            '\\' => {
-                input.next();
-                let next = input.peek();
-
-                if next == Some('\'') || next == Some('"') || next == Some('\\') {
-                    input.next();
-                    string.push(next.unwrap());
-                } else {
-                    string.push('\\');
+                c if c == quotation_mark => break,
+                c => {
+                    self.next();
+                    string.push(c)
                 }
             }
+        }
 
-            c if c == quotation_mark => break,
-            c => {
-                input.next();
-                string.push(c)
-            }
+        if self.next() != Some(quotation_mark) {
+            return Err(ParseError {
+                offset,
+                column,
+                kind: ParseErrorKind::UnclosedQuote(quotation_mark),
+            });
         }
+
+        Ok(TokenKind::String(string))
     }
 
-    if quotation_mark == '\'' {
-        input.next_expect(|c| c == '\'')?;
-    } else {
-        input.next_expect(|c| c == '"')?;
+    fn tokenize(mut self) -> Result<Vec<Token>> {
+        let mut tokens: Vec<Token> = vec![];
+
+        loop {
+            let is_argument = match tokens.as_slice() {
+                [.., name, colon] => {
+                    matches!(name.kind, TokenKind::Word(_))
+                        && colon.kind == TokenKind::Colon
+                        && name.end == colon.start
+                        && colon.end == self.offset
+                }
+                _ => false,
+            };
+
+            let is_argument = is_argument
+                && self
+                    .peek()
+                    .filter(|&c| is_argument_char(c) && c != '\'' && c != '"')
+                    .is_some();
+
+            if !is_argument {
+                self.skip_whitespace();
+            }
+
+            let (start, column) = (self.offset, self.position + 1);
+            let kind = match self.peek() {
+                None => break,
+                Some(_) if is_argument => self.lex_argument(),
+                Some('(') => {
+                    self.next();
+                    TokenKind::LParen
+                }
+                Some(')') => {
+                    self.next();
+                    TokenKind::RParen
+                }
+                Some(':') => {
+                    self.next();
+                    TokenKind::Colon
+                }
+                Some(c @ ('\'' | '"')) => self.lex_string(c)?,
+                Some(_) => self.lex_word(),
+            };
+
+            tokens.push(Token {
+                kind,
+                start,
+                end: self.offset,
+                column,
+            });
+        }
+
+        Ok(tokens)
     }
+}
 
-    Ok(string)
+struct Parser<'a> {
+    full: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Column just past the last character, used for errors at the end of input.
+    end_column: usize,
+    depth: usize,
+    terms: usize,
 }
 
-fn parse_string_or_word(input: &mut Input<'_>) -> Result<String> {
-    let word = parse_word(input);
+impl Parser<'_> {
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.pos).map(|t| &t.kind)
+    }
 
-    if !word.is_empty() {
-        Ok(word)
-    } else {
-        parse_string(input)
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned()?;
+        self.pos += 1;
+        Some(token)
     }
-}
 
-fn parse_func(input: &mut Input<'_>) -> Result<Expr> {
-    let name = parse_word(input);
+    /// Whether the current token starts exactly where the previous one ended.
+    fn is_adjacent(&self) -> bool {
+        match (self.tokens.get(self.pos.wrapping_sub(1)), self.tokens.get(self.pos)) {
+            (Some(prev), Some(next)) => prev.end == next.start,
+            _ => false,
+        }
+    }
 
-    if is_keyword(&name) {
-        return Err(());
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        match self.tokens.get(self.pos) {
+            Some(token) => ParseError {
+                offset: token.start,
+                column: token.column,
+                kind,
+            },
+            None => ParseError {
+                offset: self.full.len(),
+                column: self.end_column,
+                kind,
+            },
+        }
     }
 
-    if name.is_empty() {
-        let string = parse_string(input)?;
-        return Ok(Expr::Phrase(string));
+    fn error_expected(&self, expected: &[&'static str]) -> ParseError {
+        let found = self
+            .tokens
+            .get(self.pos)
+            .map(|t| self.full[t.start..t.end].to_owned());
+
+        self.error(ParseErrorKind::Expected {
+            expected: expected.to_vec(),
+            found,
+        })
     }
 
-    if input.peek() != Some(':') {
-        return Ok(Expr::Phrase(name));
+    /// Like [`Parser::error_expected`], but points right after the previous
+    /// token when the current one is separated from it by whitespace.
+    fn error_expected_adjacent(&self, expected: &[&'static str]) -> ParseError {
+        if self.is_adjacent() || self.pos == 0 {
+            return self.error_expected(expected);
+        }
+
+        let prev = &self.tokens[self.pos - 1];
+        let found = self.full[prev.end..].chars().next().map(String::from);
+
+        ParseError {
+            offset: prev.end,
+            column: prev.column + self.full[prev.start..prev.end].chars().count(),
+            kind: ParseErrorKind::Expected {
+                expected: expected.to_vec(),
+                found,
+            },
+        }
     }
 
-    input.next();
-    let value = parse_string_or_word(input)?;
+    fn enter(&mut self) -> Result<()> {
+        self.depth += 1;
 
-    Ok(Expr::Func(name, value))
-}
+        if self.depth > MAX_DEPTH {
+            return Err(self.error(ParseErrorKind::TooDeep));
+        }
+
+        Ok(())
+    }
+
+    fn leave(&mut self) {
+        self.depth -= 1;
+    }
 
-fn skip_whitespace(input: &mut Input<'_>) {
-    while let Some(c) = input.peek() {
-        if !is_whitespace(c) {
-            break;
+    fn count_term(&mut self) -> Result<()> {
+        self.terms += 1;
+
+        if self.terms > MAX_TERMS {
+            return Err(self.error(ParseErrorKind::TooManyTerms));
         }
 
-        input.next();
+        Ok(())
     }
-}
 
-fn parse_term(input: &mut Input<'_>) -> Result<Expr> {
-    skip_whitespace(input);
+    fn parse_func(&mut self) -> Result<Expr> {
+        self.count_term()?;
 
-    if input.remaining.starts_with("NOT") {
-        input.skip(3);
-        skip_whitespace(input);
+        let name = match self.peek() {
+            Some(TokenKind::Word(word)) => word.clone(),
+            Some(TokenKind::String(string)) => {
+                let string = string.clone();
+                self.next();
+                return Ok(Expr::Phrase(string));
+            }
+            Some(kind) if kind.is_keyword() => {
+                return Err(self.error_expected(&["search term"]))
+            }
+            _ => return Err(self.error_expected(&["word", "quoted string"])),
+        };
 
-        let inner = parse_term(input)?;
-        Ok(Expr::Not(Box::new(inner)))
-    } else if input.remaining.starts_with("(") {
-        input.next();
+        self.next();
 
-        let inner = parse_expr(input)?;
-        skip_whitespace(input);
+        if self.peek() != Some(&TokenKind::Colon) || !self.is_adjacent() {
+            return Ok(Expr::Phrase(name));
+        }
 
-        if !input.remaining.starts_with(")") {
-            return Err(());
+        self.next();
+
+        if !self.is_adjacent() {
+            return Err(self.error_expected_adjacent(&["word", "quoted string"]));
         }
 
-        input.next();
+        let value = match self.peek() {
+            Some(TokenKind::Word(value)) | Some(TokenKind::String(value)) => value.clone(),
+            _ => return Err(self.error_expected(&["word", "quoted string"])),
+        };
+
+        self.next();
 
-        Ok(inner)
-    } else {
-        parse_func(input)
+        Ok(Expr::Func(name, value))
     }
-}
 
-fn parse_operator(
-    input: &mut Input<'_>,
-    keyword: &str,
-    term: fn(&mut Input<'_>) -> Result<Expr>,
-    wrap: impl FnOnce(Vec<Expr>) -> Expr,
-) -> Result<Expr> {
-    let mut args = vec![term(input)?];
+    fn parse_term(&mut self) -> Result<Expr> {
+        match self.peek() {
+            Some(TokenKind::Not) => {
+                self.enter()?;
+                self.next();
 
-    loop {
-        skip_whitespace(input);
+                let inner = self.parse_term()?;
+                self.leave();
 
-        if input.remaining.starts_with(keyword) {
-            input.skip(keyword.len());
-            args.push(term(input)?);
-        } else {
-            break;
+                Ok(Expr::Not(Box::new(inner)))
+            }
+
+            Some(TokenKind::LParen) => {
+                self.enter()?;
+                self.next();
+
+                let inner = self.parse_expr()?;
+
+                if self.peek() != Some(&TokenKind::RParen) {
+                    return Err(self.error_expected(&["')'"]));
+                }
+
+                self.next();
+                self.leave();
+
+                Ok(inner)
+            }
+
+            _ => self.parse_func(),
         }
     }
 
-    if args.len() == 1 {
-        Ok(args.remove(0))
-    } else {
-        Ok(wrap(args))
-    }
-}
+    fn parse_operator(
+        &mut self,
+        keyword: TokenKind,
+        term: fn(&mut Self) -> Result<Expr>,
+        wrap: impl FnOnce(Vec<Expr>) -> Expr,
+    ) -> Result<Expr> {
+        let mut args = vec![term(self)?];
 
-fn parse_or(input: &mut Input<'_>) -> Result<Expr> {
-    parse_operator(input, "OR", parse_term, Expr::Or)
-}
+        while self.peek() == Some(&keyword) {
+            self.next();
+            args.push(term(self)?);
+        }
 
-fn parse_and(input: &mut Input<'_>) -> Result<Expr> {
-    parse_operator(input, "AND", parse_or, Expr::And)
-}
+        if args.len() == 1 {
+            Ok(args.remove(0))
+        } else {
+            Ok(wrap(args))
+        }
+    }
 
-fn parse_then(input: &mut Input<'_>) -> Result<Expr> {
-    parse_operator(input, "THEN", parse_and, Expr::Then)
-}
+    fn parse_or(&mut self) -> Result<Expr> {
+        self.parse_operator(TokenKind::Or, Self::parse_term, Expr::Or)
+    }
 
-fn parse_implicit_and(input: &mut Input<'_>) -> Result<Expr> {
-    let mut args = vec![parse_then(input)?];
+    fn parse_and(&mut self) -> Result<Expr> {
+        self.parse_operator(TokenKind::And, Self::parse_or, Expr::And)
+    }
+
+    fn parse_then(&mut self) -> Result<Expr> {
+        self.parse_operator(TokenKind::Then, Self::parse_and, Expr::Then)
+    }
 
-    loop {
-        skip_whitespace(input);
+    fn parse_implicit_and(&mut self) -> Result<Expr> {
+        let mut args = vec![self.parse_then()?];
+
+        loop {
+            match self.peek() {
+                None
+                | Some(TokenKind::Or)
+                | Some(TokenKind::And)
+                | Some(TokenKind::Then)
+                | Some(TokenKind::RParen) => break,
+                _ => args.push(self.parse_then()?),
+            }
+        }
 
-        let r = input.remaining;
-        if !r.starts_with("OR")
-            && !r.starts_with("AND")
-            && !r.starts_with("THEN")
-            && !r.starts_with(")")
-            && !r.is_empty()
-        {
-            args.push(parse_then(input)?);
+        if args.len() == 1 {
+            Ok(args.remove(0))
         } else {
-            break;
+            Ok(Expr::And(args))
         }
     }
 
-    if args.len() == 1 {
-        Ok(args.remove(0))
-    } else {
-        Ok(Expr::And(args))
+    fn parse_expr(&mut self) -> Result<Expr> {
+        self.parse_implicit_and()
     }
 }
 
-fn parse_expr(input: &mut Input<'_>) -> Result<Expr> {
-    parse_implicit_and(input)
+/// `value` as a function argument that parses back to itself, quoted when
+/// it would otherwise end early or be read as a string.
+pub fn quote(value: &str) -> String {
+    let is_plain = !value.is_empty()
+        && !value.starts_with(['\'', '"'])
+        && value.chars().all(is_argument_char);
+
+    if is_plain {
+        return value.to_owned();
+    }
+
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
 }
 
 pub fn parse(input: &str) -> Result<Expr> {
-    let mut input = Input {
+    let lexer = Lexer {
         full: input,
         remaining: input,
         position: 0,
         offset: 0,
     };
 
-    skip_whitespace(&mut input);
+    let mut parser = Parser {
+        full: input,
+        tokens: lexer.tokenize()?,
+        pos: 0,
+        end_column: input.chars().count() + 1,
+        depth: 0,
+        terms: 0,
+    };
+
+    if parser.peek().is_none() {
+        return Ok(Expr::False);
+    }
+
+    let expr = parser.parse_expr()?;
+
+    if parser.peek().is_some() {
+        return Err(parser.error_expected(&["end of query"]));
+    }
+
+    Ok(expr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn phrase(s: &str) -> Expr {
+        Expr::Phrase(s.to_owned())
+    }
+
+    fn func(name: &str, value: &str) -> Expr {
+        Expr::Func(name.to_owned(), value.to_owned())
+    }
+
+    #[test]
+    fn keywords_need_word_boundaries() {
+        assert_eq!(parse("ORANGE").unwrap(), phrase("ORANGE"));
+        assert_eq!(parse("NOTHING").unwrap(), phrase("NOTHING"));
+        assert_eq!(parse("ANDROID").unwrap(), phrase("ANDROID"));
+        assert_eq!(parse("THENCE").unwrap(), phrase("THENCE"));
+        assert_eq!(
+            parse("ORANGE OR NOTHING").unwrap(),
+            Expr::Or(vec![phrase("ORANGE"), phrase("NOTHING")])
+        );
+        assert_eq!(
+            parse("NOT ANDROID").unwrap(),
+            Expr::Not(Box::new(phrase("ANDROID")))
+        );
+    }
+
+    #[test]
+    fn quoted_keywords_are_phrases() {
+        assert_eq!(parse("\"AND\"").unwrap(), phrase("AND"));
+        assert_eq!(
+            parse("a \"OR\" b").unwrap(),
+            Expr::And(vec![phrase("a"), phrase("OR"), phrase("b")])
+        );
+        assert_eq!(parse("'NOT'").unwrap(), phrase("NOT"));
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(
+            parse("a b OR c AND d").unwrap(),
+            Expr::And(vec![
+                phrase("a"),
+                Expr::And(vec![Expr::Or(vec![phrase("b"), phrase("c")]), phrase("d")]),
+            ])
+        );
+        assert_eq!(
+            parse("(a OR b) THEN c").unwrap(),
+            Expr::Then(vec![Expr::Or(vec![phrase("a"), phrase("b")]), phrase("c")])
+        );
+        assert_eq!(parse("").unwrap(), Expr::False);
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(parse("author:nick").unwrap(), func("author", "nick"));
+        assert_eq!(
+            parse("date:2023-01-01..2023-01-31").unwrap(),
+            func("date", "2023-01-01..2023-01-31")
+        );
+        assert_eq!(parse("time:10:30:00").unwrap(), func("time", "10:30:00"));
+        assert_eq!(
+            parse("author:\"a \\\"b\\\\\"").unwrap(),
+            func("author", "a \"b\\")
+        );
+        assert_eq!(
+            parse("(author:nick)").unwrap(),
+            func("author", "nick")
+        );
+        // not adjacent, so not a function
+        assert!(parse("author: nick").is_err());
+    }
+
+    #[test]
+    fn quoted_values_parse_back() {
+        for value in ["nick", "#cc.ru", "10:30", "two words", "a\"b", "back\\slash", "'x", "(", ""] {
+            let query = format!("author:{}", quote(value));
+            assert_eq!(parse(&query).unwrap(), func("author", value), "{}", query);
+        }
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let nested = |n: usize| format!("{}a{}", "(".repeat(n), ")".repeat(n));
+
+        assert_eq!(parse(&nested(MAX_DEPTH)).unwrap(), phrase("a"));
+
+        let err = parse(&nested(MAX_DEPTH + 1)).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::TooDeep);
+        assert_eq!(err.offset, MAX_DEPTH);
+        assert_eq!(err.column, MAX_DEPTH + 1);
+
+        let nots = |n: usize| format!("{}a", "NOT ".repeat(n));
+        assert!(parse(&nots(MAX_DEPTH)).is_ok());
+
+        let err = parse(&nots(MAX_DEPTH + 1)).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::TooDeep);
+        assert_eq!(err.offset, 4 * MAX_DEPTH);
+        assert_eq!(err.column, 4 * MAX_DEPTH + 1);
+    }
+
+    #[test]
+    fn term_limit() {
+        let terms = |n: usize| vec!["a"; n].join(" ");
+
+        assert!(parse(&terms(MAX_TERMS)).is_ok());
+
+        let err = parse(&terms(MAX_TERMS + 1)).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::TooManyTerms);
+        assert_eq!(err.offset, 2 * MAX_TERMS);
+        assert_eq!(err.column, 2 * MAX_TERMS + 1);
+
+        let funcs = |n: usize| vec!["author:a"; n].join(" ");
+        assert!(parse(&funcs(MAX_TERMS)).is_ok());
+        assert_eq!(
+            parse(&funcs(MAX_TERMS + 1)).unwrap_err().kind,
+            ParseErrorKind::TooManyTerms
+        );
+    }
+
+    #[test]
+    fn error_positions() {
+        let err = parse("hello \"world").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::UnclosedQuote('"'));
+        assert_eq!((err.offset, err.column), (6, 7));
+        assert_eq!(err.to_string(), "unclosed quote starting at column 7");
+
+        let err = parse("(a OR b").unwrap_err();
+        assert_eq!((err.offset, err.column), (7, 8));
+        assert_eq!(err.found(), None);
+        assert_eq!(err.to_string(), "expected ')' at column 8, found end of query");
+
+        let err = parse("a OR )").unwrap_err();
+        assert_eq!((err.offset, err.column), (5, 6));
+        assert_eq!(err.found().as_deref(), Some(")"));
+
+        let err = parse("a AND").unwrap_err();
+        assert_eq!((err.offset, err.column), (5, 6));
+        assert_eq!(err.expected(), vec!["word", "quoted string"]);
+
+        let err = parse("a OR AND b").unwrap_err();
+        assert_eq!((err.offset, err.column), (5, 6));
+        assert_eq!(err.expected(), vec!["search term"]);
+
+        let err = parse("a)").unwrap_err();
+        assert_eq!((err.offset, err.column), (1, 2));
+        assert_eq!(err.expected(), vec!["end of query"]);
+    }
+
+    #[test]
+    fn error_columns_count_characters() {
+        // Cyrillic letters take two bytes each
+        let err = parse("привет (мир").unwrap_err();
+        assert_eq!(err.offset, "привет (мир".len());
+        assert_eq!(err.column, 12);
 
-    if input.has_remaining() {
-        parse_expr(&mut input)
-    } else {
-        Ok(Expr::False)
+        let err = parse("привет 'мир").unwrap_err();
+        assert_eq!((err.offset, err.column), (13, 8));
     }
 }
@@ -0,0 +1,273 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use anyhow::Result;
+use chrono_tz::Tz;
+use futures::{future, stream, Stream, StreamExt};
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use tokio::sync::broadcast::{self, error::RecvError};
+use warp::sse::Event;
+
+use crate::bots::BotList;
+use crate::import::Imported;
+use crate::models::{Message, MessageKind};
+use crate::query::eval::{Row, TsVector};
+use crate::query::MessageFilter;
+
+/// Messages a subscriber may fall behind by before it skips ahead.
+const CAPACITY: usize = 1024;
+
+/// Most messages replayed to a subscriber catching up from an ID. Further
+/// behind, it is told to reload instead.
+const CATCH_UP_LIMIT: i64 = 1000;
+
+/// Hands messages to `/logs/stream` subscribers as they are stored.
+#[derive(Clone)]
+pub struct Broadcaster {
+    sender: broadcast::Sender<Arc<Row>>,
+}
+
+/// A message as selected from `messages`, with what subscriptions filter on.
+struct StoredMessage {
+    id: i32,
+    body: String,
+    author: String,
+    time: chrono::NaiveDateTime,
+    offset: i32,
+    kind: MessageKind,
+    channel: String,
+    tsv: Option<String>,
+}
+
+impl From<StoredMessage> for Row {
+    fn from(stored: StoredMessage) -> Row {
+        Row {
+            message: Message {
+                id: stored.id,
+                time: stored.time,
+                author: stored.author,
+                body: stored.body,
+                offset: stored.offset,
+                kind: stored.kind,
+                highlight: None,
+            },
+            channel: stored.channel,
+            tsv: TsVector::parse(stored.tsv.as_deref().unwrap_or_default()),
+        }
+    }
+}
+
+impl Default for Broadcaster {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(CAPACITY).0,
+        }
+    }
+}
+
+impl Broadcaster {
+    /// Sends the messages an import notification is about to subscribers.
+    /// Of a day imported again, only the messages past the ones it replaced
+    /// are sent, as subscribers have seen the others under their old IDs.
+    pub async fn publish(&self, db: &Pool<Postgres>, imported: &Imported) -> Result<()> {
+        if self.sender.receiver_count() == 0 {
+            return Ok(());
+        }
+
+        let rows = imported_rows(db, imported).await?;
+        let new = rows
+            .into_iter()
+            .filter(|row| imported.replaced.is_none_or(|offset| row.message.offset > offset));
+        for row in new {
+            // fails only when everyone has unsubscribed in the meantime
+            let _ = self.sender.send(Arc::new(row));
+        }
+
+        Ok(())
+    }
+}
+
+/// Messages an import notification is about, in log order.
+pub async fn imported_rows(db: &Pool<Postgres>, imported: &Imported) -> Result<Vec<Row>> {
+    let messages = sqlx::query_as!(
+        StoredMessage,
+        "SELECT msg_id AS id, msg_body AS body, msg_author AS author, msg_timestamp AS time, msg_offset AS offset, msg_kind AS \"kind: MessageKind\", msg_channel AS channel, msg_tsv::text AS tsv FROM messages WHERE msg_id >= $1 AND msg_id <= $2 AND msg_channel = $3 ORDER BY msg_timestamp, msg_offset",
+        imported.first_id,
+        imported.last_id,
+        imported.channel
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(messages.into_iter().map(Row::from).collect())
+}
+
+/// A message as subscribers are sent it, with what the log page needs to
+/// show it the way it shows stored ones.
+#[derive(Serialize)]
+struct Streamed {
+    #[serde(flatten)]
+    message: Message,
+    /// `HH:MM:SS` of `time`.
+    time_of_day: String,
+    is_bot: bool,
+}
+
+/// What a subscriber is sent.
+enum Update {
+    Message(Arc<Row>),
+    /// Messages were skipped, so the client should load them another way,
+    /// e.g. by reloading the page.
+    Reload,
+}
+
+/// Messages stored after the one with ID `after`, oldest first, or `None`
+/// if there are more than [`CATCH_UP_LIMIT`].
+async fn messages_after(db: &Pool<Postgres>, after: i32) -> Result<Option<Vec<Arc<Row>>>> {
+    let messages = sqlx::query_as!(
+        StoredMessage,
+        "SELECT msg_id AS id, msg_body AS body, msg_author AS author, msg_timestamp AS time, msg_offset AS offset, msg_kind AS \"kind: MessageKind\", msg_channel AS channel, msg_tsv::text AS tsv FROM messages WHERE msg_id > $1 ORDER BY msg_id LIMIT $2",
+        after,
+        CATCH_UP_LIMIT + 1
+    )
+    .fetch_all(db)
+    .await?;
+
+    if messages.len() as i64 > CATCH_UP_LIMIT {
+        return Ok(None);
+    }
+
+    Ok(Some(messages.into_iter().map(|message| Arc::new(message.into())).collect()))
+}
+
+/// Server-sent events of new messages, with times in `tz`, authors in
+/// `bots` marked, and their IDs as event IDs. Given `after`, messages stored since that ID are sent first.
+/// Given `filter`, only messages it matches are sent.
+///
+/// Messages are never skipped silently: when more than [`CATCH_UP_LIMIT`]
+/// were stored since `after`, or the subscriber falls more than [`CAPACITY`]
+/// behind, a `reload` event is sent in place of the skipped ones.
+pub async fn events(
+    db: &Pool<Postgres>,
+    broadcaster: &Broadcaster,
+    after: Option<i32>,
+    filter: Option<MessageFilter>,
+    tz: Tz,
+    bots: BotList,
+) -> Result<impl Stream<Item = Result<Event, Infallible>>> {
+    // subscribe first, so nothing stored while catching up is missed
+    let receiver = broadcaster.sender.subscribe();
+
+    let catch_up = match after {
+        Some(after) => match messages_after(db, after).await? {
+            Some(rows) => rows.into_iter().map(Update::Message).collect(),
+            None => vec![Update::Reload],
+        },
+        None => vec![],
+    };
+    let caught_up = catch_up
+        .iter()
+        .filter_map(|update| match update {
+            Update::Message(row) => Some(row.message.id),
+            Update::Reload => None,
+        })
+        .max();
+
+    let live = stream::unfold(receiver, |mut receiver| async move {
+        let update = match receiver.recv().await {
+            Ok(row) => Update::Message(row),
+            Err(RecvError::Lagged(_)) => Update::Reload,
+            Err(RecvError::Closed) => return None,
+        };
+        Some((update, receiver))
+    })
+    .filter(move |update| {
+        future::ready(match update {
+            Update::Message(row) => caught_up.is_none_or(|id| row.message.id > id),
+            Update::Reload => true,
+        })
+    });
+
+    Ok(stream::iter(catch_up)
+        .chain(live)
+        .filter(move |update| {
+            future::ready(match (update, &filter) {
+                (Update::Message(row), Some(filter)) => filter.matches(row),
+                _ => true,
+            })
+        })
+        .map(move |update| match update {
+            Update::Message(row) => {
+                let message = row.message.clone().in_timezone(tz);
+                let streamed = Streamed {
+                    time_of_day: message.time.format("%H:%M:%S").to_string(),
+                    is_bot: bots.is_bot(&message.author),
+                    message,
+                };
+                Ok(Event::default()
+                    .id(streamed.message.id.to_string())
+                    .json_data(&streamed)
+                    .unwrap())
+            }
+            Update::Reload => Ok(Event::default().event("reload").data("messages were skipped")),
+        }))
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::postgres::PgListener;
+
+    use super::*;
+    use crate::config::Source;
+    use crate::import;
+
+    #[sqlx::test]
+    async fn reimported_days_send_only_new_messages(db: Pool<Postgres>) {
+        let mut listener = PgListener::connect_with(&db).await.unwrap();
+        listener.listen(import::CHANNEL).await.unwrap();
+        let broadcaster = Broadcaster::default();
+        let mut receiver = broadcaster.sender.subscribe();
+
+        let source = Source {
+            name: String::from("stand-in"),
+            url: String::new(),
+            channel: String::from("#stand-in"),
+            timezone: chrono_tz::UTC,
+            format: String::from("fomalhaut"),
+            import_interval: None,
+        };
+        let dir = std::env::temp_dir().join(format!("sprout-stream-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("2023-01-01.log");
+        let date = "2023-01-01".parse().unwrap();
+
+        let first = "[10:00:00] <amy> first";
+        let second = "[10:01:00] <bob> second";
+        let third = "[10:02:00] <amy> third";
+        let imports = [
+            (vec![first, second], vec![(0, "first"), (1, "second")]),
+            // the whole day again, with a line more
+            (vec![first, second, third], vec![(2, "third")]),
+            (vec![first, second, third], vec![]),
+        ];
+
+        for (lines, expected) in imports {
+            std::fs::write(&path, lines.join("\n")).unwrap();
+            import::import_file(db.clone(), &source, &path, date).await.unwrap();
+            let notification = listener.recv().await.unwrap();
+            let imported: Imported = serde_json::from_str(notification.payload()).unwrap();
+            broadcaster.publish(&db, &imported).await.unwrap();
+
+            let mut sent = vec![];
+            while let Ok(row) = receiver.try_recv() {
+                sent.push((row.message.offset, row.message.body.clone()));
+            }
+            let expected: Vec<_> = expected.iter().map(|(offset, body)| (*offset, body.to_string())).collect();
+            assert_eq!(sent, expected, "{:?}", lines);
+        }
+
+        let count: i64 = sqlx::query_scalar("SELECT count(*) FROM messages").fetch_one(&db).await.unwrap();
+        assert_eq!(count, 3);
+    }
+}
@@ -0,0 +1,36 @@
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use chrono_tz::Tz;
+
+/// Converts a wall-clock time in `tz` to a naive UTC timestamp, as stored in
+/// the database. Times skipped by a DST transition are moved past the gap.
+pub fn local_to_utc(tz: Tz, local: NaiveDateTime) -> NaiveDateTime {
+    let mut shifted = local;
+
+    for _ in 0..4 {
+        if let Some(datetime) = tz.from_local_datetime(&shifted).earliest() {
+            return datetime.naive_utc();
+        }
+
+        shifted += Duration::minutes(30);
+    }
+
+    tz.from_utc_datetime(&local).naive_utc()
+}
+
+pub fn utc_to_local(tz: Tz, utc: NaiveDateTime) -> NaiveDateTime {
+    tz.from_utc_datetime(&utc).naive_local()
+}
+
+/// UTC bounds `[start, end)` of a calendar day in `tz`.
+pub fn day_bounds(tz: Tz, date: NaiveDate) -> (NaiveDateTime, NaiveDateTime) {
+    let next = date.succ_opt().unwrap_or(date);
+
+    (
+        local_to_utc(tz, date.and_time(NaiveTime::MIN)),
+        local_to_utc(tz, next.and_time(NaiveTime::MIN)),
+    )
+}
+
+pub fn today(tz: Tz) -> NaiveDate {
+    chrono::Utc::now().with_timezone(&tz).date_naive()
+}
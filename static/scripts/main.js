@@ -71,6 +71,19 @@ function renderIRCFormatting(text) {
     return text;
 }
 
+function escapeHTML(text) {
+    return text
+        .replace(/&/g, "&amp;")
+        .replace(/</g, "&lt;")
+        .replace(/>/g, "&gt;")
+        .replace(/"/g, "&quot;")
+        .replace(/'/g, "&#39;");
+}
+
+function formatMessage(text) {
+    return renderIRCFormatting(autolinkText(escapeHTML(text)));
+}
+
 function autolinkText(text) {
     const urlRegex = /(\b(https?|ftp|file):\/\/[-A-Z0-9+&@#/%=~_|$?!:,.]*[A-Z0-9+&@#/%=~_|$])/gi;
     return text.replace(urlRegex, '<a href="$1" target="_blank" rel="noopener noreferrer">$1</a>');
@@ -94,18 +107,29 @@ function getHTMLColorFromIRC(ircColor) {
     return htmlColor;
 }
 
-function colorize() {
-    const nicks = document.querySelectorAll(".from");
+function colorize(root = document) {
+    const nicks = root.querySelectorAll(".from");
     nicks.forEach(element => {
-        const hashCode = hashCodeFromString(element.textContent);
+        // events show a bare nick; hash it like "<nick>" so colors match
+        const nick = element.dataset.nick ? `<${element.dataset.nick}>` : element.textContent;
+        const hashCode = hashCodeFromString(nick);
         const color = getColorIndex(hashCode);
         element.style.color = colorPalette[color];
     });
 
-    const messages = document.querySelectorAll(".text");
+    const messages = root.querySelectorAll(".text");
     messages.forEach(element => {
-        element.innerHTML = autolinkText(element.textContent);
-        element.innerHTML = renderIRCFormatting(element.innerHTML);
+        let html = "";
+
+        element.childNodes.forEach(node => {
+            if (node.nodeName === "MARK") {
+                html += `<mark>${formatMessage(node.textContent)}</mark>`;
+            } else {
+                html += formatMessage(node.textContent);
+            }
+        });
+
+        element.innerHTML = html;
     });
 }
 
@@ -123,8 +147,42 @@ function hashCodeFromString(str) {
     return hash;
 }
 
+const timezone = new URLSearchParams(window.location.search).get("tz");
+
+function withTimezone(url) {
+    if (!timezone) {
+        return url;
+    }
+
+    // the parameter goes before a `#fragment`
+    const hash = url.indexOf("#");
+    const [path, fragment] = hash < 0 ? [url, ""] : [url.slice(0, hash), url.slice(hash)];
+    const separator = path.includes("?") ? "&" : "?";
+    return `${path}${separator}tz=${encodeURIComponent(timezone)}${fragment}`;
+}
+
+function preserveTimezone() {
+    if (!timezone) {
+        return;
+    }
+
+    const input = document.createElement("input");
+    input.type = "hidden";
+    input.name = "tz";
+    input.value = timezone;
+    document.querySelector("#search").appendChild(input);
+
+    // links to other pages; the ones within a day's log stay fragments
+    document.querySelectorAll(".contents h2 a, .contents .message .time").forEach(element => {
+        const href = element.getAttribute("href");
+        if (!href.startsWith("#")) {
+            element.href = withTimezone(href);
+        }
+    });
+}
+
 async function getLogsDates() {
-    const response = await fetch("/dates");
+    const response = await fetch(withTimezone("/dates"));
     const dates = await response.json();
     logDates = dates;
 
@@ -135,6 +193,72 @@ async function getLogsDates() {
     document.querySelector("#collapse").classList.remove("hidden");
 }
 
+// marker and verb of events, as in MessageKind
+const eventText = {
+    action: ["*", ""],
+    join: ["-->", "has joined"],
+    part: ["<--", "has left"],
+    quit: ["<--", "has quit"],
+    nick: ["--", "is now known as"],
+    topic: ["--", "has changed the topic to:"],
+};
+
+function renderMessage(message) {
+    const element = document.createElement("div");
+    const event = eventText[message.kind];
+
+    element.className = event ? `message event ${message.kind}` : "message";
+    if (message.is_bot) {
+        element.classList.add("bot");
+    }
+    element.innerHTML = `<a id="${message.id}" class="time" href="#${message.id}">[${message.time_of_day}]</a>`;
+
+    if (event) {
+        element.insertAdjacentHTML("beforeend",
+            `<span class="marker">${event[0]}</span> <span class="from" data-nick="${escapeHTML(message.author)}">${escapeHTML(message.author)}</span> ${event[1]} <span class="text"></span>`);
+    } else {
+        element.insertAdjacentHTML("beforeend",
+            ` <span class="from">&lt;${escapeHTML(message.author)}&gt;</span> <span class="text"></span>`);
+    }
+
+    element.querySelector(".text").textContent = message.body;
+    colorize(element);
+    return element;
+}
+
+// appends messages of today's log as they arrive
+function liveView() {
+    const date = contents.dataset.liveDate;
+    if (!date) {
+        return;
+    }
+
+    const after = contents.dataset.liveAfter;
+    const url = after ? `/logs/stream?after=${after}` : "/logs/stream";
+    const source = new EventSource(withTimezone(url));
+
+    source.addEventListener("message", (event) => {
+        const message = JSON.parse(event.data);
+        if (!message.time.startsWith(date)) {
+            return;
+        }
+
+        const atBottom = contents.scrollTop + contents.clientHeight >= contents.scrollHeight - 16;
+        contents.querySelector(".error")?.remove();
+        contents.appendChild(renderMessage(message));
+
+        if (atBottom) {
+            contents.scrollTop = contents.scrollHeight;
+        }
+    });
+
+    // sent when messages were skipped, reloading shows them
+    source.addEventListener("reload", () => {
+        source.close();
+        window.location.reload();
+    });
+}
+
 function searchView(path) {
     const searchInput = document.querySelector("#input-search");
     const query = new URLSearchParams(window.location.search);
@@ -172,7 +296,7 @@ async function defaultView(path) {
             showErrorModal("Already at the begining");
         } else {
             uiState.currentDateIndex -= 1;
-            window.location.href = logDates[uiState.currentDateIndex];
+            window.location.href = withTimezone(logDates[uiState.currentDateIndex]);
         }
     });
 
@@ -202,12 +326,12 @@ async function defaultView(path) {
             showErrorModal("Already at the end");
         } else {
             uiState.currentDateIndex += 1;
-            window.location.href = logDates[uiState.currentDateIndex];
+            window.location.href = withTimezone(logDates[uiState.currentDateIndex]);
         }
     });
 
     dateInput.addEventListener("change", (event) => {
-        window.location.href = event.target.value;
+        window.location.href = withTimezone(event.target.value);
     });
 
     await getLogsDates();
@@ -223,13 +347,15 @@ async function defaultView(path) {
 
 addEventListener("DOMContentLoaded", async () => {
     colorize();
+    preserveTimezone();
     const path = window.location.pathname.substring(1);
 
-    if (path != "search") {
+    if (path != "search" && path != "stats") {
         if (window.location.hash.length <= 0) {
             const objDiv = document.querySelector(".contents");
             objDiv.scrollTop = objDiv.scrollHeight;
         }
+        liveView();
         await defaultView(path.replace("/", ""));
     } else {
         searchView();